@@ -0,0 +1,49 @@
+//! Compares draining blocks one sample at a time with `next` against a chunk
+//! at a time with `ChunkedIterator::fill`.
+
+use std::time::{Duration, Instant};
+
+use rustradio::blocks::ChunkedIterator;
+use rustradio::ext::RadioIteratorExt;
+
+static NUM_SAMPLES: usize = 1 << 18;
+static CHUNK_SIZE: usize = 512;
+static ITERATIONS: u32 = 20;
+
+fn taps() -> Vec<f32> {
+    (0..64).map(|i| i as f32 / 64.0).collect()
+}
+
+fn fir_per_sample() -> f32 {
+    let taps = taps();
+    (0..NUM_SAMPLES).map(|x| x as f32).fir(&taps).sum()
+}
+
+fn fir_chunked() -> f32 {
+    let taps = taps();
+    let mut filtered = (0..NUM_SAMPLES).map(|x| x as f32).fir(&taps);
+    let mut chunk = vec![0f32; CHUNK_SIZE];
+    let mut sum = 0f32;
+    loop {
+        let n = filtered.fill(&mut chunk);
+        sum = chunk[..n].iter().fold(sum, |sum, &x| sum + x);
+        if n < CHUNK_SIZE {
+            return sum;
+        }
+    }
+}
+
+fn bench(name: &str, f: fn() -> f32) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        std::hint::black_box(f());
+    }
+    let per_iter: Duration = start.elapsed() / ITERATIONS;
+    let rate = NUM_SAMPLES as f64 / per_iter.as_secs_f64() / 1e6;
+    println!("{:<24} {:>10.2?}/iter {:>8.1} MS/s", name, per_iter, rate);
+}
+
+fn main() {
+    bench("fir_per_sample", fir_per_sample);
+    bench("fir_chunked", fir_chunked);
+}
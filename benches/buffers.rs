@@ -0,0 +1,153 @@
+//! Compares the lock-free `push_buffer` against the mutex-based buffer it
+//! replaced, by streaming samples from one thread to another. The lock-free
+//! buffer is read both one at a time with the iterator, and in bulk with
+//! `pop_slice`.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use rustradio::buffers::push_buffer;
+
+static NUM_SAMPLES: usize = 1 << 18;
+static CHUNK_SIZE: usize = 512;
+static ITERATIONS: u32 = 50;
+
+/// The mutex-based `push_buffer`, as it was before the lock-free ring, as a
+/// baseline. Only what the benchmark uses is kept.
+mod mutex {
+    use super::*;
+
+    struct State<T> {
+        queue: VecDeque<T>,
+        capacity: usize,
+        closed: bool,
+    }
+
+    struct Buff<T> {
+        state: Mutex<State<T>>,
+        not_empty: Condvar,
+        not_full: Condvar,
+    }
+
+    pub struct Consumer<T> {
+        inner: Arc<Buff<T>>,
+    }
+
+    impl<T> Iterator for Consumer<T> {
+        type Item = T;
+        fn next(&mut self) -> Option<T> {
+            let mut state = self.inner.state.lock().unwrap();
+            loop {
+                match state.queue.pop_front() {
+                    Some(elt) => {
+                        self.inner.not_full.notify_one();
+                        return Some(elt);
+                    },
+                    None if state.closed => return None,
+                    None => state = self.inner.not_empty.wait(state).unwrap(),
+                }
+            }
+        }
+    }
+
+    pub struct Producer<T> {
+        inner: Arc<Buff<T>>,
+    }
+
+    impl<T: Clone> Producer<T> {
+        pub fn push_slice_blocking(&self, elts: &[T]) {
+            let mut state = self.inner.state.lock().unwrap();
+            for elt in elts {
+                while state.queue.len() == state.capacity {
+                    self.inner.not_empty.notify_one();
+                    state = self.inner.not_full.wait(state).unwrap();
+                }
+                state.queue.push_back(elt.clone());
+            }
+            self.inner.not_empty.notify_one();
+        }
+    }
+
+    impl<T> Drop for Producer<T> {
+        fn drop(&mut self) {
+            self.inner.state.lock().unwrap().closed = true;
+            self.inner.not_empty.notify_all();
+        }
+    }
+
+    pub fn push_buffer<T>(capacity: usize) -> (Producer<T>, Consumer<T>) {
+        let inner = Arc::new(Buff {
+            state: Mutex::new(State { queue: VecDeque::with_capacity(capacity), capacity, closed: false }),
+            not_empty: Condvar::new(),
+            not_full: Condvar::new(),
+        });
+        (Producer { inner: inner.clone() }, Consumer { inner })
+    }
+}
+
+fn mutex_push_buffer() -> f32 {
+    let (producer, consumer) = mutex::push_buffer(rustradio::DEFAULT_BUFFER_SIZE);
+    let guard = thread::spawn(move|| {
+        let chunk = vec![1f32; CHUNK_SIZE];
+        for _ in 0..NUM_SAMPLES / CHUNK_SIZE {
+            producer.push_slice_blocking(&chunk);
+        }
+    });
+    let sum = consumer.fold(0f32, |sum, x| sum + x);
+    guard.join().unwrap();
+    sum
+}
+
+fn push_buffer_iter() -> f32 {
+    let (producer, consumer) = push_buffer(rustradio::DEFAULT_BUFFER_SIZE);
+    let guard = thread::spawn(move|| {
+        let chunk = vec![1f32; CHUNK_SIZE];
+        for _ in 0..NUM_SAMPLES / CHUNK_SIZE {
+            producer.push_slice_blocking(&chunk).unwrap();
+        }
+    });
+    let sum = consumer.fold(0f32, |sum, x| sum + x);
+    guard.join().unwrap();
+    sum
+}
+
+fn push_buffer_pop_slice() -> f32 {
+    let (producer, mut consumer) = push_buffer(rustradio::DEFAULT_BUFFER_SIZE);
+    let guard = thread::spawn(move|| {
+        let chunk = vec![1f32; CHUNK_SIZE];
+        for _ in 0..NUM_SAMPLES / CHUNK_SIZE {
+            producer.push_slice_blocking(&chunk).unwrap();
+        }
+    });
+    let mut out = vec![0f32; CHUNK_SIZE];
+    let mut sum = 0f32;
+    let mut received = 0;
+    while received < NUM_SAMPLES {
+        let n = consumer.pop_slice(&mut out);
+        if n == 0 {
+            thread::yield_now();
+        }
+        sum = out.iter().take(n).fold(sum, |sum, &x| sum + x);
+        received += n;
+    }
+    guard.join().unwrap();
+    sum
+}
+
+fn bench(name: &str, f: fn() -> f32) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        std::hint::black_box(f());
+    }
+    let per_iter: Duration = start.elapsed() / ITERATIONS;
+    let rate = NUM_SAMPLES as f64 / per_iter.as_secs_f64() / 1e6;
+    println!("{:<24} {:>10.2?}/iter {:>8.1} MS/s", name, per_iter, rate);
+}
+
+fn main() {
+    bench("mutex_push_buffer", mutex_push_buffer);
+    bench("push_buffer_iter", push_buffer_iter);
+    bench("push_buffer_pop_slice", push_buffer_pop_slice);
+}
@@ -0,0 +1,26 @@
+//! Runs a flowgraph file, e.g. `cargo run --example flowgraph -- receiver.toml`
+
+use std::env;
+use std::path::Path;
+use std::process;
+
+use rustradio::flowgraph::{FlowgraphSpec, Registry};
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("usage: flowgraph <file.toml|file.json>");
+            process::exit(2);
+        },
+    };
+
+    let registry = Registry::new();
+    let result = FlowgraphSpec::load(Path::new(&path))
+        .and_then(|spec| spec.check(&registry))
+        .and_then(|graph| graph.run());
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
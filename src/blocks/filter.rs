@@ -1,54 +1,112 @@
 //! These blocks are for digital filtering.
 
-use std::num::FloatMath;
-use std::collections::RingBuf;
-use std::iter::AdditiveIterator;
 use std::f32;
-use num::Zero;
 
-use super::RadioBlock;
-use IteratorExtras::{IteratorExtra};
+use num::rational::Ratio;
+use num::One;
+
+use super::{RadioBlock, BlockRate, ChunkedIterator, Describe};
+use crate::control::{checked_control, control, Control, Updates};
+use crate::error::{Error, Result};
+use crate::sample::{Sample, MulAcc};
 
 /// Applies an FIR filter.
 ///
 /// Parameter is a slice containing the filter taps. The first tap
 /// multiplies the most recent sample, and the last tap multiplies
 /// the earliest sample in the history. This doesn't matter for symmetric
-/// filters.
+/// filters. Samples of type `A` can be filtered with taps of type `B` as long as
+/// `A: MulAcc<B>`, which includes complex samples with real taps.
 pub struct FilterFIR<'b, B: 'b> {
     pub taps: &'b [B],
 }
-pub struct FilterFIRiter<A, B, C, I> {
+pub struct FilterFIRiter<A, B, I> {
     filter: Vec<B>,
-    buff: Vec<C>, //needs to be one larger than filter, with a 0 at the end;
+    history: Vec<A>, // the most recent samples, oldest first
     iterator: I,
+    updates: Option<Updates<Vec<B>>>,
 }
 
-//TODO just added Copy to all types to avoid compiler errors. We should find a way
-//     to take them out again
-impl<A,B,C,I> Iterator<C> for FilterFIRiter<A,B,C,I>
-where A: Mul<B,C> + Copy, B: Copy, C: Mul<C,C> + Zero + Copy, I: Iterator<A> {
-    fn next(&mut self) -> Option<C> {
-        self.iterator.next().map(|x| {
-            for (i, m) in self.filter.iter().map(|a| x * *a).enumerate() {
-                self.buff[i] = m + self.buff[i + 1];
-            }
-            self.buff[0]
-        })
+impl<A, B, I> Iterator for FilterFIRiter<A, B, I>
+where A: MulAcc<B>, B: Sample, I: Iterator<Item = A> {
+    type Item = A::Output;
+    fn next(&mut self) -> Option<A::Output> {
+        let mut out = [Sample::zero()];
+        match self.fill(&mut out) {
+            0 => None,
+            _ => Some(out[0]),
+        }
     }
 }
 
-impl<'b, A, B, C, I> RadioBlock<A, C, I, FilterFIRiter<A,B,C,I>> for FilterFIR<'b, B>
-where A: Mul<B,C> + Copy, B: Clone + Copy, C: Mul<C,C> + Zero + Copy + Clone, I: Iterator<A>{
-    fn process(&self, input: I) -> FilterFIRiter<A,B,C,I> {
+impl<A, B, I> ChunkedIterator for FilterFIRiter<A, B, I>
+where A: MulAcc<B>, B: Sample, I: Iterator<Item = A> {
+    fn fill(&mut self, output: &mut [A::Output]) -> usize {
+        if let Some(taps) = self.updates.as_ref().and_then(|u| u.take()) {
+            self.filter = taps;
+        }
+        let kept = self.history.len();
+        self.history.extend(self.iterator.by_ref().take(output.len()));
+        let count = self.history.len() - kept;
+
+        // Correlate each new sample and the ones before it against the taps
+        let len = self.filter.len();
+        for (i, slot) in output[..count].iter_mut().enumerate() {
+            let end = kept + i + 1;
+            let window = &self.history[end.saturating_sub(len)..end];
+            *slot = window.iter().rev().zip(self.filter.iter())
+                .fold(Sample::zero(), |sum, (&a, &b)| a.mul_acc(b, sum));
+        }
+
+        // Only keep the samples that later outputs still need
+        let excess = self.history.len().saturating_sub(len.saturating_sub(1));
+        self.history.drain(..excess);
+        count
+    }
+}
+
+impl<'b, A, B, I> RadioBlock<I> for FilterFIR<'b, B>
+where A: MulAcc<B>, B: Sample, I: Iterator<Item = A> {
+    type Input = A;
+    type Output = A::Output;
+    type Iter = FilterFIRiter<A,B,I>;
+    const INPUTS: usize = 1;
+    const OUTPUTS: usize = 1;
+
+    fn process(&self, input: I) -> FilterFIRiter<A,B,I> {
         FilterFIRiter {
             filter: self.taps.to_vec(),
-            buff: Vec::from_elem(self.taps.len() + 1, Zero::zero()),
-            iterator: input
+            history: Vec::with_capacity(self.taps.len()),
+            iterator: input,
+            updates: None,
         }
     }
 }
 
+impl<'b, B: Sample> FilterFIR<'b, B> {
+    /// Like `process`, but also returns a handle for swapping the taps
+    ///
+    /// If the new taps are longer than the old ones, the first few outputs
+    /// after the swap treat samples from before the old filter's history as zeros.
+    pub fn process_controlled<A, I>(&self, input: I) -> (FilterFIRiter<A, B, I>, Control<Vec<B>>)
+    where A: MulAcc<B>, I: Iterator<Item = A> {
+        let (control, updates) = control();
+        let mut iter = self.process(input);
+        iter.updates = Some(updates);
+        (iter, control)
+    }
+}
+
+impl<'b, B> BlockRate for FilterFIR<'b, B> {
+    fn rate(&self) -> Result<Ratio<usize>> { Ok(One::one()) }
+}
+impl<'b, B> Describe for FilterFIR<'b, B> {
+    fn kind(&self) -> String { "FilterFIR".to_string() }
+    fn params(&self) -> Vec<(String, String)> {
+        vec![("taps".to_string(), self.taps.len().to_string())]
+    }
+}
+
 /// Polyphase Rational Resampler
 ///
 /// This block resamples the incoming samples at a rational factor. It
@@ -56,97 +114,199 @@ where A: Mul<B,C> + Copy, B: Clone + Copy, C: Mul<C,C> + Zero + Copy + Clone, I:
 ///
 /// The taps are in the same order as the `FilterFIR`, meaning the first tap (at
 /// index 0) multiplies the most recent sample
+///
+/// `up` and `down` have to be at least 1, or `process` panics.
+/// `RationalResampler::new` and `validate` report them as an error instead.
 pub struct RationalResampler<'b, B: 'b>{
-    pub up: uint,
-    pub down: uint,
+    pub up: usize,
+    pub down: usize,
     pub taps: &'b [B],
 }
 
-pub struct RationalResamplerIter<A, B, I: Iterator<A>> {
-    up: uint,
-    down: uint,
-    filter_length: uint,
+pub struct RationalResamplerIter<A, B, I> {
+    up: usize,
+    down: usize,
+    filter_length: usize,
     filters: Vec<Vec<B>>,
-    filter_idx: uint,
-    sample_history: RingBuf<A>,
+    filter_idx: usize,
+    history: Vec<A>, // the most recent samples, oldest first
     iterator: I,
+    updates: Option<Updates<ResamplerSettings<B>>>,
 }
 
-impl<A, B, C, I> Iterator<C> for RationalResamplerIter<A, B, I>
-where A: Zero + Clone, B: Mul<A,C> + Clone, C: Zero, I: Iterator<A> {
-    fn next(&mut self) -> Option<C> {
-        if self.sample_history.is_empty() {
-            // start off with all zeros and the first element
-            self.sample_history.reserve_exact(self.filter_length);
-            for _ in range(0u, self.filter_length - 1) {
-                self.sample_history.push_front(Zero::zero());
-            }
-            match self.iterator.next() {
-                None => return None,
-                Some(x) => self.sample_history.push_front(x),
-            }
+/// New settings for a running `RationalResampler`
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResamplerSettings<B> {
+    pub up: usize,
+    pub down: usize,
+    pub taps: Vec<B>,
+}
+
+impl<B> ResamplerSettings<B> {
+    /// Checks that `up` and `down` are both at least 1
+    pub fn validate(&self) -> Result<()> {
+        check_ratio(self.up, self.down)
+    }
+}
+
+fn check_ratio(up: usize, down: usize) -> Result<()> {
+    if up == 0 || down == 0 {
+        return Err(Error::invalid_parameter(
+            format!("can't resample by {}/{}, up and down must be at least 1", up, down)));
+    }
+    Ok(())
+}
+
+/// Splits the given FIR filter into `up` smaller filters
+fn polyphase_filters<B: Sample>(taps: &[B], up: usize) -> Vec<Vec<B>> {
+    (0..up).map(|i| taps.iter().skip(i).step_by(up).copied().collect()).collect()
+}
+
+impl<A, B, I> Iterator for RationalResamplerIter<A, B, I>
+where A: MulAcc<B>, B: Sample, I: Iterator<Item = A> {
+    type Item = A::Output;
+    fn next(&mut self) -> Option<A::Output> {
+        let mut out = [Sample::zero()];
+        match self.fill(&mut out) {
+            0 => None,
+            _ => Some(out[0]),
         }
+    }
+}
 
-        // Get new samples, if needed
-        while self.filter_idx >= self.up {
-            self.filter_idx -= self.up;
-            self.sample_history.pop_back();
-            match self.iterator.next() {
-                None => return None,
-                Some(x) => self.sample_history.push_front(x)
+impl<A, B, I> ChunkedIterator for RationalResamplerIter<A, B, I>
+where A: MulAcc<B>, B: Sample, I: Iterator<Item = A> {
+    fn fill(&mut self, output: &mut [A::Output]) -> usize {
+        if let Some(settings) = self.updates.as_ref().and_then(|u| u.take()) {
+            self.up = settings.up;
+            self.down = settings.down;
+            self.filters = polyphase_filters(&settings.taps, settings.up);
+            self.filter_length = self.filters[0].len();
+            // the new settings start with the next input sample
+            self.filter_idx = self.up;
+            if self.history.len() < self.filter_length {
+                let missing = self.filter_length - self.history.len();
+                self.history.splice(..0, (0..missing).map(|_| A::zero()));
             }
         }
 
-        // Save the current filter index, and then increment for next time
-        let current_filter_idx = self.filter_idx;
-        self.filter_idx += self.down;
+        let mut count = 0;
+        for slot in output.iter_mut() {
+            // Get new samples, if needed
+            while self.filter_idx >= self.up {
+                match self.iterator.next() {
+                    None => break,
+                    Some(x) => self.history.push(x),
+                }
+                self.filter_idx -= self.up;
+            }
+            if self.filter_idx >= self.up {
+                break;
+            }
+
+            // Correlate the most recent samples against the current FIR filter
+            let window = &self.history[self.history.len() - self.filter_length..];
+            *slot = self.filters[self.filter_idx].iter().zip(window.iter().rev())
+                .fold(Sample::zero(), |sum, (&b, &a)| a.mul_acc(b, sum));
+            self.filter_idx += self.down;
+            count += 1;
+        }
 
-        // Correlate the most recent samples against the current FIR filter
-        Some(self.filters[current_filter_idx].iter().zip(self.sample_history.iter())
-            .fold(Zero::zero(), |sum: C, (b, a)| sum + b.clone() * a.clone()))
+        // Only keep the samples that later outputs still need
+        let excess = self.history.len() - self.filter_length;
+        self.history.drain(..excess);
+        count
     }
 }
 
-impl<'b, A, B, C, I> RadioBlock<A, C, I, RationalResamplerIter<A, B, I>> for RationalResampler<'b, B>
-where A: Zero + Clone, B: Mul<A,C> + Clone, C: Zero, I: Iterator<A> {
-    fn process(&self, input: I) -> RationalResamplerIter<A, B, I> {
+impl<'b, A, B, I> RadioBlock<I> for RationalResampler<'b, B>
+where A: MulAcc<B>, B: Sample, I: Iterator<Item = A> {
+    type Input = A;
+    type Output = A::Output;
+    type Iter = RationalResamplerIter<A, B, I>;
+    const INPUTS: usize = 1;
+    const OUTPUTS: usize = 1;
 
-        // Split the given FIR filter into smaller filters
-        let mut filters: Vec<Vec<B>> = Vec::new();
-        for i in range(0, self.up) {
-            filters.push(self.taps.iter().map(|x| x.clone()).skip(i).stride(self.up).collect());
-        }
+    fn process(&self, input: I) -> RationalResamplerIter<A, B, I> {
+        let filters = polyphase_filters(self.taps, self.up);
+        let filter_length = filters[0].len();
 
         RationalResamplerIter {
             up: self.up,
             down: self.down,
-            filter_length: filters[0].len(),
-            filters: filters,
-            filter_idx: 0,
-            sample_history: RingBuf::new(),
+            filter_length,
+            filters,
+            // start off with all zeros, waiting on the first element
+            filter_idx: self.up,
+            history: vec![A::zero(); filter_length],
             iterator: input,
+            updates: None,
         }
     }
 }
 
+impl<'b, B> RationalResampler<'b, B> {
+    /// Resamples by `up / down`, failing with `Error::InvalidParameter` if
+    /// either is 0
+    pub fn new(up: usize, down: usize, taps: &'b [B]) -> Result<RationalResampler<'b, B>> {
+        let block = RationalResampler { up, down, taps };
+        block.validate()?;
+        Ok(block)
+    }
+
+    /// Checks that `up` and `down` are both at least 1
+    pub fn validate(&self) -> Result<()> {
+        check_ratio(self.up, self.down)
+    }
+}
+
+impl<'b, B: Sample> RationalResampler<'b, B> {
+    /// Like `process`, but also returns a handle for changing the ratio and taps
+    ///
+    /// The new settings take effect from the next input sample. The handle
+    /// rejects settings that `ResamplerSettings::validate` fails.
+    pub fn process_controlled<A, I>(&self, input: I) -> (RationalResamplerIter<A, B, I>, Control<ResamplerSettings<B>>)
+    where A: MulAcc<B>, I: Iterator<Item = A> {
+        let (control, updates) = checked_control(ResamplerSettings::validate);
+        let mut iter = self.process(input);
+        iter.updates = Some(updates);
+        (iter, control)
+    }
+}
+
+impl<'b, B> BlockRate for RationalResampler<'b, B> {
+    fn rate(&self) -> Result<Ratio<usize>> {
+        self.validate()?;
+        Ok(Ratio::new(self.up, self.down))
+    }
+}
+impl<'b, B> Describe for RationalResampler<'b, B> {
+    fn kind(&self) -> String { "RationalResampler".to_string() }
+    fn params(&self) -> Vec<(String, String)> {
+        vec![("up".to_string(), self.up.to_string()),
+             ("down".to_string(), self.down.to_string()),
+             ("taps".to_string(), self.taps.len().to_string())]
+    }
+}
+
 pub trait WindowFunction {
-    fn time_domain_taps(&self, num_taps: uint) -> Vec<f32>;
+    fn time_domain_taps(&self, num_taps: usize) -> Vec<f32>;
 }
 
-#[deriving(Copy)]
+#[derive(Clone, Copy)]
 pub struct HammingWindow;
 impl WindowFunction for HammingWindow {
-    fn time_domain_taps(&self, num_taps: uint) -> Vec<f32> {
-        let tau = f32::consts::PI_2;
-        Vec::from_fn(num_taps, |i| {
+    fn time_domain_taps(&self, num_taps: usize) -> Vec<f32> {
+        let tau = f32::consts::TAU;
+        (0..num_taps).map(|i| {
             0.54 - 0.46 * (tau * (i as f32) / ((num_taps as f32) - 1.0)).cos()
-        })
+        }).collect()
     }
 }
 
-#[deriving(Copy)]
+#[derive(Clone, Copy)]
 pub enum NumTapsSpecifier {
-    NumTaps(uint),
+    NumTaps(usize),
     TransitionWidth(f32),
 }
 
@@ -159,13 +319,22 @@ pub enum NumTapsSpecifier {
 ///            directly, or `TransitionWidth(w)`, which gives the desired
 ///            transition width (normalized, like `bandwidth`), and the
 ///            number of taps is calculated from this.
+///
+/// Fails with `Error::InvalidParameter` if `bandwidth` isn't between 0 and 0.5,
+/// if there are no taps, or if `num_taps` is a `TransitionWidth`, which isn't
+/// supported yet.
 pub fn low_pass_filter_taps<W: WindowFunction>(window_type: W,
                                                bandwidth: f32,
-                                               num_taps: NumTapsSpecifier) -> Vec<f32> {
+                                               num_taps: NumTapsSpecifier) -> Result<Vec<f32>> {
     let n_taps = match num_taps {
+        NumTapsSpecifier::NumTaps(0) => return Err(Error::invalid_parameter("a filter needs at least one tap")),
         NumTapsSpecifier::NumTaps(n) => n,
-        NumTapsSpecifier::TransitionWidth(_) => panic!("Transition Width not implemented"),
+        NumTapsSpecifier::TransitionWidth(_) =>
+            return Err(Error::invalid_parameter("transition width isn't supported, give the number of taps")),
     };
+    if !(bandwidth > 0.0 && bandwidth <= 0.5) {
+        return Err(Error::invalid_parameter(format!("bandwidth {} isn't between 0 and 0.5", bandwidth)));
+    }
 
     // start out with window function
     let mut taps = window_type.time_domain_taps(n_taps);
@@ -173,20 +342,35 @@ pub fn low_pass_filter_taps<W: WindowFunction>(window_type: W,
     // multiply by sinc
     for (idx, tap) in taps.iter_mut().enumerate() {
         // convert from vector index to time index
-        let time_idx = idx as int - (n_taps as int - 1) / 2;
+        let time_idx = idx as isize - (n_taps as isize - 1) / 2;
         *tap *= if time_idx == 0 {
                 2.0 * bandwidth
             } else {
-                (time_idx as f32 * f32::consts::PI_2 * bandwidth).sin() /
+                (time_idx as f32 * f32::consts::TAU * bandwidth).sin() /
                     (time_idx as f32 * f32::consts::PI)
             }
     }
 
     // normalize
-    let sum = taps.iter().map(|&x| x).sum();
+    let sum: f32 = taps.iter().sum();
     for tap in taps.iter_mut() {
         *tap /= sum;
     }
 
-    return taps;
+    Ok(taps)
+}
+
+/// Generates the taps for a low-pass filter, with frequencies given in Hz
+///
+/// This is the same as `low_pass_filter_taps`, but works out the normalized
+/// bandwidth (and transition width, if given) from `sample_rate`.
+pub fn low_pass_filter_taps_hz<W: WindowFunction>(window_type: W,
+                                                  cutoff: f32,
+                                                  sample_rate: f32,
+                                                  num_taps: NumTapsSpecifier) -> Result<Vec<f32>> {
+    let num_taps = match num_taps {
+        NumTapsSpecifier::TransitionWidth(w) => NumTapsSpecifier::TransitionWidth(w / sample_rate),
+        n => n,
+    };
+    low_pass_filter_taps(window_type, cutoff / sample_rate, num_taps)
 }
@@ -0,0 +1,121 @@
+//! These blocks are made out of other blocks.
+//!
+//! A hierarchical block is just a struct that implements `RadioBlock` by
+//! calling `process` on the blocks inside it, with whatever buffers it needs
+//! between them. Its `Iter` is the iterator of the last block inside it, which
+//! is usually a long type, so it's worth a type alias. `Then` covers the common
+//! case of two blocks in a row, and `FmDemod` is an example of a block with its
+//! own parameters.
+
+use num::complex::Complex;
+use num::rational::Ratio;
+
+use super::{RadioBlock, BlockRate, Describe};
+use super::filter::{FilterFIR, FilterFIRiter, RationalResampler, RationalResamplerIter};
+use super::modem::{PhaseDiffs, PhaseDiffsIter};
+use crate::buffers::{buffer_fixed, FixedBuffer1};
+use crate::error::Result;
+
+/// Runs one block into another, with a fixed buffer in between
+///
+/// `Then`s can be nested to make longer chains, and the result can be used
+/// anywhere a single block can.
+///
+/// # Example
+/// ```
+/// use rustradio::blocks::RadioBlock;
+/// use rustradio::blocks::hier::Then;
+/// use rustradio::blocks::filter::FilterFIR;
+/// use rustradio::blocks::stream::Stride;
+///
+/// let taps = vec![1i64, 1];
+/// let block = Then { first: FilterFIR{ taps: &taps }, second: Stride{ stride: 2 } };
+/// let collected: Vec<i64> = block.process(0i64..).take(3).collect();
+/// assert_eq!(collected, vec![0, 3, 7]);
+/// ```
+#[derive(Clone, Copy)]
+pub struct Then<R1, R2> {
+    pub first: R1,
+    pub second: R2,
+}
+impl<I, R1, R2> RadioBlock<I> for Then<R1, R2>
+where R1: RadioBlock<I>, R2: RadioBlock<FixedBuffer1<R1::Output, R1::Iter>> {
+    type Input = R1::Input;
+    type Output = R2::Output;
+    type Iter = R2::Iter;
+    const INPUTS: usize = R1::INPUTS;
+    const OUTPUTS: usize = R2::OUTPUTS;
+
+    fn process(&self, input: I) -> R2::Iter {
+        let first = self.first.process(input);
+        self.second.process(buffer_fixed(first, crate::DEFAULT_BUFFER_SIZE))
+    }
+}
+impl<R1: BlockRate, R2: BlockRate> BlockRate for Then<R1, R2> {
+    fn rate(&self) -> Result<Ratio<usize>> { Ok(self.first.rate()? * self.second.rate()?) }
+}
+impl<R1: Describe, R2: Describe> Describe for Then<R1, R2> {
+    fn kind(&self) -> String { format!("{} -> {}", self.first.kind(), self.second.kind()) }
+    fn params(&self) -> Vec<(String, String)> {
+        let mut params = self.first.params();
+        params.extend(self.second.params());
+        params
+    }
+}
+
+type Buffered<I> = FixedBuffer1<Complex<f32>, I>;
+pub type FmDemodIter<I> =
+    PhaseDiffsIter<Buffered<RationalResamplerIter<Complex<f32>, f32, Buffered<FilterFIRiter<Complex<f32>, f32, I>>>>, f32>;
+
+/// Demodulates FM from complex baseband samples
+///
+/// The samples are channel filtered with `channel_taps`, resampled by
+/// `up / down` with `resampler_taps`, and then the phase differences between
+/// them are the demodulated signal, in radians/sample. `process` panics if `up`
+/// or `down` is 0, which `validate` reports as an error instead.
+#[derive(Clone, Copy)]
+pub struct FmDemod<'b> {
+    pub channel_taps: &'b [f32],
+    pub up: usize,
+    pub down: usize,
+    pub resampler_taps: &'b [f32],
+}
+impl<'b, I> RadioBlock<I> for FmDemod<'b>
+where I: Iterator<Item = Complex<f32>> {
+    type Input = Complex<f32>;
+    type Output = f32;
+    type Iter = FmDemodIter<I>;
+    const INPUTS: usize = 1;
+    const OUTPUTS: usize = 1;
+
+    fn process(&self, input: I) -> FmDemodIter<I> {
+        let b_filter = FilterFIR{ taps: self.channel_taps };
+        let b_resampler = RationalResampler{ up: self.up, down: self.down, taps: self.resampler_taps };
+
+        let filtered = b_filter.process(input);
+        let resampled = b_resampler.process(buffer_fixed(filtered, crate::DEFAULT_BUFFER_SIZE));
+        PhaseDiffs.process(buffer_fixed(resampled, crate::DEFAULT_BUFFER_SIZE))
+    }
+}
+impl<'b> FmDemod<'b> {
+    /// Checks the resampling ratio, as `RationalResampler::validate` does
+    pub fn validate(&self) -> Result<()> {
+        RationalResampler::new(self.up, self.down, self.resampler_taps)?;
+        Ok(())
+    }
+}
+impl<'b> BlockRate for FmDemod<'b> {
+    fn rate(&self) -> Result<Ratio<usize>> {
+        self.validate()?;
+        Ok(Ratio::new(self.up, self.down))
+    }
+}
+impl<'b> Describe for FmDemod<'b> {
+    fn kind(&self) -> String { "FmDemod".to_string() }
+    fn params(&self) -> Vec<(String, String)> {
+        vec![("channel_taps".to_string(), self.channel_taps.len().to_string()),
+             ("up".to_string(), self.up.to_string()),
+             ("down".to_string(), self.down.to_string()),
+             ("resampler_taps".to_string(), self.resampler_taps.len().to_string())]
+    }
+}
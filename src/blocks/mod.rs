@@ -1,14 +1,71 @@
 pub mod stream;
 pub mod modem;
 pub mod filter;
+pub mod hier;
+
+use num::rational::Ratio;
+
+use crate::error::Result;
 
 /// This is the trait that all processing blocks must follow. The block will transform
-/// items of type `A` into items of type `B`. Both of these types should be tuples if more than
-/// one input or output stream is needed.
+/// an iterator `I` over items of type `Input` into an iterator over items of type `Output`.
+/// Both of these types are tuples if more than one input or output stream is needed, and
+/// `INPUTS` and `OUTPUTS` give the number of streams. `join` and `split` turn up to
+/// 8 separate streams into such a tuple stream, and back again.
 ///
-pub trait RadioBlock<A, B, I: Iterator<A>, O: Iterator<B>> {
+pub trait RadioBlock<I> {
+    type Input;
+    type Output;
+    type Iter: ChunkedIterator<Item = Self::Output>;
+
+    /// The number of input streams
+    const INPUTS: usize;
+    /// The number of output streams
+    const OUTPUTS: usize;
 
     /// This function takes the input iterator and transforms it to
     /// another iterator using any parameters found in the block object.
-    fn process(&self, input: I) -> O;
+    fn process(&self, input: I) -> Self::Iter;
+}
+
+/// The iterators produced by blocks, which can also be drained a slice at a time.
+///
+/// Buffers that know they're reading from a block, such as the scheduler's
+/// workers, call `fill` instead of `next`. The default implementation just
+/// calls `next` for each element, so simple blocks only need an empty `impl`.
+/// Blocks with heavier inner loops override it to work on whole slices, which
+/// lets the compiler vectorise them.
+pub trait ChunkedIterator: Iterator {
+    /// Writes output items to the front of `output`, returning how many were
+    /// written. Fewer than `output.len()` items are written only if the stream
+    /// has ended.
+    fn fill(&mut self, output: &mut [Self::Item]) -> usize {
+        let mut count = 0;
+        for slot in output.iter_mut() {
+            match self.next() {
+                Some(x) => *slot = x,
+                None => break,
+            }
+            count += 1;
+        }
+        count
+    }
+}
+
+/// Declares how many output items a block produces for each input item it consumes.
+///
+/// For blocks with several inputs, an input item is one item from each input
+/// stream. This is used by `rate::RateGraph` to check a flowgraph before
+/// running it. Blocks whose parameters don't make a rate, such as a `Stride`
+/// of 0, fail with `Error::InvalidParameter`.
+pub trait BlockRate {
+    fn rate(&self) -> Result<Ratio<usize>>;
+}
+
+/// Describes a block's type and parameters, for `dot::DotGraph`.
+pub trait Describe {
+    /// The type of block, e.g. `"FilterFIR"`
+    fn kind(&self) -> String;
+    /// The block's parameters, as names and values
+    fn params(&self) -> Vec<(String, String)> { Vec::new() }
 }
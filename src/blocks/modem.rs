@@ -1,52 +1,236 @@
 //! These blocks are for modulation and demodulation (both digital and analog).
 
-use std::num::FloatMath;
-use std::iter::Chain;
-use std::option::IntoIter;
 use num::complex::Complex;
-use num::{Num, Zero, One};
+use num::{Zero, One};
 
-use super::RadioBlock;
-use IteratorExtras::{IteratorExtra, Scan1};
+use num::rational::Ratio;
+
+use super::{RadioBlock, BlockRate, ChunkedIterator, Describe};
+use crate::error::{Error, Result};
+use crate::message::MessagePort;
+use crate::sample::FloatSample;
 
 /// Performs analog frequency modulation.
 ///
 /// There are no parameters. Input stream is in radians/sample. One must pre-amplify for
 /// different sensitivities.
-#[deriving(Copy)]
+#[derive(Clone, Copy)]
 pub struct FreqMod;
 pub struct FreqModIter<I, T> {
     iterator: I,
-    phase: T,
+    phase: Option<T>,
 }
-impl<T, I> Iterator<Complex<T>> for FreqModIter<I, T>
-where T: Num + FloatMath + One + Zero, I: Iterator<T> {
+impl<T, I> Iterator for FreqModIter<I, T>
+where T: FloatSample, I: Iterator<Item = T> {
+    type Item = Complex<T>;
     fn next(&mut self) -> Option<Complex<T>> {
-        self.iterator.next().map(|p| {
-            self.phase = self.phase + p;
-            Complex::from_polar(&One::one(), &self.phase)
-        })
+        let phase = match self.phase {
+            // the output starts at zero phase, before the first input
+            None => Zero::zero(),
+            Some(phase) => phase + self.iterator.next()?,
+        };
+        self.phase = Some(phase);
+        Some(Complex::from_polar(One::one(), phase))
     }
 }
-impl<'r, T, I> RadioBlock<T, Complex<T>, I, Chain<IntoIter<Complex<T>>, FreqModIter<I, T>>> for FreqMod
-where T: Num + FloatMath + One + Zero, I: Iterator<T> {
-    fn process(&self, input: I) -> Chain<IntoIter<Complex<T>>, FreqModIter<I, T>> {
-        Some(Complex::from_polar(&One::one(), &Zero::zero())).into_iter().chain(
-            FreqModIter{ iterator: input, phase: Zero::zero() }
-        )
+impl<T, I> ChunkedIterator for FreqModIter<I, T>
+where T: FloatSample, I: Iterator<Item = T> {
+    fn fill(&mut self, output: &mut [Complex<T>]) -> usize {
+        let mut count = 0;
+        let mut phase = match self.phase {
+            Some(phase) => phase,
+            None => match output.first_mut() {
+                Some(slot) => {
+                    *slot = Complex::from_polar(One::one(), Zero::zero());
+                    count = 1;
+                    Zero::zero()
+                },
+                None => return 0,
+            },
+        };
+        let start = count;
+
+        // Accumulate the phases first, so the conversion to complex can be vectorised
+        for (slot, p) in output[start..].iter_mut().zip(self.iterator.by_ref()) {
+            phase = phase + p;
+            slot.re = phase;
+            count += 1;
+        }
+        for slot in output[start..count].iter_mut() {
+            *slot = Complex::from_polar(One::one(), slot.re);
+        }
+        self.phase = Some(phase);
+        count
+    }
+}
+impl<T, I> RadioBlock<I> for FreqMod
+where T: FloatSample, I: Iterator<Item = T> {
+    type Input = T;
+    type Output = Complex<T>;
+    type Iter = FreqModIter<I, T>;
+    const INPUTS: usize = 1;
+    const OUTPUTS: usize = 1;
+
+    fn process(&self, input: I) -> FreqModIter<I, T> {
+        FreqModIter{ iterator: input, phase: None }
     }
 }
 
+impl BlockRate for FreqMod {
+    fn rate(&self) -> Result<Ratio<usize>> { Ok(One::one()) }
+}
+impl Describe for FreqMod {
+    fn kind(&self) -> String { "FreqMod".to_string() }
+}
+
 /// Calculates the phase difference between successive samples
-#[deriving(Copy)]
+#[derive(Clone, Copy)]
 pub struct PhaseDiffs;
-impl<'r, T, I> RadioBlock<Complex<T>, T, I, Scan1<'r, Complex<T>, T, I>> for PhaseDiffs
-where T: FloatMath + Clone + Num, I: Iterator<Complex<T>> {
-    fn process(&self, input: I) -> Scan1<'r, Complex<T>, T, I> {
-        input.scan1(|last, current| {
-            let phase_diff = (current * last.conj()).arg();
-            *last = current;
-            Some(phase_diff)
-        })
+pub struct PhaseDiffsIter<I, T> {
+    iterator: I,
+    last: Option<Complex<T>>,
+}
+impl<T, I> Iterator for PhaseDiffsIter<I, T>
+where T: FloatSample, I: Iterator<Item = Complex<T>> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        // the first sample only serves as the reference for the second
+        let last = match self.last.take() {
+            Some(last) => last,
+            None => self.iterator.next()?,
+        };
+        let current = self.iterator.next()?;
+        self.last = Some(current);
+        Some((current * last.conj()).arg())
+    }
+}
+impl<T, I> ChunkedIterator for PhaseDiffsIter<I, T>
+where T: FloatSample, I: Iterator<Item = Complex<T>> {}
+impl<T, I> RadioBlock<I> for PhaseDiffs
+where T: FloatSample, I: Iterator<Item = Complex<T>> {
+    type Input = Complex<T>;
+    type Output = T;
+    type Iter = PhaseDiffsIter<I, T>;
+    const INPUTS: usize = 1;
+    const OUTPUTS: usize = 1;
+
+    fn process(&self, input: I) -> PhaseDiffsIter<I, T> {
+        PhaseDiffsIter{ iterator: input, last: None }
+    }
+}
+impl BlockRate for PhaseDiffs {
+    fn rate(&self) -> Result<Ratio<usize>> { Ok(One::one()) }
+}
+impl Describe for PhaseDiffs {
+    fn kind(&self) -> String { "PhaseDiffs".to_string() }
+}
+
+/// Finds packets in a stream of bits, and publishes their payloads as messages.
+///
+/// The input is a stream of hard decisions, one bit per `u8` (any non-zero value
+/// is a one). After each occurrence of `access_code`, the next `payload_len`
+/// bytes, sent most significant bit first, are published to `packets`. The bits
+/// themselves are passed through unchanged, so the block can sit in the middle
+/// of a flowgraph or at the end of one.
+///
+/// `access_code` can't be empty, or `process` panics. `Deframer::new` and
+/// `validate` report it as an error instead.
+#[derive(Clone)]
+pub struct Deframer<'b> {
+    pub access_code: &'b [u8],
+    pub payload_len: usize,
+    pub packets: MessagePort<Vec<u8>>,
+}
+pub struct DeframerIter<I> {
+    iterator: I,
+    access_code: Vec<bool>,
+    payload_len: usize,
+    packets: MessagePort<Vec<u8>>,
+    recent: Vec<bool>, // the last `access_code.len()` bits, oldest first
+    payload: Option<(Vec<u8>, usize)>, // the bytes so far, and the number of bits
+}
+impl<I: Iterator<Item = u8>> Iterator for DeframerIter<I> {
+    type Item = u8;
+    fn next(&mut self) -> Option<u8> {
+        let x = self.iterator.next()?;
+        let bit = x != 0;
+
+        if let Some((mut bytes, bits)) = self.payload.take() {
+            if bits % 8 == 0 {
+                bytes.push(0);
+            }
+            if bit {
+                *bytes.last_mut().unwrap() |= 0x80 >> (bits % 8);
+            }
+            if bits + 1 == self.payload_len * 8 {
+                self.packets.publish(bytes);
+            } else {
+                self.payload = Some((bytes, bits + 1));
+            }
+            return Some(x);
+        }
+
+        if self.recent.len() == self.access_code.len() {
+            self.recent.remove(0);
+        }
+        self.recent.push(bit);
+        if self.recent == self.access_code {
+            self.recent.clear();
+            if self.payload_len == 0 {
+                self.packets.publish(Vec::new());
+            } else {
+                self.payload = Some((Vec::with_capacity(self.payload_len), 0));
+            }
+        }
+        Some(x)
+    }
+}
+impl<I: Iterator<Item = u8>> ChunkedIterator for DeframerIter<I> {}
+impl<'b, I> RadioBlock<I> for Deframer<'b>
+where I: Iterator<Item = u8> {
+    type Input = u8;
+    type Output = u8;
+    type Iter = DeframerIter<I>;
+    const INPUTS: usize = 1;
+    const OUTPUTS: usize = 1;
+
+    fn process(&self, input: I) -> DeframerIter<I> {
+        assert!(!self.access_code.is_empty(), "the access code must have at least one bit");
+        DeframerIter {
+            iterator: input,
+            access_code: self.access_code.iter().map(|&b| b != 0).collect(),
+            payload_len: self.payload_len,
+            packets: self.packets.clone(),
+            recent: Vec::with_capacity(self.access_code.len()),
+            payload: None,
+        }
+    }
+}
+impl<'b> Deframer<'b> {
+    /// Finds packets after `access_code`, failing with
+    /// `Error::InvalidParameter` if it's empty
+    pub fn new(access_code: &'b [u8], payload_len: usize, packets: MessagePort<Vec<u8>>) -> Result<Deframer<'b>> {
+        let block = Deframer { access_code, payload_len, packets };
+        block.validate()?;
+        Ok(block)
+    }
+
+    /// Checks that the access code has at least one bit
+    pub fn validate(&self) -> Result<()> {
+        if self.access_code.is_empty() {
+            return Err(Error::invalid_parameter("the access code must have at least one bit"));
+        }
+        Ok(())
+    }
+}
+impl<'b> BlockRate for Deframer<'b> {
+    fn rate(&self) -> Result<Ratio<usize>> { Ok(One::one()) }
+}
+impl<'b> Describe for Deframer<'b> {
+    fn kind(&self) -> String { "Deframer".to_string() }
+    fn params(&self) -> Vec<(String, String)> {
+        let access_code: String = self.access_code.iter().map(|&b| if b != 0 { '1' } else { '0' }).collect();
+        vec![("access_code".to_string(), access_code),
+             ("payload_len".to_string(), self.payload_len.to_string())]
     }
 }
@@ -1,38 +1,54 @@
 //! These blocks are for processing and manipulating streams of (almost) any type.
 
-use super::RadioBlock;
-use IteratorExtras::{IteratorExtra, MapPairs};
-use IteratorExtras;
+use num::rational::Ratio;
+use num::One;
+
+use super::{RadioBlock, BlockRate, ChunkedIterator, Describe};
+use crate::control::{control, Control, Updates};
+use crate::error::{Error, Result};
+use crate::sample::MulAcc;
 
 /// Splits a stream into two identical streams
-#[deriving(Copy)]
+#[derive(Clone, Copy)]
 pub struct Split;
 pub struct SplitIter<I> {
     iterator: I,
 }
-impl<A: Clone, I: Iterator<A>> Iterator<(A,A)> for SplitIter<I> {
+impl<A: Clone, I: Iterator<Item = A>> Iterator for SplitIter<I> {
+    type Item = (A, A);
     fn next(&mut self) -> Option<(A,A)> {
-        match self.iterator.next() {
-            Some(a) => Some((a.clone(),a)),
-            None => None,
-        }
+        self.iterator.next().map(|a| (a.clone(), a))
     }
 }
-impl<'a, A, I> RadioBlock<A, (A, A), I, SplitIter<I>> for Split
-where A: Clone, I: Iterator<A> {
+impl<A: Clone, I: Iterator<Item = A>> ChunkedIterator for SplitIter<I> {}
+impl<A, I> RadioBlock<I> for Split
+where A: Clone, I: Iterator<Item = A> {
+    type Input = A;
+    type Output = (A, A);
+    type Iter = SplitIter<I>;
+    const INPUTS: usize = 1;
+    const OUTPUTS: usize = 2;
+
     fn process(&self, input: I) -> SplitIter<I> {
         SplitIter{ iterator: input }
     }
 }
+impl BlockRate for Split {
+    fn rate(&self) -> Result<Ratio<usize>> { Ok(One::one()) }
+}
+impl Describe for Split {
+    fn kind(&self) -> String { "Split".to_string() }
+}
 
 /// Interleaves two streams into one stream.
-#[deriving(Copy)]
+#[derive(Clone, Copy)]
 pub struct Interleave;
 pub struct InterleaveIter<A, I> {
     iterator: I,
     other: Option<A>,
 }
-impl<A, I: Iterator<(A,A)>> Iterator<A> for InterleaveIter<A, I> {
+impl<A, I: Iterator<Item = (A,A)>> Iterator for InterleaveIter<A, I> {
+    type Item = A;
     fn next(&mut self) -> Option<A> {
         match self.other.take() {
             Some(b) => Some(b),
@@ -40,49 +56,182 @@ impl<A, I: Iterator<(A,A)>> Iterator<A> for InterleaveIter<A, I> {
         }
     }
 }
-impl<'a, A, I> RadioBlock<(A, A), A, I, InterleaveIter<A, I>> for Interleave
-where A: Clone, I: Iterator<(A, A)> {
+impl<A, I: Iterator<Item = (A,A)>> ChunkedIterator for InterleaveIter<A, I> {}
+impl<A, I> RadioBlock<I> for Interleave
+where A: Clone, I: Iterator<Item = (A, A)> {
+    type Input = (A, A);
+    type Output = A;
+    type Iter = InterleaveIter<A, I>;
+    const INPUTS: usize = 2;
+    const OUTPUTS: usize = 1;
+
     fn process(&self, input: I) -> InterleaveIter<A, I> {
         InterleaveIter{ iterator: input, other: None }
     }
 }
+impl BlockRate for Interleave {
+    fn rate(&self) -> Result<Ratio<usize>> { Ok(Ratio::from_integer(2)) }
+}
+impl Describe for Interleave {
+    fn kind(&self) -> String { "Interleave".to_string() }
+}
 
-#[deriving(Copy)]
+/// Splits alternating elements of a stream into two streams.
+#[derive(Clone, Copy)]
 pub struct DeInterleave;
-impl<'a, A, I> RadioBlock<A, (A,A), I, MapPairs<'a, A, (A,A), I>> for DeInterleave
-where I: Iterator<A> {
-    fn process(&self, input: I) -> MapPairs<'a, A, (A,A), I> {
-        input.map_pairs(|[x,y]| (x,y))
+pub struct DeInterleaveIter<I> {
+    iterator: I,
+}
+impl<A, I: Iterator<Item = A>> Iterator for DeInterleaveIter<I> {
+    type Item = (A, A);
+    fn next(&mut self) -> Option<(A, A)> {
+        let x = self.iterator.next()?;
+        let y = self.iterator.next()?;
+        Some((x, y))
     }
 }
+impl<A, I: Iterator<Item = A>> ChunkedIterator for DeInterleaveIter<I> {}
+impl<A, I> RadioBlock<I> for DeInterleave
+where I: Iterator<Item = A> {
+    type Input = A;
+    type Output = (A, A);
+    type Iter = DeInterleaveIter<I>;
+    const INPUTS: usize = 1;
+    const OUTPUTS: usize = 2;
+
+    fn process(&self, input: I) -> DeInterleaveIter<I> {
+        DeInterleaveIter{ iterator: input }
+    }
+}
+impl BlockRate for DeInterleave {
+    fn rate(&self) -> Result<Ratio<usize>> { Ok(Ratio::new(1, 2)) }
+}
+impl Describe for DeInterleave {
+    fn kind(&self) -> String { "DeInterleave".to_string() }
+}
 
 /// Multiplies two streams.
-#[deriving(Copy)]
+#[derive(Clone, Copy)]
 pub struct Multiply;
 pub struct MultiplyIter<I> {
     iterator: I,
 }
-impl<A, B, C, I> Iterator<C> for MultiplyIter<I>
-where A: Mul<B,C>, I: Iterator<(A,B)> {
-    fn next(&mut self) -> Option<C> {
-        self.iterator.next().map(|(a,b)| a * b)
+impl<A, B, I> Iterator for MultiplyIter<I>
+where A: MulAcc<B>, I: Iterator<Item = (A,B)> {
+    type Item = A::Output;
+    fn next(&mut self) -> Option<A::Output> {
+        self.iterator.next().map(|(a,b)| a.mul(b))
     }
 }
-impl<'a, A, B, C, I> RadioBlock<(A,B), C, I, MultiplyIter<I>> for Multiply
-where A: Mul<B,C>, I: Iterator<(A,B)> {
+impl<A, B, I> ChunkedIterator for MultiplyIter<I>
+where A: MulAcc<B>, I: Iterator<Item = (A,B)> {
+    fn fill(&mut self, output: &mut [A::Output]) -> usize {
+        let mut count = 0;
+        for (slot, (a, b)) in output.iter_mut().zip(self.iterator.by_ref()) {
+            *slot = a.mul(b);
+            count += 1;
+        }
+        count
+    }
+}
+impl<A, B, I> RadioBlock<I> for Multiply
+where A: MulAcc<B>, I: Iterator<Item = (A,B)> {
+    type Input = (A, B);
+    type Output = A::Output;
+    type Iter = MultiplyIter<I>;
+    const INPUTS: usize = 2;
+    const OUTPUTS: usize = 1;
+
     fn process(&self, input: I) -> MultiplyIter<I> {
         MultiplyIter{ iterator: input }
     }
 }
+impl BlockRate for Multiply {
+    fn rate(&self) -> Result<Ratio<usize>> { Ok(One::one()) }
+}
+impl Describe for Multiply {
+    fn kind(&self) -> String { "Multiply".to_string() }
+}
 
 /// Takes every `n`th element.
-#[deriving(Copy)]
+///
+/// The stride has to be at least 1, or `process` panics. `Stride::new` and
+/// `validate` report a stride of 0 as an error instead.
+#[derive(Clone, Copy)]
 pub struct Stride {
-    pub stride: uint,
+    pub stride: usize,
+}
+pub struct StrideIter<I> {
+    iterator: I,
+    stride: usize,
+    started: bool,
+    updates: Option<Updates<usize>>,
+}
+impl<A, I: Iterator<Item = A>> Iterator for StrideIter<I> {
+    type Item = A;
+    fn next(&mut self) -> Option<A> {
+        if let Some(stride) = self.updates.as_ref().and_then(|u| u.take()) {
+            self.stride = stride.max(1);
+        }
+        if self.started {
+            self.iterator.nth(self.stride - 1)
+        } else {
+            self.started = true;
+            self.iterator.next()
+        }
+    }
+}
+impl<A, I: Iterator<Item = A>> ChunkedIterator for StrideIter<I> {}
+impl<A, I> RadioBlock<I> for Stride
+where I: Iterator<Item = A> {
+    type Input = A;
+    type Output = A;
+    type Iter = StrideIter<I>;
+    const INPUTS: usize = 1;
+    const OUTPUTS: usize = 1;
+
+    fn process(&self, input: I) -> StrideIter<I> {
+        assert!(self.stride > 0, "stride must be at least 1");
+        StrideIter{ iterator: input, stride: self.stride, started: false, updates: None }
+    }
+}
+impl Stride {
+    /// Takes every `stride`th element, failing with `Error::InvalidParameter`
+    /// if `stride` is 0
+    pub fn new(stride: usize) -> Result<Stride> {
+        let block = Stride { stride };
+        block.validate()?;
+        Ok(block)
+    }
+
+    /// Checks that the stride is at least 1
+    pub fn validate(&self) -> Result<()> {
+        if self.stride == 0 {
+            return Err(Error::invalid_parameter("stride must be at least 1"));
+        }
+        Ok(())
+    }
+
+    /// Like `process`, but also returns a handle for changing the stride
+    ///
+    /// A new stride sets the gap between the last output and the next one.
+    pub fn process_controlled<A, I>(&self, input: I) -> (StrideIter<I>, Control<usize>)
+    where I: Iterator<Item = A> {
+        let (control, updates) = control();
+        let mut iter = self.process(input);
+        iter.updates = Some(updates);
+        (iter, control)
+    }
+}
+impl BlockRate for Stride {
+    fn rate(&self) -> Result<Ratio<usize>> {
+        self.validate()?;
+        Ok(Ratio::new(1, self.stride))
+    }
 }
-impl<A, I> RadioBlock<A, A, I, IteratorExtras::Stride<A, I>> for Stride
-where I: Iterator<A> {
-    fn process(&self, input: I) -> IteratorExtras::Stride<A, I> {
-        input.stride(self.stride)
+impl Describe for Stride {
+    fn kind(&self) -> String { "Stride".to_string() }
+    fn params(&self) -> Vec<(String, String)> {
+        vec![("stride".to_string(), self.stride.to_string())]
     }
 }
@@ -0,0 +1,416 @@
+/// The lock-free ring buffer behind `push_buffer`
+pub mod spsc;
+
+pub use self::spsc::{Consumer, Producer};
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::cmp::{min, max};
+
+use crate::error::Error;
+use crate::stats::BufferStats;
+
+pub struct FixedBuffer1<A, It> {
+    buff: VecDeque<A>,
+    capacity: usize,
+    input: It,
+    stats: Option<BufferStats>,
+}
+impl<A, It> FixedBuffer1<A, It> {
+    /// Records the buffer's fill level in `stats`
+    pub fn with_stats(mut self, stats: BufferStats) -> FixedBuffer1<A, It> {
+        stats.record_len(self.buff.len());
+        self.stats = Some(stats);
+        self
+    }
+}
+impl<A, It: Iterator<Item = A>> Iterator for FixedBuffer1<A, It> {
+    type Item = A;
+    fn next(&mut self) -> Option<A> {
+        if self.buff.is_empty() {
+            for _ in 0..self.capacity {
+                match self.input.next() {
+                    Some(a) => self.buff.push_front(a),
+                    None => break
+                }
+            }
+            if let Some(stats) = &self.stats {
+                stats.record_len(self.buff.len());
+            }
+        }
+        let elt = self.buff.pop_back();
+        if let Some(stats) = &self.stats {
+            stats.record_len(self.buff.len());
+        }
+        elt
+    }
+}
+pub fn buffer_fixed<A, It: Iterator<Item = A>>(it: It, capacity: usize) -> FixedBuffer1<A, It> {
+    FixedBuffer1 {
+        buff: VecDeque::with_capacity(capacity),
+        capacity,
+        input: it,
+        stats: None,
+    }
+}
+
+/// What a split buffer does when one of its outputs falls so far behind that
+/// its buffer fills up while the other output still needs data
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum OverflowPolicy {
+    /// Return an error once either buffer reaches its capacity
+    Fixed,
+    /// Keep growing the buffers, without bound
+    Grow,
+    /// Grow the buffers up to the given number of elements, then return an error
+    GrowTo(usize),
+    /// Discard the oldest half of the full buffer, counting the elements dropped
+    DropOldest,
+}
+
+/// An error reported by a split buffer's `try_next`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BufferError {
+    /// The other output's buffer is full, holding this many elements
+    Overrun(usize),
+}
+
+struct FixedBuffer2Inner<A, B, It> {
+    iter: It,
+    first: VecDeque<A>,
+    first_capacity: usize,
+    second: VecDeque<B>,
+    second_capacity: usize,
+    policy: OverflowPolicy,
+    dropped: usize,
+    /// The overrun that ended the streams, if there was one
+    overrun: Option<BufferError>,
+    first_stats: Option<BufferStats>,
+    second_stats: Option<BufferStats>,
+}
+type FixedBuffer2Shared<A, B, It> = Rc<RefCell<FixedBuffer2Inner<A, B, It>>>;
+
+impl<A, B, It: Iterator<Item = (A,B)>> FixedBuffer2Inner<A, B, It> {
+    /// How many more elements a buffer can take right now
+    fn room(&self, len: usize, capacity: usize) -> usize {
+        match self.policy {
+            OverflowPolicy::Fixed | OverflowPolicy::DropOldest => capacity - len,
+            OverflowPolicy::Grow => capacity,
+            OverflowPolicy::GrowTo(limit) => min(capacity, limit - min(limit, len)),
+        }
+    }
+
+    /// Pulls more elements from the input into both buffers
+    fn fill(&mut self) -> Result<(), BufferError> {
+        let mut num_to_take = min(self.room(self.first.len(), self.first_capacity),
+                                  self.room(self.second.len(), self.second_capacity));
+        if num_to_take == 0 {
+            let first_full = self.first.len() >= self.second.len();
+            let full_stats = if first_full { &self.first_stats } else { &self.second_stats };
+            if let Some(stats) = full_stats {
+                stats.record_overflow();
+            }
+            if self.policy != OverflowPolicy::DropOldest {
+                return Err(BufferError::Overrun(max(self.first.len(), self.second.len())));
+            }
+            if self.first.len() == self.first_capacity {
+                let to_drop = max(self.first_capacity / 2, 1);
+                for _ in 0..to_drop { self.first.pop_front(); }
+                self.dropped += to_drop;
+            }
+            if self.second.len() == self.second_capacity {
+                let to_drop = max(self.second_capacity / 2, 1);
+                for _ in 0..to_drop { self.second.pop_front(); }
+                self.dropped += to_drop;
+            }
+            num_to_take = min(self.first_capacity - self.first.len(),
+                              self.second_capacity - self.second.len());
+        }
+        for _ in 0..num_to_take {
+            match self.iter.next() {
+                Some((a,b)) => {self.first.push_back(a);
+                                self.second.push_back(b);},
+                None => break
+            }
+        }
+        self.record_lens();
+        Ok(())
+    }
+
+    fn record_lens(&self) {
+        if let Some(stats) = &self.first_stats {
+            stats.record_len(self.first.len());
+        }
+        if let Some(stats) = &self.second_stats {
+            stats.record_len(self.second.len());
+        }
+    }
+}
+
+pub struct FixedBuffer2First<A, B, It> {
+    data: FixedBuffer2Shared<A, B, It>
+}
+impl<A,B, It: Iterator<Item = (A,B)>> FixedBuffer2First<A, B, It> {
+    /// Like `next`, but returns the error instead of ending the stream on overflow
+    pub fn try_next(&mut self) -> Result<Option<A>, BufferError> {
+        let mut inner = self.data.borrow_mut();
+        if inner.first.is_empty() {
+            inner.fill()?;
+        }
+        let elt = inner.first.pop_front();
+        if let Some(stats) = &inner.first_stats {
+            stats.record_len(inner.first.len());
+        }
+        Ok(elt)
+    }
+
+    /// Records the fill level and overflows of this output's buffer in `stats`
+    pub fn with_stats(self, stats: BufferStats) -> FixedBuffer2First<A, B, It> {
+        {
+            let mut inner = self.data.borrow_mut();
+            stats.record_len(inner.first.len());
+            inner.first_stats = Some(stats);
+        }
+        self
+    }
+
+    /// The number of elements discarded so far by `OverflowPolicy::DropOldest`
+    pub fn dropped(&self) -> usize {
+        self.data.borrow().dropped
+    }
+
+    /// Returns `Error::BufferOverrun` if the streams were ended by an overflow
+    pub fn check(&self) -> Result<(), Error> {
+        self.data.borrow().overrun.map_or(Ok(()), |e| Err(e.into()))
+    }
+}
+impl<A,B, It: Iterator<Item = (A,B)>> Iterator for FixedBuffer2First<A, B, It> {
+    type Item = A;
+    fn next(&mut self) -> Option<A> {
+        match self.try_next() {
+            Ok(a) => a,
+            Err(e) => {
+                self.data.borrow_mut().overrun = Some(e);
+                None
+            },
+        }
+    }
+}
+
+pub struct FixedBuffer2Second<A, B, It> {
+    data: FixedBuffer2Shared<A, B, It>
+}
+impl<A,B, It: Iterator<Item = (A,B)>> FixedBuffer2Second<A, B, It> {
+    /// Like `next`, but returns the error instead of ending the stream on overflow
+    pub fn try_next(&mut self) -> Result<Option<B>, BufferError> {
+        let mut inner = self.data.borrow_mut();
+        if inner.second.is_empty() {
+            inner.fill()?;
+        }
+        let elt = inner.second.pop_front();
+        if let Some(stats) = &inner.second_stats {
+            stats.record_len(inner.second.len());
+        }
+        Ok(elt)
+    }
+
+    /// Records the fill level and overflows of this output's buffer in `stats`
+    pub fn with_stats(self, stats: BufferStats) -> FixedBuffer2Second<A, B, It> {
+        {
+            let mut inner = self.data.borrow_mut();
+            stats.record_len(inner.second.len());
+            inner.second_stats = Some(stats);
+        }
+        self
+    }
+
+    /// The number of elements discarded so far by `OverflowPolicy::DropOldest`
+    pub fn dropped(&self) -> usize {
+        self.data.borrow().dropped
+    }
+
+    /// Returns `Error::BufferOverrun` if the streams were ended by an overflow
+    pub fn check(&self) -> Result<(), Error> {
+        self.data.borrow().overrun.map_or(Ok(()), |e| Err(e.into()))
+    }
+}
+impl<A,B, It: Iterator<Item = (A,B)>> Iterator for FixedBuffer2Second<A,B,It> {
+    type Item = B;
+    fn next(&mut self) -> Option<B> {
+        match self.try_next() {
+            Ok(b) => b,
+            Err(e) => {
+                self.data.borrow_mut().overrun = Some(e);
+                None
+            },
+        }
+    }
+}
+
+/// Splits a stream of pairs into two streams, each with a fixed-size buffer
+///
+/// If one output falls far enough behind that its buffer fills up, the other
+/// output ends early, and `check` on either output returns
+/// `Error::BufferOverrun`. An output that ends early looks just like one that
+/// finished, so `check` it once the stream is done. Use
+/// `split_fixed_with_policy` to handle this differently.
+pub fn split_fixed<A, B, It: Iterator<Item = (A,B)>>(it: It, cap_a: usize, cap_b: usize) ->
+                                                (FixedBuffer2First<A, B, It>,
+                                                 FixedBuffer2Second<A, B, It>) {
+    split_fixed_with_policy(it, cap_a, cap_b, OverflowPolicy::Fixed)
+}
+
+/// Like `split_fixed`, but with a choice of what to do when a buffer overflows
+///
+/// With `OverflowPolicy::Fixed` or `OverflowPolicy::GrowTo`, an overflow
+/// makes `try_next` return `Err(BufferError::Overrun(_))`, while `next`
+/// ends the stream and leaves the error for `check`.
+pub fn split_fixed_with_policy<A, B, It>(it: It, cap_a: usize, cap_b: usize, policy: OverflowPolicy) ->
+                                           (FixedBuffer2First<A, B, It>,
+                                            FixedBuffer2Second<A, B, It>)
+where It: Iterator<Item = (A,B)> {
+    let data = Rc::new(RefCell::new(FixedBuffer2Inner {
+        iter: it,
+        first: VecDeque::with_capacity(cap_a),
+        first_capacity: cap_a,
+        second: VecDeque::with_capacity(cap_b),
+        second_capacity: cap_b,
+        policy,
+        dropped: 0,
+        overrun: None,
+        first_stats: None,
+        second_stats: None,
+    }));
+
+    (FixedBuffer2First { data: data.clone() }, FixedBuffer2Second { data })
+}
+
+struct FanOutInner<A, It> {
+    iter: It,
+    buff: VecDeque<A>,
+    /// Stream index of the element at the front of `buff`
+    start: usize,
+    capacity: usize,
+    /// Stream index of the next element for each output, or `None` once
+    /// that output has been dropped
+    cursors: Vec<Option<usize>>,
+    /// The overrun that ended the streams, if there was one
+    overrun: Option<BufferError>,
+}
+type FanOutShared<A, It> = Rc<RefCell<FanOutInner<A, It>>>;
+
+/// Hands out any number of outputs that all see the same stream
+pub struct FanOutFixed<A, It> {
+    data: FanOutShared<A, It>
+}
+impl<A, It> FanOutFixed<A, It> {
+    /// Creates a new output
+    ///
+    /// The output starts at the oldest element still held in the buffer, so
+    /// all outputs should be created before any of them are read from.
+    pub fn output(&self) -> FixedBufferN<A, It> {
+        let mut inner = self.data.borrow_mut();
+        let start = inner.start;
+        inner.cursors.push(Some(start));
+        FixedBufferN { data: self.data.clone(), id: inner.cursors.len() - 1 }
+    }
+}
+
+pub struct FixedBufferN<A, It> {
+    data: FanOutShared<A, It>,
+    id: usize,
+}
+impl<A, It> FixedBufferN<A, It> {
+    /// Returns `Error::BufferOverrun` if the streams were ended by an overflow
+    pub fn check(&self) -> Result<(), Error> {
+        self.data.borrow().overrun.map_or(Ok(()), |e| Err(e.into()))
+    }
+}
+impl<A: Clone, It: Iterator<Item = A>> Iterator for FixedBufferN<A, It> {
+    type Item = A;
+    fn next(&mut self) -> Option<A> {
+        let mut inner = self.data.borrow_mut();
+        let inner = &mut *inner;
+        let pos = inner.cursors[self.id].unwrap();
+
+        if pos == inner.start + inner.buff.len() {
+            let num_to_take = inner.capacity - inner.buff.len();
+            if num_to_take == 0 {
+                inner.overrun = Some(BufferError::Overrun(inner.capacity));
+                return None;
+            }
+            for _ in 0..num_to_take {
+                match inner.iter.next() {
+                    Some(a) => inner.buff.push_back(a),
+                    None => break
+                }
+            }
+            if pos == inner.start + inner.buff.len() {
+                return None;
+            }
+        }
+
+        let elt = inner.buff[pos - inner.start].clone();
+        inner.cursors[self.id] = Some(pos + 1);
+
+        // Release everything that all of the outputs have read
+        let oldest = inner.cursors.iter().filter_map(|&c| c).min().unwrap();
+        while inner.start < oldest {
+            inner.buff.pop_front();
+            inner.start += 1;
+        }
+
+        Some(elt)
+    }
+}
+impl<A, It> Drop for FixedBufferN<A, It> {
+    fn drop(&mut self) {
+        // Stop holding back the other outputs
+        self.data.borrow_mut().cursors[self.id] = None;
+    }
+}
+
+/// Broadcasts one stream to any number of outputs
+///
+/// Unlike chaining `split_fixed`s, every output reads from the same backing
+/// buffer using its own read cursor, so each element is only stored once. The
+/// buffer holds at most `capacity` elements, measured from the output that is
+/// furthest behind. Outputs are created with `FanOutFixed::output`. If an output
+/// needs more than that, it ends early, and `check` returns
+/// `Error::BufferOverrun`, so `check` an output once it has ended.
+pub fn fan_out_fixed<A, It: Iterator<Item = A>>(it: It, capacity: usize) -> FanOutFixed<A, It> {
+    FanOutFixed {
+        data: Rc::new(RefCell::new(FanOutInner {
+            iter: it,
+            buff: VecDeque::with_capacity(capacity),
+            start: 0,
+            capacity,
+            cursors: Vec::new(),
+            overrun: None,
+        }))
+    }
+}
+
+/// Provides a means to iterate over elements that are provided via pushing
+///
+/// This function returns two objects, a `Producer` and `Consumer`, that share
+/// an internal buffer. You can push elements to the buffer via the `push_slice`
+/// method of the `Producer` object. You can also iterate over the buffer by
+/// using the `Consumer`. The `Consumer` will block until elements are available,
+/// and will return `None` once the `Producer` is closed (or dropped) and the
+/// buffer is empty. The buffer's capacity is fixed: `push_slice` fails if it
+/// would overflow, while `push_slice_blocking` waits for the `Consumer` to
+/// catch up. Both fail once the `Consumer` has been dropped.
+///
+/// The buffer is the lock-free ring in `spsc`, so it has a single producer:
+/// the `Producer` can be moved to another thread, but not shared between
+/// threads.
+///
+/// Panics if `capacity` is 0, since `push_slice_blocking` could never push
+/// anything.
+pub fn push_buffer<T>(capacity: usize) -> (Producer<T>, Consumer<T>)
+where T: Send + Clone {
+    assert!(capacity > 0, "a push_buffer needs room for at least one element");
+    spsc::ring_buffer(capacity)
+}
@@ -0,0 +1,287 @@
+//! The lock-free single-producer/single-consumer ring behind `push_buffer`
+//!
+//! The `Producer` and `Consumer` only share a pair of atomic indices into a
+//! fixed ring of slots, so neither side takes a lock to push or pop. A side
+//! that has to wait (for data, or for room) spins and then yields for a little
+//! while, since the other side usually catches up quickly, and then sleeps on a
+//! condition variable until the other side wakes it.
+
+use std::cell::{Cell, UnsafeCell};
+use std::cmp::min;
+use std::hint;
+use std::marker::PhantomData;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::sync::atomic::{fence, AtomicUsize, AtomicBool};
+use std::sync::atomic::Ordering::{Acquire, Release, Relaxed, SeqCst};
+use std::thread;
+
+use crate::stats::BufferStats;
+
+/// How many times a side checks again before yielding its thread
+const SPINS: usize = 64;
+/// How many times a side yields its thread before going to sleep
+const YIELDS: usize = 16;
+
+struct Ring<T> {
+    slots: Vec<UnsafeCell<Option<T>>>,
+    /// Total number of elements ever read. Only written by the `Consumer`.
+    head: AtomicUsize,
+    /// Total number of elements ever written. Only written by the `Producer`.
+    tail: AtomicUsize,
+    /// Set once the `Producer` is closed or dropped
+    closed: AtomicBool,
+    /// Set once the `Consumer` is dropped, so nothing will read what's pushed
+    disconnected: AtomicBool,
+    /// The number of sides asleep on `wakeup`
+    sleepers: AtomicUsize,
+    lock: Mutex<()>,
+    wakeup: Condvar,
+    stats: OnceLock<BufferStats>,
+}
+
+unsafe impl<T: Send> Send for Ring<T> {}
+unsafe impl<T: Send> Sync for Ring<T> {}
+
+impl<T> Ring<T> {
+    /// Returns the slot for the (unwrapped) index `idx`
+    ///
+    /// Callers must only touch slots that the indices say they own.
+    fn slot(&self, idx: usize) -> *mut Option<T> {
+        self.slots[idx % self.slots.len()].get()
+    }
+
+    fn record_len(&self, head: usize, tail: usize) {
+        if let Some(stats) = self.stats.get() {
+            stats.record_len(tail - head);
+        }
+    }
+
+    /// Waits until `ready` returns true, spinning and yielding at first and
+    /// then sleeping until the other side calls `wake`
+    fn wait_until(&self, ready: impl Fn() -> bool) {
+        for i in 0..SPINS + YIELDS {
+            if ready() {
+                return;
+            }
+            if i < SPINS {
+                hint::spin_loop();
+            } else {
+                thread::yield_now();
+            }
+        }
+        let mut guard = self.lock.lock().unwrap();
+        self.sleepers.fetch_add(1, SeqCst);
+        // pairs with the fence in `wake`: either this sees the other side's
+        // change, or the other side sees the sleeper and takes the lock
+        fence(SeqCst);
+        while !ready() {
+            guard = self.wakeup.wait(guard).unwrap();
+        }
+        self.sleepers.fetch_sub(1, SeqCst);
+    }
+
+    /// Wakes the `Producer` if the `Consumer` has popped enough to let it
+    /// finish waiting, which is at most half of the ring
+    fn wake_producer(&self, head: usize, tail: usize) {
+        if tail - head <= self.slots.len() / 2 {
+            self.wake();
+        }
+    }
+
+    /// Wakes the other side if it's asleep in `wait_until`, after a change
+    /// it might be waiting for
+    fn wake(&self) {
+        fence(SeqCst);
+        if self.sleepers.load(SeqCst) > 0 {
+            let _guard = self.lock.lock().unwrap();
+            self.wakeup.notify_all();
+        }
+    }
+}
+
+pub struct Consumer<T> {
+    ring: Arc<Ring<T>>,
+}
+
+impl<T: Send> Consumer<T> {
+    /// Pops the next element without blocking
+    ///
+    /// Returns `None` whenever the buffer is empty, even if the `Producer`
+    /// may push more elements later.
+    pub fn try_next(&mut self) -> Option<T> {
+        let head = self.ring.head.load(Relaxed);
+        let tail = self.ring.tail.load(Acquire);
+        if head == tail {
+            return None;
+        }
+        let elt = unsafe { (*self.ring.slot(head)).take() };
+        self.ring.head.store(head + 1, Release);
+        self.ring.record_len(head + 1, tail);
+        self.ring.wake_producer(head + 1, tail);
+        elt
+    }
+
+    /// Moves as many available elements as fit into `out`, without blocking
+    ///
+    /// Returns the number of elements written to the front of `out`.
+    pub fn pop_slice(&mut self, out: &mut [T]) -> usize {
+        let head = self.ring.head.load(Relaxed);
+        let tail = self.ring.tail.load(Acquire);
+        let count = min(tail - head, out.len());
+        for (i, dest) in out.iter_mut().take(count).enumerate() {
+            *dest = unsafe { (*self.ring.slot(head + i)).take().unwrap() };
+        }
+        self.ring.head.store(head + count, Release);
+        if count > 0 {
+            self.ring.record_len(head + count, tail);
+            self.ring.wake_producer(head + count, tail);
+        }
+        count
+    }
+
+    /// Records the buffer's fill level, and the pushes it had no room for, in `stats`
+    ///
+    /// Only the first `BufferStats` attached to a buffer is kept.
+    pub fn with_stats(self, stats: BufferStats) -> Consumer<T> {
+        stats.record_len(self.ring.tail.load(Acquire) - self.ring.head.load(Relaxed));
+        let _ = self.ring.stats.set(stats);
+        self
+    }
+}
+
+impl<T> Drop for Consumer<T> {
+    /// Wakes a `Producer` waiting for room, so it can give up
+    fn drop(&mut self) {
+        self.ring.disconnected.store(true, Release);
+        self.ring.wake();
+    }
+}
+
+impl<T: Send> Iterator for Consumer<T> {
+    type Item = T;
+    /// Waits until an element is available, or returns `None` once the
+    /// `Producer` has been closed and the buffer is drained.
+    fn next(&mut self) -> Option<T> {
+        loop {
+            if let Some(elt) = self.try_next() {
+                return Some(elt);
+            }
+            if self.ring.closed.load(Acquire) {
+                // The producer may have pushed right before closing
+                return self.try_next();
+            }
+            let ring = &*self.ring;
+            let head = ring.head.load(Relaxed);
+            ring.wait_until(|| ring.tail.load(Acquire) != head || ring.closed.load(Acquire));
+        }
+    }
+}
+
+pub struct Producer<T> {
+    ring: Arc<Ring<T>>,
+    // only one thread may push at a time, so the producer is not `Sync`
+    marker: PhantomData<Cell<()>>,
+}
+
+impl<T: Send + Clone> Producer<T> {
+    /// Pushes as many elements from the front of `elts` as there is room for,
+    /// returning how many were pushed
+    fn push_available(&self, elts: &[T]) -> usize {
+        let head = self.ring.head.load(Acquire);
+        let tail = self.ring.tail.load(Relaxed);
+        let count = min(self.ring.slots.len() - (tail - head), elts.len());
+        for (i, elt) in elts.iter().take(count).enumerate() {
+            unsafe { *self.ring.slot(tail + i) = Some(elt.clone()); }
+        }
+        self.ring.tail.store(tail + count, Release);
+        self.ring.record_len(head, tail + count);
+        if count > 0 {
+            self.ring.wake();
+        }
+        count
+    }
+
+    /// Push a slice of elements to the internal buffer
+    ///
+    /// If there is not enough capacity in the buffer for all of the
+    /// elements in the slice, `Err(n)` will be returned, where `n`
+    /// is the number of elements in the slice that were successfully
+    /// pushed to the buffer. Nothing is pushed once the `Consumer` has been
+    /// dropped, which returns `Err(0)`.
+    pub fn push_slice(&self, elts: &[T]) -> Result<(), usize> {
+        if self.ring.disconnected.load(Acquire) {
+            return Err(0);
+        }
+        let count = self.push_available(elts);
+        if count == elts.len() {
+            return Ok(());
+        }
+        if let Some(stats) = self.ring.stats.get() {
+            stats.record_overflow();
+        }
+        Err(count)
+    }
+
+    /// Push a slice of elements to the internal buffer, waiting for the
+    /// `Consumer` to make room whenever the buffer is full
+    ///
+    /// If the `Consumer` is dropped, or already has been, this stops waiting
+    /// and returns `Err(n)`, where `n` is the number of elements pushed
+    /// before then. Nothing will read any more elements, so whatever is
+    /// producing them can stop.
+    pub fn push_slice_blocking(&self, elts: &[T]) -> Result<(), usize> {
+        let ring = &*self.ring;
+        let mut pushed = 0;
+        loop {
+            if ring.disconnected.load(Acquire) {
+                return Err(pushed);
+            }
+            pushed += self.push_available(&elts[pushed..]);
+            if pushed == elts.len() {
+                return Ok(());
+            }
+            // waiting for more than one free slot saves waking up for each
+            // element the consumer pops, see `wake_producer`
+            let tail = ring.tail.load(Relaxed);
+            let wanted = min(elts.len() - pushed, ring.slots.len().div_ceil(2));
+            ring.wait_until(|| {
+                ring.slots.len() - (tail - ring.head.load(Acquire)) >= wanted
+                    || ring.disconnected.load(Acquire)
+            });
+        }
+    }
+}
+
+impl<T> Producer<T> {
+    /// Signals that no more elements will be pushed
+    ///
+    /// Once the buffer has been drained, the `Consumer` will return `None`.
+    /// Dropping the `Producer` closes it as well.
+    pub fn close(&self) {
+        self.ring.closed.store(true, Release);
+        self.ring.wake();
+    }
+}
+
+impl<T> Drop for Producer<T> {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
+
+/// Makes a ring with room for `capacity` elements, see `push_buffer`
+pub(super) fn ring_buffer<T>(capacity: usize) -> (Producer<T>, Consumer<T>)
+where T: Send + Clone {
+    let ring = Arc::new(Ring {
+        slots: (0..capacity).map(|_| UnsafeCell::new(None)).collect(),
+        head: AtomicUsize::new(0),
+        tail: AtomicUsize::new(0),
+        closed: AtomicBool::new(false),
+        disconnected: AtomicBool::new(false),
+        sleepers: AtomicUsize::new(0),
+        lock: Mutex::new(()),
+        wakeup: Condvar::new(),
+        stats: OnceLock::new(),
+    });
+    (Producer { ring: ring.clone(), marker: PhantomData }, Consumer { ring })
+}
@@ -0,0 +1,87 @@
+//! Changes the parameters of blocks while samples are flowing through them.
+//!
+//! Blocks that can be adjusted have a `process_controlled` method alongside
+//! `process`. It returns the block's iterator and a `Control` handle for it.
+//! The handle can be cloned and sent to other threads. A value passed to `set`
+//! is picked up by the iterator just before it produces its next output
+//! sample, so every output is computed entirely with either the old or the new
+//! parameters. If several values are set in between two outputs, only the
+//! latest is used. Values the block can't work with are rejected by `set`,
+//! and the block carries on with what it had.
+//!
+//! # Example
+//! ```
+//! use rustradio::blocks::stream::Stride;
+//!
+//! let (mut strided, control) = Stride{ stride: 2 }.process_controlled(0usize..);
+//! assert_eq!(strided.next(), Some(0));
+//! assert_eq!(strided.next(), Some(2));
+//! control.set(5)?;
+//! assert_eq!(strided.next(), Some(7));
+//! assert_eq!(strided.next(), Some(12));
+//! # Ok::<(), rustradio::Error>(())
+//! ```
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::error::Result;
+
+struct Shared<T> {
+    pending: Mutex<Option<T>>,
+    changed: AtomicBool,
+}
+
+/// The handle used to update a running block
+pub struct Control<T> {
+    shared: Arc<Shared<T>>,
+    validate: fn(&T) -> Result<()>,
+}
+
+impl<T> Control<T> {
+    /// Queues a new value, which takes effect before the block's next output
+    ///
+    /// Fails with `Error::InvalidParameter`, without queueing anything, if the
+    /// block can't work with the value.
+    pub fn set(&self, value: T) -> Result<()> {
+        (self.validate)(&value)?;
+        *self.shared.pending.lock().unwrap() = Some(value);
+        self.shared.changed.store(true, Ordering::Release);
+        Ok(())
+    }
+}
+
+impl<T> Clone for Control<T> {
+    fn clone(&self) -> Control<T> {
+        Control { shared: self.shared.clone(), validate: self.validate }
+    }
+}
+
+/// The block's end of a `Control`
+pub struct Updates<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> Updates<T> {
+    /// Takes the latest value set, if there is one
+    ///
+    /// This is cheap when nothing has changed, so blocks call it for every sample.
+    pub fn take(&self) -> Option<T> {
+        if !self.shared.changed.load(Ordering::Acquire) {
+            return None;
+        }
+        self.shared.changed.store(false, Ordering::Release);
+        self.shared.pending.lock().unwrap().take()
+    }
+}
+
+/// Creates a `Control` handle and the `Updates` it sends values to
+pub fn control<T>() -> (Control<T>, Updates<T>) {
+    checked_control(|_| Ok(()))
+}
+
+/// Like `control`, but `set` only passes on values that `validate` accepts
+pub fn checked_control<T>(validate: fn(&T) -> Result<()>) -> (Control<T>, Updates<T>) {
+    let shared = Arc::new(Shared { pending: Mutex::new(None), changed: AtomicBool::new(false) });
+    (Control { shared: shared.clone(), validate }, Updates { shared })
+}
@@ -0,0 +1,338 @@
+//! Draws a flowgraph as a Graphviz DOT graph.
+//!
+//! A `DotGraph` builds the flowgraph it draws. Streams go through it in place
+//! of the `connect`, `split` and `join` functions: `source` starts a `Traced`
+//! stream, and `connect`, `split` and `join` build the same buffers and blocks
+//! their namesakes do, while adding what they built to the graph. `sink` hands
+//! back the finished stream to be run, so what's drawn is always what runs.
+//!
+//! Each block's node is labelled with the block's type and parameters from
+//! `Describe`. Each connection is labelled with the item type of the stream
+//! and the capacities of the buffers it goes through, in order. Ports are
+//! shown at the ends of an edge whenever a block has more than one input or
+//! output. The text from `to_dot` can be rendered with `dot -Tsvg`.
+//!
+//! # Example
+//! ```
+//! use rustradio::blocks::filter::FilterFIR;
+//! use rustradio::blocks::stream::Stride;
+//! use rustradio::dot::DotGraph;
+//!
+//! let taps = vec![1f32; 16];
+//! let mut graph = DotGraph::new();
+//! let source = graph.source("source", "Samples", (0..100).map(|x| x as f32));
+//! let filtered = graph.connect("filter", &FilterFIR{ taps: &taps }, source);
+//! let decimated = graph.connect_with_capacity("decimate", &Stride{ stride: 4 }, filtered, 512);
+//! let output: Vec<f32> = graph.sink("sink", "Collect", decimated).collect();
+//! assert_eq!(output.len(), 25);
+//!
+//! let dot = graph.to_dot();
+//! assert!(dot.contains("filter\\nFilterFIR\\ntaps: 16"));
+//! assert!(dot.contains("label=\"f32\\n512\""));
+//! ```
+
+use std::any::type_name;
+use std::fmt::Write;
+
+use crate::blocks::{Describe, RadioBlock};
+use crate::buffers::{self, FixedBuffer1};
+use crate::{JoinStreams, SplitStreams, DEFAULT_BUFFER_SIZE};
+
+/// A node in a `DotGraph`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NodeId(usize);
+
+/// One of a node's numbered inputs or outputs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Port {
+    pub node: NodeId,
+    pub port: usize,
+}
+
+impl From<NodeId> for Port {
+    fn from(node: NodeId) -> Port {
+        Port { node, port: 0 }
+    }
+}
+
+impl From<(NodeId, usize)> for Port {
+    fn from((node, port): (NodeId, usize)) -> Port {
+        Port { node, port }
+    }
+}
+
+struct Node {
+    name: String,
+    kind: String,
+    params: Vec<(String, String)>,
+}
+
+struct Edge {
+    from: Port,
+    to: Port,
+    item_type: String,
+    capacities: Vec<usize>,
+}
+
+/// Where one of the streams making up a `Traced` stream comes from
+#[derive(Clone, Debug)]
+pub struct Origin {
+    port: Port,
+    item_type: String,
+    /// The capacities of the buffers the stream has gone through since the port
+    capacities: Vec<usize>,
+}
+
+/// A stream built by a `DotGraph`, which remembers where in the graph it comes from
+///
+/// A stream of tuples, such as the output of a block with several outputs, or
+/// of `join`, comes from one port for each element.
+pub struct Traced<I> {
+    iter: I,
+    origins: Vec<Origin>,
+}
+
+impl<I> Traced<I> {
+    /// The stream itself, without drawing where it goes
+    pub fn into_inner(self) -> I {
+        self.iter
+    }
+}
+
+/// A description of a flowgraph, which can be written out as DOT
+#[derive(Default)]
+pub struct DotGraph {
+    nodes: Vec<Node>,
+    edges: Vec<Edge>,
+}
+
+impl DotGraph {
+    pub fn new() -> DotGraph {
+        DotGraph::default()
+    }
+
+    /// Adds a node with the given type and parameters
+    pub(crate) fn node(&mut self, name: &str, kind: &str, params: Vec<(String, String)>) -> NodeId {
+        self.nodes.push(Node { name: name.to_string(), kind: kind.to_string(), params });
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Adds a connection whose item type is given by name
+    pub(crate) fn edge(&mut self, from: impl Into<Port>, to: impl Into<Port>, item_type: &str, capacities: Vec<usize>) {
+        self.edges.push(Edge { from: from.into(), to: to.into(), item_type: item_type.to_string(), capacities });
+    }
+
+    /// Adds edges from each of `origins` to the matching input of `node`
+    fn connect_origins(&mut self, origins: Vec<Origin>, node: NodeId) {
+        for (port, origin) in origins.into_iter().enumerate() {
+            self.edge(origin.port, (node, port), &origin.item_type, origin.capacities);
+        }
+    }
+
+    /// Starts a stream at a node for `iter`, which isn't a block, such as a
+    /// file or a device
+    pub fn source<I: Iterator>(&mut self, name: &str, kind: &str, iter: I) -> Traced<I> {
+        let node = self.node(name, kind, Vec::new());
+        let item_type = short_type_name(type_name::<I::Item>());
+        Traced { iter, origins: vec![Origin { port: node.into(), item_type, capacities: Vec::new() }] }
+    }
+
+    /// Connects a block to its input stream through a buffer of `DEFAULT_BUFFER_SIZE`, as `connect` does
+    pub fn connect<A, I, R>(&mut self, name: &str, block: &R, input: Traced<I>) -> Traced<R::Iter>
+    where I: Iterator<Item = A>, R: RadioBlock<FixedBuffer1<A, I>, Input = A> + Describe {
+        self.connect_with_capacity(name, block, input, DEFAULT_BUFFER_SIZE)
+    }
+
+    /// Connects a block to its input stream through a buffer of `capacity` items
+    ///
+    /// Panics if the block doesn't have one input for each stream that makes
+    /// up `input`.
+    pub fn connect_with_capacity<A, I, R>(&mut self, name: &str, block: &R, input: Traced<I>, capacity: usize)
+        -> Traced<R::Iter>
+    where I: Iterator<Item = A>, R: RadioBlock<FixedBuffer1<A, I>, Input = A> + Describe {
+        assert_eq!(input.origins.len(), R::INPUTS,
+                   "{} has {} inputs, but is connected to {} streams", name, R::INPUTS, input.origins.len());
+        let node = self.node(name, &block.kind(), block.params());
+        let mut origins = input.origins;
+        for origin in origins.iter_mut() {
+            origin.capacities.push(capacity);
+        }
+        self.connect_origins(origins, node);
+
+        let item_types = tuple_types(type_name::<R::Output>(), R::OUTPUTS);
+        let origins = item_types.into_iter().enumerate()
+            .map(|(port, item_type)| Origin { port: (node, port).into(), item_type, capacities: Vec::new() })
+            .collect();
+        Traced { iter: block.process(buffers::buffer_fixed(input.iter, capacity)), origins }
+    }
+
+    /// Splits a stream of tuples into a tuple of streams, each with a buffer of
+    /// `DEFAULT_BUFFER_SIZE`, as `split` does
+    pub fn split<I>(&mut self, input: Traced<I>) -> <<I::Item as SplitStreams<I>>::Streams as TraceStreams>::Traced
+    where I: Iterator, I::Item: SplitStreams<I>, <I::Item as SplitStreams<I>>::Streams: TraceStreams {
+        self.split_with_capacity(input, DEFAULT_BUFFER_SIZE)
+    }
+
+    /// Splits a stream of tuples into a tuple of streams, each with a buffer of `capacity` items
+    ///
+    /// Each stream comes from the matching output of the block that made the
+    /// tuples, or from the same port if they were all made by one source.
+    pub fn split_with_capacity<I>(&mut self, input: Traced<I>, capacity: usize)
+        -> <<I::Item as SplitStreams<I>>::Streams as TraceStreams>::Traced
+    where I: Iterator, I::Item: SplitStreams<I>, <I::Item as SplitStreams<I>>::Streams: TraceStreams {
+        let streams = <<I::Item as SplitStreams<I>>::Streams as TraceStreams>::STREAMS;
+        let mut origins = match input.origins.len() {
+            n if n == streams => input.origins,
+            1 => tuple_types(type_name::<I::Item>(), streams).into_iter()
+                .map(|item_type| Origin { item_type, ..input.origins[0].clone() })
+                .collect(),
+            n => panic!("can't split a stream from {} ports into {} streams", n, streams),
+        };
+        for origin in origins.iter_mut() {
+            origin.capacities.push(capacity);
+        }
+        <I::Item as SplitStreams<I>>::split_streams(input.iter, capacity).trace(origins)
+    }
+
+    /// Combines a tuple of streams into a single stream of tuples, as `join` does
+    pub fn join<S: JoinTraced>(&self, streams: S) -> Traced<S::Joined> {
+        streams.join_traced()
+    }
+
+    /// Ends a stream at a node for whatever consumes it, and returns the stream
+    /// to be run
+    pub fn sink<I>(&mut self, name: &str, kind: &str, input: Traced<I>) -> I {
+        let node = self.node(name, kind, Vec::new());
+        self.connect_origins(input.origins, node);
+        input.iter
+    }
+
+    /// Writes out the graph in the DOT language
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph flowgraph {\n    rankdir=LR;\n    node [shape=box];\n");
+        for (i, node) in self.nodes.iter().enumerate() {
+            let mut label = format!("{}\n{}", node.name, node.kind);
+            for (name, value) in node.params.iter() {
+                write!(label, "\n{}: {}", name, value).unwrap();
+            }
+            writeln!(dot, "    n{} [label=\"{}\"];", i, escape(&label)).unwrap();
+        }
+        for edge in self.edges.iter() {
+            let mut label = edge.item_type.clone();
+            if !edge.capacities.is_empty() {
+                let capacities: Vec<String> = edge.capacities.iter().map(usize::to_string).collect();
+                write!(label, "\n{}", capacities.join(", ")).unwrap();
+            }
+            write!(dot, "    n{} -> n{} [label=\"{}\"", edge.from.node.0, edge.to.node.0, escape(&label)).unwrap();
+            if self.outputs(edge.from.node) > 1 {
+                write!(dot, ", taillabel=\"{}\"", edge.from.port).unwrap();
+            }
+            if self.inputs(edge.to.node) > 1 {
+                write!(dot, ", headlabel=\"{}\"", edge.to.port).unwrap();
+            }
+            dot.push_str("];\n");
+        }
+        dot.push_str("}\n");
+        dot
+    }
+
+    fn outputs(&self, node: NodeId) -> usize {
+        self.edges.iter().filter(|e| e.from.node == node).map(|e| e.from.port + 1).max().unwrap_or(0)
+    }
+
+    fn inputs(&self, node: NodeId) -> usize {
+        self.edges.iter().filter(|e| e.to.node == node).map(|e| e.to.port + 1).max().unwrap_or(0)
+    }
+}
+
+/// Tuples of streams made by `split`, which `DotGraph::split` turns into `Traced` streams
+pub trait TraceStreams {
+    type Traced;
+    /// The number of streams in the tuple
+    const STREAMS: usize;
+    fn trace(self, origins: Vec<Origin>) -> Self::Traced;
+}
+
+/// Tuples of `Traced` streams that `DotGraph::join` can combine
+pub trait JoinTraced {
+    type Joined: Iterator;
+    fn join_traced(self) -> Traced<Self::Joined>;
+}
+
+macro_rules! trace_streams {
+    ($n:expr; $($s:ident: $S:ident),+) => {
+        impl<$($S),+> TraceStreams for ($($S,)+) {
+            type Traced = ($(Traced<$S>,)+);
+            const STREAMS: usize = $n;
+            fn trace(self, origins: Vec<Origin>) -> Self::Traced {
+                let ($($s,)+) = self;
+                let mut origins = origins.into_iter();
+                ($(Traced { iter: $s, origins: vec![origins.next().unwrap()] },)+)
+            }
+        }
+
+        impl<$($S),+> JoinTraced for ($(Traced<$S>,)+)
+        where ($($S,)+): JoinStreams {
+            type Joined = <($($S,)+) as JoinStreams>::Joined;
+            fn join_traced(self) -> Traced<Self::Joined> {
+                let ($($s,)+) = self;
+                let origins = [$($s.origins),+].concat();
+                Traced { iter: ($($s.iter,)+).join_streams(), origins }
+            }
+        }
+    };
+}
+
+trace_streams!(2; s1: S1, s2: S2);
+trace_streams!(3; s1: S1, s2: S2, s3: S3);
+trace_streams!(4; s1: S1, s2: S2, s3: S3, s4: S4);
+trace_streams!(5; s1: S1, s2: S2, s3: S3, s4: S4, s5: S5);
+trace_streams!(6; s1: S1, s2: S2, s3: S3, s4: S4, s5: S5, s6: S6);
+trace_streams!(7; s1: S1, s2: S2, s3: S3, s4: S4, s5: S5, s6: S6, s7: S7);
+trace_streams!(8; s1: S1, s2: S2, s3: S3, s4: S4, s5: S5, s6: S6, s7: S7, s8: S8);
+
+/// The short names of the element types of a tuple type with `n` elements, or
+/// of the type itself if `n` is 1
+fn tuple_types(name: &str, n: usize) -> Vec<String> {
+    let inner = match name.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+        Some(inner) if n > 1 => inner,
+        _ => return vec![short_type_name(name)],
+    };
+    let mut types = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in inner.char_indices() {
+        match c {
+            '<' | '(' | '[' => depth += 1,
+            '>' | ')' | ']' => depth -= 1,
+            ',' if depth == 0 => {
+                types.push(short_type_name(inner[start..i].trim()));
+                start = i + 1;
+            },
+            _ => {},
+        }
+    }
+    types.push(short_type_name(inner[start..].trim()));
+    types
+}
+
+/// Strips the module paths from a type name, so `num_complex::Complex<f32>` becomes `Complex<f32>`
+fn short_type_name(name: &str) -> String {
+    let mut short = String::new();
+    let mut word = String::new();
+    for c in name.chars() {
+        if c.is_alphanumeric() || c == '_' || c == ':' {
+            word.push(c);
+        } else {
+            short.push_str(word.rsplit("::").next().unwrap());
+            word.clear();
+            short.push(c);
+        }
+    }
+    short.push_str(word.rsplit("::").next().unwrap());
+    short
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
@@ -0,0 +1,83 @@
+//! The errors that constructing or running blocks can produce.
+
+use std::error;
+use std::fmt;
+use std::io;
+
+use crate::buffers::BufferError;
+
+/// An error from anywhere in the crate
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file or other stream failed
+    Io(io::Error),
+    /// A block or function was given a parameter it can't work with
+    InvalidParameter(String),
+    /// A buffer filled up because one of the streams reading from it fell too
+    /// far behind, after holding this many elements
+    BufferOverrun(usize),
+    /// A hardware device failed or couldn't be found
+    Device(String),
+    /// A block panicked on a worker thread, with this message, which ended
+    /// its stream early
+    Panicked(String),
+    /// A flowgraph file isn't valid TOML or JSON, or isn't laid out like a
+    /// flowgraph
+    Parse(String),
+    /// No block type with this name has been registered
+    UnknownBlockType { block: String, kind: String },
+    /// A flowgraph's connections don't make sense, e.g. an input that isn't
+    /// connected
+    Connection(String),
+    /// A block's inputs have the wrong number or type of items
+    Type { block: String, reason: String },
+}
+
+/// A `Result` with `rustradio::Error` as its error type
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    /// An `InvalidParameter` error with the given reason
+    pub fn invalid_parameter(reason: impl Into<String>) -> Error {
+        Error::InvalidParameter(reason.into())
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+            Error::InvalidParameter(reason) => write!(f, "invalid parameter: {}", reason),
+            Error::BufferOverrun(len) => write!(f, "buffer overrun after {} elements", len),
+            Error::Device(reason) => write!(f, "device error: {}", reason),
+            Error::Panicked(message) => write!(f, "a block panicked: {}", message),
+            Error::Parse(reason) => write!(f, "couldn't parse flowgraph: {}", reason),
+            Error::UnknownBlockType { block, kind } => write!(f, "block {} has unknown type {}", block, kind),
+            Error::Connection(reason) => write!(f, "bad connection: {}", reason),
+            Error::Type { block, reason } => write!(f, "block {}: {}", block, reason),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<BufferError> for Error {
+    fn from(e: BufferError) -> Error {
+        match e {
+            BufferError::Overrun(len) => Error::BufferOverrun(len),
+        }
+    }
+}
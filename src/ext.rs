@@ -0,0 +1,106 @@
+//! An extension trait that makes every block available as an iterator adapter.
+//!
+//! Each method is equivalent to calling `process` on the corresponding block,
+//! so `source.fir(&taps)` is the same as `FilterFIR{ taps: &taps }.process(source)`.
+//! Unlike `connect`, no buffer is put between stages unless `buffered` is used.
+//!
+//! # Example
+//! ```
+//! use rustradio::ext::RadioIteratorExt;
+//!
+//! let taps = vec![1i64, 2, 3];
+//! let filtered: Vec<i64> = (0i64..).fir(&taps).stride(2).take(3).collect();
+//! assert_eq!(filtered, vec![0, 4, 16]);
+//! ```
+
+use std::iter::Map;
+
+use num::complex::Complex;
+
+use crate::blocks::RadioBlock;
+use crate::blocks::stream::*;
+use crate::blocks::filter::*;
+use crate::blocks::modem::*;
+use crate::buffers::{buffer_fixed, FixedBuffer1};
+use crate::tags::Tagged;
+use crate::rate::Rated;
+use crate::sample::{Sample, FloatSample, MulAcc};
+
+pub trait RadioIteratorExt: Iterator + Sized {
+    /// Applies an FIR filter, see `FilterFIR`
+    fn fir<B>(self, taps: &[B]) -> FilterFIRiter<Self::Item, B, Self>
+    where Self::Item: MulAcc<B>, B: Sample {
+        FilterFIR{ taps }.process(self)
+    }
+
+    /// Resamples by `up / down`, see `RationalResampler`
+    fn resample<B>(self, up: usize, down: usize, taps: &[B]) -> RationalResamplerIter<Self::Item, B, Self>
+    where Self::Item: MulAcc<B>, B: Sample {
+        RationalResampler{ up, down, taps }.process(self)
+    }
+
+    /// Frequency modulates a stream of phase increments, see `FreqMod`
+    fn freq_mod<T>(self) -> FreqModIter<Self, T>
+    where T: FloatSample, Self: Iterator<Item = T> {
+        FreqMod.process(self)
+    }
+
+    /// Calculates the phase difference between successive samples, see `PhaseDiffs`
+    fn phase_diffs<T>(self) -> PhaseDiffsIter<Self, T>
+    where T: FloatSample, Self: Iterator<Item = Complex<T>> {
+        PhaseDiffs.process(self)
+    }
+
+    /// Takes every `stride`th element, see `Stride`
+    fn stride(self, stride: usize) -> StrideIter<Self> {
+        Stride{ stride }.process(self)
+    }
+
+    /// Duplicates each element into a tuple, see `Split`
+    fn split(self) -> SplitIter<Self>
+    where Self::Item: Clone {
+        Split.process(self)
+    }
+
+    /// Flattens a stream of pairs into one stream, see `Interleave`
+    fn interleave<A>(self) -> InterleaveIter<A, Self>
+    where A: Clone, Self: Iterator<Item = (A, A)> {
+        Interleave.process(self)
+    }
+
+    /// Pairs up alternating elements, see `DeInterleave`
+    fn deinterleave(self) -> DeInterleaveIter<Self> {
+        DeInterleave.process(self)
+    }
+
+    /// Multiplies the elements of a stream of pairs, see `Multiply`
+    fn multiply<A, B>(self) -> MultiplyIter<Self>
+    where A: MulAcc<B>, Self: Iterator<Item = (A, B)> {
+        Multiply.process(self)
+    }
+
+    /// Puts a fixed buffer of the given capacity after this stage, like `buffer_fixed`
+    fn buffered(self, capacity: usize) -> FixedBuffer1<Self::Item, Self> {
+        buffer_fixed(self, capacity)
+    }
+
+    /// Attaches a sample rate, in Hz, to this stream, see `Rated`
+    fn with_sample_rate(self, sample_rate: f64) -> Rated<Self> {
+        Rated::new(self, sample_rate)
+    }
+
+    /// Wraps each sample in a `Tagged`, without any tags
+    #[allow(clippy::type_complexity)]
+    fn tagged(self) -> Map<Self, fn(Self::Item) -> Tagged<Self::Item>> {
+        self.map(Tagged::new)
+    }
+
+    /// Strips the tags off a tagged stream
+    #[allow(clippy::type_complexity)]
+    fn untagged<A>(self) -> Map<Self, fn(Tagged<A>) -> A>
+    where Self: Iterator<Item = Tagged<A>> {
+        self.map(|tagged| tagged.sample)
+    }
+}
+
+impl<I: Iterator> RadioIteratorExt for I {}
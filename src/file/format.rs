@@ -0,0 +1,297 @@
+//! Reading and writing I/Q samples in the formats other tools use
+//!
+//! Unlike `file_read_stream`, which needs the file to hold exactly the
+//! in-memory layout of its items, these functions take a `SampleFormat`
+//! describing how the file stores its samples, and convert each one to or from
+//! a `Complex<f32>` on the fly. Some common formats are:
+//! - `SampleFormat::CU8`, unsigned bytes from `rtl_sdr`
+//! - `SampleFormat::CS8`, signed bytes from `hackrf_transfer`, in `.cs8` files
+//! - `SampleFormat::CS16_LE` and `CS16_BE`, 16 bit integers from many SDRs
+//! - `SampleFormat::CF32_LE`, GNU Radio's `.cfile`
+//! - `SampleFormat::CF64_LE`, double precision floats
+//!
+//! Integers are scaled so their full range is -1 to 1, with unsigned integers
+//! offset so the middle of their range is zero.
+//!
+//! # Example
+//! ```no_run
+//! use rustradio::file::format::{file_read_samples, file_write_samples, SampleFormat};
+//! use std::path::Path;
+//!
+//! // convert an rtl_sdr dump to a GNU Radio cfile
+//! let samples = file_read_samples(Path::new("capture.bin"), SampleFormat::CU8)?;
+//! file_write_samples(Path::new("capture.cfile"), SampleFormat::CF32_LE, samples)?;
+//! # Ok::<(), rustradio::Error>(())
+//! ```
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use num::complex::Complex;
+
+use crate::error::{Error, Result};
+use super::invalid_data;
+
+/// The type of each component of a sample
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Scalar {
+    F32,
+    F64,
+    I32,
+    I16,
+    I8,
+    U32,
+    U16,
+    U8,
+}
+
+impl Scalar {
+    /// The number of bytes each component takes up
+    pub fn bytes(self) -> usize {
+        match self {
+            Scalar::I8 | Scalar::U8 => 1,
+            Scalar::I16 | Scalar::U16 => 2,
+            Scalar::F32 | Scalar::I32 | Scalar::U32 => 4,
+            Scalar::F64 => 8,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Scalar::F32 => "f32",
+            Scalar::F64 => "f64",
+            Scalar::I32 => "i32",
+            Scalar::I16 => "i16",
+            Scalar::I8 => "i8",
+            Scalar::U32 => "u32",
+            Scalar::U16 => "u16",
+            Scalar::U8 => "u8",
+        }
+    }
+
+    /// The largest magnitude of an integer, which is scaled to 1
+    fn full_scale(self) -> f64 {
+        2f64.powi(self.bytes() as i32 * 8 - 1)
+    }
+
+    fn decode(self, bytes: &[u8], big_endian: bool) -> f32 {
+        let mut buf = [0u8; 8];
+        let buf = &mut buf[..self.bytes()];
+        buf.copy_from_slice(bytes);
+        if big_endian {
+            buf.reverse();
+        }
+        let scale = self.full_scale();
+        match self {
+            Scalar::F32 => f32::from_le_bytes(buf.try_into().unwrap()),
+            Scalar::F64 => f64::from_le_bytes(buf.try_into().unwrap()) as f32,
+            Scalar::I32 => (i32::from_le_bytes(buf.try_into().unwrap()) as f64 / scale) as f32,
+            Scalar::I16 => (i16::from_le_bytes(buf.try_into().unwrap()) as f64 / scale) as f32,
+            Scalar::I8 => (buf[0] as i8 as f64 / scale) as f32,
+            Scalar::U32 => ((u32::from_le_bytes(buf.try_into().unwrap()) as f64 - scale) / scale) as f32,
+            Scalar::U16 => ((u16::from_le_bytes(buf.try_into().unwrap()) as f64 - scale) / scale) as f32,
+            Scalar::U8 => ((buf[0] as f64 - scale) / scale) as f32,
+        }
+    }
+
+    /// Appends `x` to `out`, clipping it to the range the type can hold
+    fn encode(self, x: f32, out: &mut Vec<u8>, big_endian: bool) {
+        let start = out.len();
+        let scale = self.full_scale();
+        let int = || (x as f64 * scale).round().clamp(-scale, scale - 1.0);
+        match self {
+            Scalar::F32 => out.extend(x.to_le_bytes()),
+            Scalar::F64 => out.extend((x as f64).to_le_bytes()),
+            Scalar::I32 => out.extend((int() as i32).to_le_bytes()),
+            Scalar::I16 => out.extend((int() as i16).to_le_bytes()),
+            Scalar::I8 => out.extend((int() as i8).to_le_bytes()),
+            Scalar::U32 => out.extend(((int() + scale) as u32).to_le_bytes()),
+            Scalar::U16 => out.extend(((int() + scale) as u16).to_le_bytes()),
+            Scalar::U8 => out.push((int() + scale) as u8),
+        }
+        if big_endian {
+            out[start..].reverse();
+        }
+    }
+}
+
+/// How samples are stored, named as in SigMF, such as `cf32_le` or `ru8`
+///
+/// Parsing also accepts `s` for signed integers, as in `cs8` or `cs16_le`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SampleFormat {
+    /// Whether each sample is an I/Q pair, rather than a single real number
+    pub complex: bool,
+    pub scalar: Scalar,
+    /// Ignored for 8 bit types, which have no byte order
+    pub big_endian: bool,
+}
+
+impl SampleFormat {
+    /// Unsigned 8 bit I/Q, as written by `rtl_sdr`
+    pub const CU8: SampleFormat = SampleFormat::complex(Scalar::U8, false);
+    /// Signed 8 bit I/Q, as written by `hackrf_transfer`
+    pub const CS8: SampleFormat = SampleFormat::complex(Scalar::I8, false);
+    pub const CS16_LE: SampleFormat = SampleFormat::complex(Scalar::I16, false);
+    pub const CS16_BE: SampleFormat = SampleFormat::complex(Scalar::I16, true);
+    /// 32 bit float I/Q, as written by GNU Radio
+    pub const CF32_LE: SampleFormat = SampleFormat::complex(Scalar::F32, false);
+    pub const CF64_LE: SampleFormat = SampleFormat::complex(Scalar::F64, false);
+
+    /// I/Q samples whose parts are each a `scalar`
+    pub const fn complex(scalar: Scalar, big_endian: bool) -> SampleFormat {
+        SampleFormat { complex: true, scalar, big_endian }
+    }
+
+    /// The number of bytes each sample takes up
+    pub fn bytes(self) -> usize {
+        self.scalar.bytes() * if self.complex { 2 } else { 1 }
+    }
+
+    /// Converts the first `bytes()` bytes to a sample
+    ///
+    /// Real samples get an imaginary part of zero.
+    pub fn decode(self, bytes: &[u8]) -> Complex<f32> {
+        let n = self.scalar.bytes();
+        let re = self.scalar.decode(&bytes[..n], self.big_endian);
+        let im = if self.complex { self.scalar.decode(&bytes[n..2 * n], self.big_endian) } else { 0.0 };
+        Complex::new(re, im)
+    }
+
+    /// Appends a sample to `out`, clipping it if this is an integer format
+    ///
+    /// Only the real part of the sample is kept if this is a real format.
+    pub fn encode(self, sample: Complex<f32>, out: &mut Vec<u8>) {
+        self.scalar.encode(sample.re, out, self.big_endian);
+        if self.complex {
+            self.scalar.encode(sample.im, out, self.big_endian);
+        }
+    }
+}
+
+impl Default for SampleFormat {
+    /// `cf32_le`
+    fn default() -> SampleFormat {
+        SampleFormat::CF32_LE
+    }
+}
+
+impl FromStr for SampleFormat {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<SampleFormat> {
+        let unsupported = || invalid_data(format!("unsupported sample format {:?}", s));
+        let (complex, rest) = match s.split_at_checked(1) {
+            Some(("c", rest)) => (true, rest),
+            Some(("r", rest)) => (false, rest),
+            _ => return Err(unsupported()),
+        };
+        let (scalar, endian) = rest.split_once('_').map_or((rest, None), |(t, e)| (t, Some(e)));
+        let scalar = scalar.replace('s', "i");
+        let scalar = [Scalar::F32, Scalar::F64, Scalar::I32, Scalar::I16, Scalar::I8,
+                      Scalar::U32, Scalar::U16, Scalar::U8]
+            .into_iter()
+            .find(|t| t.name() == scalar)
+            .ok_or_else(unsupported)?;
+        let big_endian = match (endian, scalar.bytes()) {
+            (None, 1) => false,
+            (Some("le"), n) if n > 1 => false,
+            (Some("be"), n) if n > 1 => true,
+            _ => return Err(unsupported()),
+        };
+        Ok(SampleFormat { complex, scalar, big_endian })
+    }
+}
+
+impl fmt::Display for SampleFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", if self.complex { "c" } else { "r" }, self.scalar.name())?;
+        match (self.scalar.bytes(), self.big_endian) {
+            (1, _) => Ok(()),
+            (_, false) => write!(f, "_le"),
+            (_, true) => write!(f, "_be"),
+        }
+    }
+}
+
+/// An iterator over the samples stored in a reader
+///
+/// As with `ReaderIterator`, a read that fails ends the stream, and the error
+/// is kept for `check`.
+pub struct SampleReader<R> {
+    reader: R,
+    format: SampleFormat,
+    bytes: Vec<u8>,
+    /// The error that ended the stream, until it's checked
+    error: Option<io::Error>,
+}
+
+impl<R> SampleReader<R> {
+    /// Returns the error that ended the stream, if a read failed
+    ///
+    /// The error is only returned once.
+    pub fn check(&mut self) -> Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<R: Read> Iterator for SampleReader<R> {
+    type Item = Complex<f32>;
+    fn next(&mut self) -> Option<Complex<f32>> {
+        if self.error.is_some() {
+            return None;
+        }
+        match self.reader.read_exact(&mut self.bytes) {
+            Ok(()) => Some(self.format.decode(&self.bytes)),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => None,
+            Err(e) => {
+                self.error = Some(e);
+                None
+            },
+        }
+    }
+}
+
+/// Returns an iterator that converts the samples in `reader` from `format`
+pub fn read_samples<R: Read>(reader: R, format: SampleFormat) -> SampleReader<R> {
+    SampleReader { reader, format, bytes: vec![0u8; format.bytes()], error: None }
+}
+
+/// Returns an iterator that converts the samples in a file from `format`
+///
+/// It fails if the file can't be opened.
+pub fn file_read_samples(filename: &Path, format: SampleFormat) -> Result<SampleReader<BufReader<File>>> {
+    Ok(read_samples(BufReader::new(File::open(filename)?), format))
+}
+
+/// Converts every sample of `input` to `format` and writes it to `writer`,
+/// stopping at the first error
+///
+/// Fails without writing anything if `format` isn't complex, rather than
+/// dropping the imaginary parts.
+pub fn write_samples<I, W>(mut writer: W, format: SampleFormat, input: I) -> Result<()>
+where I: Iterator<Item = Complex<f32>>, W: Write {
+    if !format.complex {
+        return Err(Error::invalid_parameter(format!("can't write complex samples as {}", format)));
+    }
+    let mut bytes = Vec::new();
+    for sample in input {
+        bytes.clear();
+        format.encode(sample, &mut bytes);
+        writer.write_all(&bytes)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Converts the samples of `input` to `format` and writes them to a file, see
+/// `write_samples`
+pub fn file_write_samples<I>(filename: &Path, format: SampleFormat, input: I) -> Result<()>
+where I: Iterator<Item = Complex<f32>> {
+    write_samples(BufWriter::new(File::create(filename)?), format, input)
+}
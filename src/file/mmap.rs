@@ -0,0 +1,225 @@
+//! Random access to large recordings through memory maps
+//!
+//! `file_read_stream` streams a file from the start. An `MmapFile` instead
+//! maps the whole file into memory, so any part of it can be read without
+//! reading what comes before. Pages are loaded by the operating system as
+//! they're touched, so opening even a multi-gigabyte capture is immediate.
+//!
+//! An `MmapSource` is a stream of the elements in a range of the file. It can
+//! `seek` to any element of its range, be narrowed to a sub-range, and loop
+//! back to the start of its range when it reaches the end, to play the same
+//! samples over and over.
+//!
+//! # Example
+//! ```no_run
+//! use num::Complex;
+//! use rustradio::file::mmap::MmapFile;
+//! use std::path::Path;
+//! use std::time::Duration;
+//!
+//! let file = MmapFile::<Complex<f32>>::open(Path::new("capture.cf32"))?;
+//! println!("{} samples", file.len());
+//! // a tenth of a second, starting a minute in
+//! let sample_rate = 2.4e6;
+//! let mut source = file.iter();
+//! source.seek_time(Duration::from_secs(60), sample_rate);
+//! let window: Vec<Complex<f32>> = source.take(240000).collect();
+//! // the same window, played ten times
+//! let start = file.index_at(Duration::from_secs(60), sample_rate);
+//! let repeated = file.range(start..start + 240000).looping().take(2400000);
+//! assert_eq!(repeated.count(), 2400000);
+//! # Ok::<(), rustradio::Error>(())
+//! ```
+
+use std::fs::File;
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::slice;
+use std::sync::Arc;
+use std::time::Duration;
+
+use memmap2::Mmap;
+
+use crate::blocks::ChunkedIterator;
+use crate::error::Result;
+use super::StreamElement;
+
+/// A file of back-to-back elements of type `T`, mapped into memory
+///
+/// As with `file_read_stream`, the elements are in the machine's byte order,
+/// and a partial element at the end of the file is ignored. Cloning shares the
+/// same mapping.
+///
+/// The file must not be changed by anything else while it's mapped, or the
+/// elements read from it can change underneath the reader.
+pub struct MmapFile<T> {
+    map: Arc<Mmap>,
+    item: PhantomData<T>,
+}
+
+impl<T> Clone for MmapFile<T> {
+    fn clone(&self) -> MmapFile<T> {
+        MmapFile { map: self.map.clone(), item: PhantomData }
+    }
+}
+
+/// Resolves `range` to the indices it covers of `0..len`
+///
+/// Panics if the range is backwards or ends past `len`, as slicing would.
+fn resolve<R: RangeBounds<usize>>(range: R, len: usize) -> (usize, usize) {
+    let start = match range.start_bound() {
+        Bound::Included(&i) => i,
+        Bound::Excluded(&i) => i + 1,
+        Bound::Unbounded => 0,
+    };
+    let end = match range.end_bound() {
+        Bound::Included(&i) => i + 1,
+        Bound::Excluded(&i) => i,
+        Bound::Unbounded => len,
+    };
+    assert!(start <= end && end <= len, "range {}..{} is out of bounds for length {}", start, end, len);
+    (start, end)
+}
+
+impl<T: StreamElement> MmapFile<T> {
+    /// Maps the file at `filename`, failing if it can't be opened or mapped
+    pub fn open(filename: &Path) -> Result<MmapFile<T>> {
+        let file = File::open(filename)?;
+        // SAFETY: the file isn't changed while it's mapped, as documented above
+        let map = unsafe { Mmap::map(&file)? };
+        Ok(MmapFile { map: Arc::new(map), item: PhantomData })
+    }
+
+    /// All the elements in the file
+    pub fn as_slice(&self) -> &[T] {
+        let size = mem::size_of::<T>();
+        if size == 0 || self.map.is_empty() {
+            return &[];
+        }
+        // mappings start on a page boundary, which is aligned for any element
+        assert!(self.map.as_ptr().align_offset(mem::align_of::<T>()) == 0);
+        // SAFETY: in bounds and aligned, and any bytes are a valid element
+        unsafe { slice::from_raw_parts(self.map.as_ptr() as *const T, self.map.len() / size) }
+    }
+
+    /// The number of elements in the file
+    pub fn len(&self) -> usize {
+        self.as_slice().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The index of the element `time` after the first, at `sample_rate`
+    /// elements per second
+    pub fn index_at(&self, time: Duration, sample_rate: f64) -> usize {
+        (time.as_secs_f64() * sample_rate).round() as usize
+    }
+
+    /// A stream of every element in the file
+    pub fn iter(&self) -> MmapSource<T> {
+        self.range(..)
+    }
+
+    /// A stream of the elements in `range`
+    ///
+    /// Panics if the range ends past the end of the file.
+    pub fn range<R: RangeBounds<usize>>(&self, range: R) -> MmapSource<T> {
+        let (start, end) = resolve(range, self.len());
+        MmapSource { file: self.clone(), start, end, pos: start, looping: false }
+    }
+}
+
+/// A stream of the elements in a range of an `MmapFile`
+///
+/// Positions are relative to the start of the range.
+pub struct MmapSource<T> {
+    file: MmapFile<T>,
+    start: usize,
+    end: usize,
+    pos: usize,
+    looping: bool,
+}
+
+impl<T: StreamElement> MmapSource<T> {
+    /// The number of elements in the range, including any already read
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The index of the next element to be read
+    pub fn position(&self) -> usize {
+        self.pos - self.start
+    }
+
+    /// Continues the stream from element `index` of the range
+    ///
+    /// Seeking to `len()` or past it ends the stream, unless it's looping.
+    pub fn seek(&mut self, index: usize) {
+        self.pos = self.start + index.min(self.len());
+    }
+
+    /// Continues the stream from the element `time` after the start of the
+    /// range, at `sample_rate` elements per second
+    pub fn seek_time(&mut self, time: Duration, sample_rate: f64) {
+        self.seek(self.file.index_at(time, sample_rate));
+    }
+
+    /// A stream of the elements in `range`, which is relative to this range
+    ///
+    /// The new stream starts at the beginning of its range, and doesn't loop.
+    /// Panics if the range ends past the end of this one.
+    pub fn range<R: RangeBounds<usize>>(&self, range: R) -> MmapSource<T> {
+        let (start, end) = resolve(range, self.len());
+        self.file.range(self.start + start..self.start + end)
+    }
+
+    /// Makes the stream go back to the start of its range each time it reaches
+    /// the end, so it never ends unless the range is empty
+    pub fn looping(mut self) -> MmapSource<T> {
+        self.looping = true;
+        self
+    }
+
+    /// The elements left before the end of the range, going back to the start
+    /// first if the stream is looping
+    fn remaining(&mut self) -> &[T] {
+        if self.pos == self.end && self.looping {
+            self.pos = self.start;
+        }
+        &self.file.as_slice()[self.pos..self.end]
+    }
+}
+
+impl<T: StreamElement> Iterator for MmapSource<T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        let item = *self.remaining().first()?;
+        self.pos += 1;
+        Some(item)
+    }
+}
+
+impl<T: StreamElement> ChunkedIterator for MmapSource<T> {
+    fn fill(&mut self, output: &mut [T]) -> usize {
+        let mut count = 0;
+        while count < output.len() {
+            let remaining = self.remaining();
+            if remaining.is_empty() {
+                break;
+            }
+            let n = remaining.len().min(output.len() - count);
+            output[count..count + n].copy_from_slice(&remaining[..n]);
+            self.pos += n;
+            count += n;
+        }
+        count
+    }
+}
@@ -0,0 +1,253 @@
+/// I/Q samples in the raw formats other tools use
+pub mod format;
+/// WAV audio files, which other programs can play or record
+pub mod wav;
+/// SigMF recordings, which keep their metadata alongside the samples
+#[cfg(feature = "sigmf")]
+pub mod sigmf;
+/// Random access to large files, through memory maps
+#[cfg(feature = "mmap")]
+pub mod mmap;
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::mem;
+use std::path::Path;
+use std::slice;
+
+use crate::blocks::ChunkedIterator;
+use crate::error::{Error, Result};
+
+/// The error for a file that's malformed or in a format that isn't supported
+fn invalid_data(reason: impl Into<String>) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, reason.into()))
+}
+
+/// A type that's stored in files exactly as it's laid out in memory
+///
+/// Every `StreamElement` has no padding bytes, and every bit pattern is a
+/// valid value, so a slice of them can be written out as bytes and filled in
+/// from bytes. This holds for the integer and float primitives, and for
+/// `Complex` numbers and arrays of other elements, which are the only types
+/// that implement it. The trait is sealed because implementing it for a type
+/// like `bool` or a struct with padding would be unsound.
+///
+/// ```compile_fail
+/// use rustradio::file::file_write_stream;
+/// use std::path::Path;
+/// file_write_stream(Path::new("flags.bin"), [true, false].into_iter())?;
+/// # Ok::<(), rustradio::Error>(())
+/// ```
+pub trait StreamElement: Copy + Send + 'static + sealed::Sealed {}
+
+impl<T: Copy + Send + 'static + sealed::Sealed> StreamElement for T {}
+
+mod sealed {
+    use num::complex::Complex;
+
+    pub trait Sealed {}
+
+    macro_rules! sealed {
+        ($($t:ty),*) => {$( impl Sealed for $t {} )*};
+    }
+    sealed!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+    // `Complex` is `repr(C)` with two fields of the same type, so has no padding
+    impl<T: Sealed> Sealed for Complex<T> {}
+    impl<T: Sealed, const N: usize> Sealed for [T; N] {}
+}
+
+fn as_bytes<T: StreamElement>(items: &[T]) -> &[u8] {
+    // SAFETY: stream elements have no padding, so every byte is initialized
+    unsafe { slice::from_raw_parts(items.as_ptr() as *const u8, mem::size_of_val(items)) }
+}
+
+fn as_bytes_mut<T: StreamElement>(items: &mut [T]) -> &mut [u8] {
+    // SAFETY: as for `as_bytes`, and any bytes written are a valid element
+    unsafe { slice::from_raw_parts_mut(items.as_mut_ptr() as *mut u8, mem::size_of_val(items)) }
+}
+
+/// The number of elements read or written at a time, about 64 kB of them
+fn chunk_len<T>() -> usize {
+    ((1 << 16) / mem::size_of::<T>().max(1)).max(1)
+}
+
+/// The elements read from a `Read`, see `read_stream`
+///
+/// A read that fails ends the stream just as the end of the file does, so the
+/// error is kept for `check`.
+pub struct ReaderIterator<Buff: Read, T: StreamElement> {
+    buffer: Buff,
+    items: Vec<T>,
+    /// How many bytes at the start of `items` have been read, which can end
+    /// part way through an element
+    filled: usize,
+    /// The index of the next element to return
+    pos: usize,
+    /// The error that ended the stream, until it's checked
+    error: Option<io::Error>,
+}
+
+impl<Buff: Read, T: StreamElement> ReaderIterator<Buff, T> {
+    /// Returns the error that ended the stream, if a read failed
+    ///
+    /// The error is only returned once.
+    pub fn check(&mut self) -> Result<()> {
+        match self.error.take() {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// The number of elements that have been read but not returned
+    fn available(&self) -> usize {
+        self.filled.checked_div(mem::size_of::<T>()).unwrap_or(0) - self.pos
+    }
+
+    /// Reads more elements once the ones already read have all been returned,
+    /// returning whether there are any
+    fn refill(&mut self) -> bool {
+        let size = mem::size_of::<T>();
+        if size == 0 || self.error.is_some() {
+            return false;
+        }
+        let bytes = as_bytes_mut(&mut self.items);
+        // move any part of an element to the front
+        bytes.copy_within(self.pos * size..self.filled, 0);
+        self.filled -= self.pos * size;
+        self.pos = 0;
+        while self.filled < size {
+            match self.buffer.read(&mut bytes[self.filled..]) {
+                Ok(0) => return false,
+                Ok(n) => self.filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {},
+                Err(e) => {
+                    self.error = Some(e);
+                    return false;
+                },
+            }
+        }
+        true
+    }
+}
+
+impl<Buff: Read, T: StreamElement> Iterator for ReaderIterator<Buff, T> {
+    type Item = T;
+    fn next(&mut self) -> Option<T> {
+        if self.available() == 0 && !self.refill() {
+            return None;
+        }
+        self.pos += 1;
+        Some(self.items[self.pos - 1])
+    }
+}
+
+impl<Buff: Read, T: StreamElement> ChunkedIterator for ReaderIterator<Buff, T> {
+    fn fill(&mut self, output: &mut [T]) -> usize {
+        let mut count = 0;
+        while count < output.len() {
+            if self.available() == 0 && !self.refill() {
+                break;
+            }
+            let n = self.available().min(output.len() - count);
+            output[count..count + n].copy_from_slice(&self.items[self.pos..self.pos + n]);
+            self.pos += n;
+            count += n;
+        }
+        count
+    }
+}
+
+/// Returns an iterator that reads a stream of elements from a file
+///
+/// This function assumes the file is back-to-back elements of type `T`, in
+/// the machine's byte order. A partial element at the end of the file is
+/// ignored. It fails if the file can't be opened.
+///
+/// # Example
+/// ```no_run
+/// use rustradio::file::file_read_stream;
+/// use std::path::Path;
+/// // reads a stream of floats from file
+/// let filename = Path::new("somefile.bin");
+/// let mut stream = file_read_stream::<f32>(&filename)?;
+/// for item in stream {
+///     println!("got value {}", item);
+/// }
+/// # Ok::<(), rustradio::Error>(())
+/// ```
+pub fn file_read_stream<T: StreamElement>(filename: &Path) -> Result<ReaderIterator<BufReader<File>, T>> {
+    let file = File::open(filename)?;
+    let reader = BufReader::new(file);
+    Ok(read_stream(reader))
+}
+
+/// Returns an iterator that reads a stream of elements from `reader`, a chunk
+/// at a time
+pub fn read_stream<T, R>(reader: R) -> ReaderIterator<R, T>
+where T: StreamElement, R: Read {
+    // SAFETY: all zero bytes are a valid element
+    let zero: T = unsafe { mem::zeroed() };
+    ReaderIterator {
+        buffer: reader,
+        items: vec![zero; chunk_len::<T>()],
+        filled: 0,
+        pos: 0,
+        error: None,
+    }
+}
+
+/// Writes every element of `input` to `writer`, stopping at the first error
+///
+/// The elements are collected into chunks, each written with a single call.
+pub fn write_stream<T, I, W>(mut writer: W, input: I) -> Result<()>
+where T: StreamElement, I: Iterator<Item = T>, W: Write {
+    let mut chunk = Vec::with_capacity(chunk_len::<T>());
+    for item in input {
+        chunk.push(item);
+        if chunk.len() == chunk_len::<T>() {
+            writer.write_all(as_bytes(&chunk))?;
+            chunk.clear();
+        }
+    }
+    writer.write_all(as_bytes(&chunk))?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads the elements from an iterator and writes them to a file
+///
+/// This function will write all the elements in an iterator to file,
+/// back-to-back, exactly as each element is represented in memory. It fails
+/// if the file can't be created or written to.
+///
+/// # Example
+/// ```no_run
+/// use rustradio::file::file_write_stream;
+/// use std::path::Path;
+/// let source = 0u32..;
+/// file_write_stream(&Path::new("somefile.bin"), source)?;
+/// # Ok::<(), rustradio::Error>(())
+/// ```
+pub fn file_write_stream<T, I>(filename: &Path, input: I) -> Result<()>
+where T: StreamElement, I: Iterator<Item = T> {
+    let file = File::create(filename)?;
+    write_stream(BufWriter::new(file), input)
+}
+
+#[test]
+fn write_then_read() {
+    use num::complex::Complex;
+
+    let source = vec![Complex{re: 0f32, im: 3f32},
+                      Complex{re: 1f32, im: 2f32},
+                      Complex{re: 2f32, im: 1f32},
+                      Complex{re: 3f32, im: 0f32}];
+    let temp_dir = tempfile::tempdir().unwrap();
+    let temp_file = temp_dir.path().join("test_file");
+
+    file_write_stream(&temp_file, source.iter().copied()).unwrap();
+    let result: Vec<Complex<f32>> = file_read_stream(&temp_file).unwrap().collect();
+    assert_eq!(source, result);
+    assert!(file_read_stream::<f32>(&temp_dir.path().join("missing")).is_err());
+}
@@ -0,0 +1,437 @@
+//! Reading and writing SigMF recordings
+//!
+//! A SigMF recording is a pair of files: `name.sigmf-data` holds the raw
+//! samples, and `name.sigmf-meta` is JSON describing them. The metadata says
+//! how the samples are stored, so a recording is read as `Complex<f32>`
+//! samples whatever its datatype, with real samples given an imaginary part of
+//! zero. The rest of the metadata becomes tags on the stream:
+//! - the global sample rate is a `SampleRate` tag on the first sample
+//! - each capture segment's frequency and start time are `CenterFrequency` and
+//!   `Timestamp` tags on its first sample
+//! - each annotation is an `Annotation` tag on its first sample
+//!
+//! Writing works the other way around, collecting the tags of a stream into the
+//! metadata. The tagged stream from an `RTLSDR` carries the sample rate, the
+//! centre frequency and the time streaming started, so recording it needs no
+//! more than a description.
+//!
+//! # Example
+//! ```no_run
+//! use rustradio::file::sigmf::{file_read_sigmf, file_write_sigmf, Global};
+//! use std::path::Path;
+//!
+//! let recording = file_read_sigmf(Path::new("capture.sigmf-meta"))?;
+//! println!("sample rate {:?}", recording.meta().global.sample_rate);
+//! // keep the first million samples, along with their metadata
+//! let global = Global { description: Some("the start of capture".into()), ..Global::default() };
+//! file_write_sigmf(Path::new("start"), global, recording.take(1000000))?;
+//! # Ok::<(), rustradio::Error>(())
+//! ```
+
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use num::complex::Complex;
+use serde_json::{Map, Value};
+
+use crate::error::{Error, Result};
+use crate::tags::{insert_tags, Annotation, InsertTags, Tag, Tagged};
+use super::format::{read_samples, write_samples, SampleFormat, SampleReader};
+use super::invalid_data;
+
+/// The fields of a recording's `global` object
+#[derive(Clone, Debug, PartialEq)]
+pub struct Global {
+    pub datatype: SampleFormat,
+    /// In samples per second
+    pub sample_rate: Option<f64>,
+    /// The version of the SigMF specification the metadata follows
+    pub version: String,
+    pub description: Option<String>,
+    pub author: Option<String>,
+    /// The hardware the recording was made with
+    pub hw: Option<String>,
+    /// The program the recording was made with
+    pub recorder: Option<String>,
+    /// Every other field, by its name, such as `core:num_channels` or fields
+    /// from extensions
+    pub extra: Map<String, Value>,
+}
+
+impl Default for Global {
+    fn default() -> Global {
+        Global {
+            datatype: SampleFormat::default(),
+            sample_rate: None,
+            version: "1.0.0".to_string(),
+            description: None,
+            author: None,
+            hw: None,
+            recorder: None,
+            extra: Map::new(),
+        }
+    }
+}
+
+/// A segment of a recording, captured with the same settings
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Capture {
+    /// The index of the first sample of the segment
+    pub sample_start: u64,
+    /// The centre frequency, in Hz
+    pub frequency: Option<f64>,
+    /// The time the first sample was captured
+    pub datetime: Option<SystemTime>,
+}
+
+/// The contents of a `.sigmf-meta` file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Meta {
+    pub global: Global,
+    /// Sorted by `sample_start`
+    pub captures: Vec<Capture>,
+    /// Pairs of the index of the first annotated sample and the annotation,
+    /// sorted by index
+    pub annotations: Vec<(u64, Annotation)>,
+}
+
+fn take_string(object: &mut Map<String, Value>, key: &str) -> Option<String> {
+    match object.remove(key)? {
+        Value::String(s) => Some(s),
+        other => Some(other.to_string()),
+    }
+}
+
+fn take_f64(object: &mut Map<String, Value>, key: &str) -> Option<f64> {
+    object.remove(key)?.as_f64()
+}
+
+fn take_u64(object: &mut Map<String, Value>, key: &str) -> Option<u64> {
+    object.remove(key)?.as_u64()
+}
+
+/// Inserts `value` under `key`, if there is one
+fn put<T: Into<Value>>(object: &mut Map<String, Value>, key: &str, value: Option<T>) {
+    if let Some(value) = value {
+        object.insert(key.to_string(), value.into());
+    }
+}
+
+fn objects(meta: &mut Map<String, Value>, key: &str) -> Result<Vec<Map<String, Value>>> {
+    match meta.remove(key) {
+        None => Ok(Vec::new()),
+        Some(Value::Array(values)) => values.into_iter()
+            .map(|value| match value {
+                Value::Object(object) => Ok(object),
+                _ => Err(invalid_data(format!("{} must hold objects", key))),
+            })
+            .collect(),
+        Some(_) => Err(invalid_data(format!("{} must be an array", key))),
+    }
+}
+
+impl Meta {
+    /// Parses the contents of a `.sigmf-meta` file
+    pub fn from_json(text: &str) -> Result<Meta> {
+        let mut meta = match serde_json::from_str(text) {
+            Ok(Value::Object(meta)) => meta,
+            Ok(_) => return Err(invalid_data("SigMF metadata must be an object")),
+            Err(e) => return Err(invalid_data(e.to_string())),
+        };
+        let mut global = match meta.remove("global") {
+            Some(Value::Object(global)) => global,
+            _ => return Err(invalid_data("SigMF metadata has no global object")),
+        };
+        let datatype = take_string(&mut global, "core:datatype")
+            .ok_or_else(|| invalid_data("SigMF metadata has no datatype"))?
+            .parse()?;
+        let mut captures: Vec<Capture> = objects(&mut meta, "captures")?.into_iter()
+            .map(|mut capture| Capture {
+                sample_start: take_u64(&mut capture, "core:sample_start").unwrap_or(0),
+                frequency: take_f64(&mut capture, "core:frequency"),
+                datetime: take_string(&mut capture, "core:datetime").and_then(|s| parse_datetime(&s)),
+            })
+            .collect();
+        captures.sort_by_key(|capture| capture.sample_start);
+        let mut annotations: Vec<(u64, Annotation)> = objects(&mut meta, "annotations")?.into_iter()
+            .map(|mut annotation| (take_u64(&mut annotation, "core:sample_start").unwrap_or(0), Annotation {
+                sample_count: take_u64(&mut annotation, "core:sample_count"),
+                freq_lower_edge: take_f64(&mut annotation, "core:freq_lower_edge"),
+                freq_upper_edge: take_f64(&mut annotation, "core:freq_upper_edge"),
+                label: take_string(&mut annotation, "core:label"),
+                comment: take_string(&mut annotation, "core:comment"),
+            }))
+            .collect();
+        annotations.sort_by_key(|&(start, _)| start);
+        Ok(Meta {
+            global: Global {
+                datatype,
+                sample_rate: take_f64(&mut global, "core:sample_rate"),
+                version: take_string(&mut global, "core:version").unwrap_or_default(),
+                description: take_string(&mut global, "core:description"),
+                author: take_string(&mut global, "core:author"),
+                hw: take_string(&mut global, "core:hw"),
+                recorder: take_string(&mut global, "core:recorder"),
+                extra: global,
+            },
+            captures,
+            annotations,
+        })
+    }
+
+    /// The contents of a `.sigmf-meta` file describing the recording
+    pub fn to_json(&self) -> String {
+        let mut global = self.global.extra.clone();
+        put(&mut global, "core:datatype", Some(self.global.datatype.to_string()));
+        put(&mut global, "core:version", Some(self.global.version.clone()));
+        put(&mut global, "core:sample_rate", self.global.sample_rate);
+        put(&mut global, "core:description", self.global.description.clone());
+        put(&mut global, "core:author", self.global.author.clone());
+        put(&mut global, "core:hw", self.global.hw.clone());
+        put(&mut global, "core:recorder", self.global.recorder.clone());
+        let captures = self.captures.iter()
+            .map(|capture| {
+                let mut object = Map::new();
+                put(&mut object, "core:sample_start", Some(capture.sample_start));
+                put(&mut object, "core:frequency", capture.frequency);
+                put(&mut object, "core:datetime", capture.datetime.map(format_datetime));
+                Value::Object(object)
+            })
+            .collect();
+        let annotations = self.annotations.iter()
+            .map(|(start, annotation)| {
+                let mut object = Map::new();
+                put(&mut object, "core:sample_start", Some(*start));
+                put(&mut object, "core:sample_count", annotation.sample_count);
+                put(&mut object, "core:freq_lower_edge", annotation.freq_lower_edge);
+                put(&mut object, "core:freq_upper_edge", annotation.freq_upper_edge);
+                put(&mut object, "core:label", annotation.label.clone());
+                put(&mut object, "core:comment", annotation.comment.clone());
+                Value::Object(object)
+            })
+            .collect();
+        let mut meta = Map::new();
+        meta.insert("global".to_string(), Value::Object(global));
+        meta.insert("captures".to_string(), Value::Array(captures));
+        meta.insert("annotations".to_string(), Value::Array(annotations));
+        serde_json::to_string_pretty(&Value::Object(meta)).unwrap()
+    }
+
+    /// Adds the tags on sample `index` to the metadata, see `write_sigmf`
+    fn record_tags(&mut self, index: u64, tags: Vec<Tag>) -> Result<()> {
+        for tag in tags {
+            let capture = match tag {
+                Tag::SampleRate(rate) => {
+                    let current = *self.global.sample_rate.get_or_insert(rate);
+                    if current != rate {
+                        return Err(Error::invalid_parameter(format!(
+                            "the sample rate changed from {} to {} Hz at sample {}", current, rate, index)));
+                    }
+                    continue;
+                },
+                Tag::Annotation(annotation) => {
+                    self.annotations.push((index, annotation));
+                    continue;
+                },
+                Tag::CenterFrequency(_) | Tag::Timestamp(_) => {
+                    let last = self.captures.last().unwrap();
+                    if last.sample_start != index {
+                        // the frequency stays the same until it's retuned
+                        let next = Capture { sample_start: index, frequency: last.frequency, datetime: None };
+                        self.captures.push(next);
+                    }
+                    self.captures.last_mut().unwrap()
+                },
+                _ => continue,
+            };
+            match tag {
+                Tag::CenterFrequency(freq) => capture.frequency = Some(freq),
+                Tag::Timestamp(time) => capture.datetime = Some(time),
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    /// The tags the metadata puts on the stream, sorted by sample index
+    pub fn tags(&self) -> Vec<(usize, Tag)> {
+        let mut tags = Vec::new();
+        tags.extend(self.global.sample_rate.map(|rate| (0, Tag::SampleRate(rate))));
+        for capture in &self.captures {
+            let start = capture.sample_start as usize;
+            tags.extend(capture.frequency.map(|freq| (start, Tag::CenterFrequency(freq))));
+            tags.extend(capture.datetime.map(|time| (start, Tag::Timestamp(time))));
+        }
+        tags.extend(self.annotations.iter()
+            .map(|(start, annotation)| (*start as usize, Tag::Annotation(annotation.clone()))));
+        // a stable sort, so the tags on each sample stay in the order above
+        tags.sort_by_key(|&(index, _)| index);
+        tags
+    }
+}
+
+/// The number of days between 1970-01-01 and a date in the proleptic
+/// Gregorian calendar
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The inverse of `days_from_civil`
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * shifted_month + 2) / 5 + 1;
+    let month = (shifted_month + 2) % 12 + 1;
+    let year = era * 400 + year_of_era + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Formats a time as ISO 8601 in UTC, such as `2024-03-01T12:30:00.250000Z`
+fn format_datetime(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs() as i64;
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let secs = secs.rem_euclid(86400);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z", year, month, day,
+            secs / 3600, secs / 60 % 60, secs % 60, since_epoch.subsec_micros())
+}
+
+/// Parses an ISO 8601 time with a UTC offset, like the ones `format_datetime`
+/// makes
+fn parse_datetime(s: &str) -> Option<SystemTime> {
+    let (date, time) = s.split_once(['T', 't', ' '])?;
+    let (time, offset) = match time.strip_suffix(['Z', 'z']) {
+        Some(time) => (time, 0),
+        None => {
+            let (time, zone) = time.split_at(time.rfind(['+', '-'])?);
+            let (hours, minutes) = zone[1..].split_once(':')?;
+            let offset = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+            (time, if zone.starts_with('-') { -offset } else { offset })
+        },
+    };
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let date: Vec<i64> = date.split('-').map(str::parse).collect::<std::result::Result<_, _>>().ok()?;
+    let time: Vec<i64> = time.split(':').map(str::parse).collect::<std::result::Result<_, _>>().ok()?;
+    let (&[year, month, day], &[hour, minute, second]) = (&date[..], &time[..]) else {
+        return None;
+    };
+    // nanoseconds, ignoring any digits past them
+    let digits = &fraction[..fraction.len().min(9)];
+    let nanos = match digits {
+        "" => 0,
+        _ => digits.parse::<u32>().ok()? * 10u32.pow(9 - digits.len() as u32),
+    };
+    let secs = days_from_civil(year, month, day) * 86400 + hour * 3600 + minute * 60 + second - offset;
+    let since_epoch = Duration::from_secs(secs.unsigned_abs());
+    let time = if secs >= 0 { UNIX_EPOCH + since_epoch } else { UNIX_EPOCH - since_epoch };
+    Some(time + Duration::from_nanos(nanos as u64))
+}
+
+/// The tagged samples of a recording
+pub struct SigMFReader<R> {
+    meta: Meta,
+    samples: InsertTags<SampleReader<R>>,
+}
+
+impl<R> SigMFReader<R> {
+    /// The metadata of the recording
+    pub fn meta(&self) -> &Meta {
+        &self.meta
+    }
+}
+
+impl<R: Read> Iterator for SigMFReader<R> {
+    type Item = Tagged<Complex<f32>>;
+    fn next(&mut self) -> Option<Tagged<Complex<f32>>> {
+        self.samples.next()
+    }
+}
+
+/// Reads the samples of a recording from `data`, as described by `meta`
+///
+/// Fails if the recording has more than one channel.
+pub fn read_sigmf<R: Read>(meta: Meta, data: R) -> Result<SigMFReader<R>> {
+    let channels = meta.global.extra.get("core:num_channels").and_then(Value::as_u64).unwrap_or(1);
+    if channels != 1 {
+        return Err(Error::invalid_parameter(format!(
+            "recordings with {} channels aren't supported", channels)));
+    }
+    let samples = read_samples(data, meta.global.datatype);
+    let tags = meta.tags();
+    Ok(SigMFReader { meta, samples: insert_tags(samples, tags) })
+}
+
+/// The paths of the meta and data files of the recording at `path`, which
+/// can be either file or their shared name without an extension
+fn recording_paths(path: &Path) -> (PathBuf, PathBuf) {
+    let base = match path.extension().and_then(|e| e.to_str()) {
+        Some("sigmf-meta" | "sigmf-data") => path.with_extension(""),
+        _ => path.to_path_buf(),
+    };
+    (base.with_extension("sigmf-meta"), base.with_extension("sigmf-data"))
+}
+
+/// Reads the recording at `path`, see `read_sigmf`
+///
+/// `path` can be the `.sigmf-meta` file, the `.sigmf-data` file, or their
+/// shared name without an extension. Fails if either file can't be opened or
+/// the metadata can't be parsed.
+pub fn file_read_sigmf(path: &Path) -> Result<SigMFReader<BufReader<File>>> {
+    let (meta_path, data_path) = recording_paths(path);
+    let meta = Meta::from_json(&fs::read_to_string(meta_path)?)?;
+    read_sigmf(meta, BufReader::new(File::open(data_path)?))
+}
+
+/// Writes the samples of a stream to `writer`, returning their metadata
+///
+/// The samples are stored as `global.datatype`, which has to be complex.
+/// Samples outside of -1 to 1 are clipped if it's an integer type. The tags
+/// fill in the rest of the metadata:
+/// - the first `SampleRate` tag sets the global sample rate, if `global`
+///   doesn't have one, and a recording only has the one rate, so a tag with
+///   a different rate fails with `Error::InvalidParameter`
+/// - `CenterFrequency` and `Timestamp` tags start new capture segments
+/// - `Annotation` tags become annotations
+pub fn write_sigmf<I, W>(writer: W, global: Global, input: I) -> Result<Meta>
+where I: Iterator<Item = Tagged<Complex<f32>>>, W: Write {
+    let format = global.datatype;
+    let mut meta = Meta { global, captures: vec![Capture::default()], annotations: Vec::new() };
+    let mut error = None;
+    let samples = input.enumerate().map_while(|(index, Tagged { sample, tags })| {
+        match meta.record_tags(index as u64, tags) {
+            Ok(()) => Some(sample),
+            Err(e) => {
+                error = Some(e);
+                None
+            },
+        }
+    });
+    write_samples(writer, format, samples)?;
+    match error {
+        Some(e) => Err(e),
+        None => Ok(meta),
+    }
+}
+
+/// Writes a stream to a recording at `path`, see `write_sigmf`
+///
+/// `path` names the recording as in `file_read_sigmf`. The data file is
+/// written as the stream arrives, and the meta file once it ends.
+pub fn file_write_sigmf<I>(path: &Path, global: Global, input: I) -> Result<Meta>
+where I: Iterator<Item = Tagged<Complex<f32>>> {
+    let (meta_path, data_path) = recording_paths(path);
+    let meta = write_sigmf(BufWriter::new(File::create(data_path)?), global, input)?;
+    fs::write(meta_path, meta.to_json())?;
+    Ok(meta)
+}
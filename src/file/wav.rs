@@ -0,0 +1,336 @@
+//! Reading and writing WAV files
+//!
+//! Samples are read and written as floats between -1 and 1, whatever the
+//! file's encoding: 8, 16, 24 or 32 bit PCM, or 32 or 64 bit IEEE float. The
+//! type of item chosen picks the number of channels. A mono file is a stream
+//! of `A`, a stereo file is a stream of `(A, A)` pairs, the same as the output
+//! of `DeInterleave` or the input of `Interleave`, and a stereo file can also
+//! be a stream of `Complex<A>`, with the left channel as I and the right as Q.
+//!
+//! # Example
+//! ```no_run
+//! use num::Complex;
+//! use rustradio::ext::RadioIteratorExt;
+//! use rustradio::file::wav::{file_read_wav, file_write_wav, WavFormat};
+//! use std::path::Path;
+//!
+//! // an I/Q recording made by another tool
+//! let iq = file_read_wav::<Complex<f32>>(Path::new("recording.wav"))?;
+//! let sample_rate = iq.spec().sample_rate;
+//! let audio = iq.phase_diffs().map(|x| x / std::f32::consts::PI);
+//! file_write_wav(Path::new("audio.wav"), WavFormat::Pcm16, sample_rate, audio)?;
+//! # Ok::<(), rustradio::Error>(())
+//! ```
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::marker::PhantomData;
+use std::path::Path;
+
+use num::complex::Complex;
+
+use crate::error::{Error, Result};
+use super::invalid_data;
+use crate::sample::FloatSample;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// How each sample is stored in a WAV file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WavFormat {
+    /// Unsigned 8 bit integers, centred on 128
+    Pcm8,
+    Pcm16,
+    Pcm24,
+    Pcm32,
+    Float32,
+    Float64,
+}
+
+impl WavFormat {
+    fn from_header(tag: u16, bits: u16) -> Option<WavFormat> {
+        match (tag, bits) {
+            (FORMAT_PCM, 8) => Some(WavFormat::Pcm8),
+            (FORMAT_PCM, 16) => Some(WavFormat::Pcm16),
+            (FORMAT_PCM, 24) => Some(WavFormat::Pcm24),
+            (FORMAT_PCM, 32) => Some(WavFormat::Pcm32),
+            (FORMAT_FLOAT, 32) => Some(WavFormat::Float32),
+            (FORMAT_FLOAT, 64) => Some(WavFormat::Float64),
+            _ => None,
+        }
+    }
+
+    fn tag(self) -> u16 {
+        match self {
+            WavFormat::Float32 | WavFormat::Float64 => FORMAT_FLOAT,
+            _ => FORMAT_PCM,
+        }
+    }
+
+    /// The number of bytes each sample takes up
+    pub fn bytes(self) -> usize {
+        match self {
+            WavFormat::Pcm8 => 1,
+            WavFormat::Pcm16 => 2,
+            WavFormat::Pcm24 => 3,
+            WavFormat::Pcm32 | WavFormat::Float32 => 4,
+            WavFormat::Float64 => 8,
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> f64 {
+        match self {
+            WavFormat::Pcm8 => (bytes[0] as f64 - 128.0) / 128.0,
+            WavFormat::Pcm16 => i16::from_le_bytes([bytes[0], bytes[1]]) as f64 / 32768.0,
+            // shift into the top of an i32 to sign extend
+            WavFormat::Pcm24 => (i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) as f64 / 8388608.0,
+            WavFormat::Pcm32 => i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64 / 2147483648.0,
+            WavFormat::Float32 => f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            WavFormat::Float64 => f64::from_le_bytes(bytes[..8].try_into().unwrap()),
+        }
+    }
+
+    /// Appends `x` to `out`, clipping it to the range the format can hold
+    fn encode(self, x: f64, out: &mut Vec<u8>) {
+        let scale = |full_scale: f64| (x * full_scale).round().clamp(-full_scale, full_scale - 1.0);
+        match self {
+            WavFormat::Pcm8 => out.push((scale(128.0) + 128.0) as u8),
+            WavFormat::Pcm16 => out.extend((scale(32768.0) as i16).to_le_bytes()),
+            WavFormat::Pcm24 => out.extend(&(scale(8388608.0) as i32).to_le_bytes()[..3]),
+            WavFormat::Pcm32 => out.extend((scale(2147483648.0) as i32).to_le_bytes()),
+            WavFormat::Float32 => out.extend((x as f32).to_le_bytes()),
+            WavFormat::Float64 => out.extend(x.to_le_bytes()),
+        }
+    }
+}
+
+/// The layout of a WAV file's samples
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WavSpec {
+    pub format: WavFormat,
+    pub channels: u16,
+    /// In samples per second, per channel
+    pub sample_rate: u32,
+}
+
+/// An item that's stored as one sample from each channel of a WAV file
+pub trait WavFrame: Sized {
+    const CHANNELS: u16;
+    /// Makes an item from one sample per channel, each between -1 and 1
+    fn from_channels(channels: &[f64]) -> Self;
+    /// Calls `f` with the sample for each channel, in order
+    fn for_channels<F: FnMut(f64)>(self, f: F);
+}
+
+impl<A: FloatSample> WavFrame for A {
+    const CHANNELS: u16 = 1;
+    fn from_channels(channels: &[f64]) -> A {
+        A::from_f64(channels[0])
+    }
+    fn for_channels<F: FnMut(f64)>(self, mut f: F) {
+        f(self.as_f64());
+    }
+}
+
+impl<A: FloatSample> WavFrame for (A, A) {
+    const CHANNELS: u16 = 2;
+    fn from_channels(channels: &[f64]) -> (A, A) {
+        (A::from_f64(channels[0]), A::from_f64(channels[1]))
+    }
+    fn for_channels<F: FnMut(f64)>(self, mut f: F) {
+        f(self.0.as_f64());
+        f(self.1.as_f64());
+    }
+}
+
+impl<A: FloatSample> WavFrame for Complex<A> {
+    const CHANNELS: u16 = 2;
+    fn from_channels(channels: &[f64]) -> Complex<A> {
+        Complex::new(A::from_f64(channels[0]), A::from_f64(channels[1]))
+    }
+    fn for_channels<F: FnMut(f64)>(self, mut f: F) {
+        f(self.re.as_f64());
+        f(self.im.as_f64());
+    }
+}
+
+fn read_u16(bytes: &[u8]) -> u16 {
+    u16::from_le_bytes([bytes[0], bytes[1]])
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])
+}
+
+/// Reads the header up to the start of the samples, returning the spec and the
+/// number of bytes of samples
+fn read_header<R: Read>(reader: &mut R) -> Result<(WavSpec, u64)> {
+    let mut riff = [0u8; 12];
+    reader.read_exact(&mut riff)?;
+    if &riff[..4] != b"RIFF" || &riff[8..] != b"WAVE" {
+        return Err(invalid_data("not a WAV file"));
+    }
+    let mut spec = None;
+    loop {
+        let mut chunk = [0u8; 8];
+        reader.read_exact(&mut chunk)?;
+        let size = read_u32(&chunk[4..]) as u64;
+        match &chunk[..4] {
+            b"fmt " => {
+                // the largest format, WAVE_FORMAT_EXTENSIBLE, takes 40 bytes
+                if size > 64 {
+                    return Err(invalid_data(format!("fmt chunk is too long, at {} bytes", size)));
+                }
+                let mut fmt = vec![0u8; size as usize];
+                reader.read_exact(&mut fmt)?;
+                if fmt.len() < 16 {
+                    return Err(invalid_data("fmt chunk is too short"));
+                }
+                let mut tag = read_u16(&fmt);
+                if tag == FORMAT_EXTENSIBLE && fmt.len() >= 26 {
+                    // the format is the start of the sub-format GUID
+                    tag = read_u16(&fmt[24..]);
+                }
+                let bits = read_u16(&fmt[14..]);
+                let format = WavFormat::from_header(tag, bits)
+                    .ok_or_else(|| invalid_data(format!("unsupported format {} with {} bit samples", tag, bits)))?;
+                spec = Some(WavSpec { format, channels: read_u16(&fmt[2..]), sample_rate: read_u32(&fmt[4..]) });
+            },
+            b"data" => match spec {
+                Some(spec) => return Ok((spec, size)),
+                None => return Err(invalid_data("data chunk before fmt chunk")),
+            },
+            // chunks are padded to an even length
+            _ => { io::copy(&mut reader.by_ref().take(size + size % 2), &mut io::sink())?; },
+        }
+    }
+}
+
+/// The samples of a WAV file
+pub struct WavReader<R, S> {
+    reader: R,
+    spec: WavSpec,
+    remaining: u64,
+    frame: Vec<u8>,
+    channels: Vec<f64>,
+    item: PhantomData<S>,
+}
+
+impl<R: Read, S: WavFrame> WavReader<R, S> {
+    /// The layout of the file's samples
+    pub fn spec(&self) -> WavSpec {
+        self.spec
+    }
+
+    /// The number of items left in the file
+    pub fn len(&self) -> usize {
+        (self.remaining / self.frame.len() as u64) as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<R: Read, S: WavFrame> Iterator for WavReader<R, S> {
+    type Item = S;
+    fn next(&mut self) -> Option<S> {
+        if self.remaining < self.frame.len() as u64 || self.reader.read_exact(&mut self.frame).is_err() {
+            return None;
+        }
+        self.remaining -= self.frame.len() as u64;
+        let bytes = self.spec.format.bytes();
+        for (channel, sample) in self.channels.iter_mut().zip(self.frame.chunks_exact(bytes)) {
+            *channel = self.spec.format.decode(sample);
+        }
+        Some(S::from_channels(&self.channels))
+    }
+}
+
+/// Reads the samples from a WAV file
+///
+/// `S` is `A` for a mono file, or `(A, A)` or `Complex<A>` for a stereo file.
+/// Fails if the header can't be read, or if the file has the wrong number of
+/// channels.
+pub fn read_wav<S: WavFrame, R: Read>(mut reader: R) -> Result<WavReader<R, S>> {
+    let (spec, remaining) = read_header(&mut reader)?;
+    if spec.channels != S::CHANNELS {
+        return Err(Error::invalid_parameter(format!(
+            "the file has {} channels, but the items have {}", spec.channels, S::CHANNELS)));
+    }
+    Ok(WavReader {
+        reader,
+        spec,
+        remaining,
+        frame: vec![0u8; spec.format.bytes() * spec.channels as usize],
+        channels: vec![0.0; spec.channels as usize],
+        item: PhantomData,
+    })
+}
+
+/// Reads the samples from the WAV file at `filename`, see `read_wav`
+pub fn file_read_wav<S: WavFrame>(filename: &Path) -> Result<WavReader<BufReader<File>, S>> {
+    read_wav(BufReader::new(File::open(filename)?))
+}
+
+fn write_header<W: Write>(writer: &mut W, spec: WavSpec, data_len: u32) -> Result<()> {
+    let block_align = spec.channels * spec.format.bytes() as u16;
+    let byte_rate = spec.sample_rate.checked_mul(block_align as u32)
+        .ok_or_else(|| Error::invalid_parameter(format!("sample rate {} is too high for a WAV file", spec.sample_rate)))?;
+    let mut header = Vec::with_capacity(44);
+    header.extend(b"RIFF");
+    // the RIFF chunk includes the pad byte after an odd length of samples
+    header.extend(36u32.saturating_add(data_len).saturating_add(data_len % 2).to_le_bytes());
+    header.extend(b"WAVEfmt ");
+    header.extend(16u32.to_le_bytes());
+    header.extend(spec.format.tag().to_le_bytes());
+    header.extend(spec.channels.to_le_bytes());
+    header.extend(spec.sample_rate.to_le_bytes());
+    header.extend(byte_rate.to_le_bytes());
+    header.extend(block_align.to_le_bytes());
+    header.extend((spec.format.bytes() as u16 * 8).to_le_bytes());
+    header.extend(b"data");
+    header.extend(data_len.to_le_bytes());
+    writer.write_all(&header)?;
+    Ok(())
+}
+
+/// Writes a stream to a WAV file, stopping at the first error
+///
+/// The number of channels comes from the type of item, as in `read_wav`.
+/// Samples outside of -1 to 1 are clipped, unless `format` is a float. The
+/// header is written first and then filled in once the stream ends, which is
+/// why `writer` needs to be seekable.
+pub fn write_wav<S, I, W>(mut writer: W, format: WavFormat, sample_rate: u32, input: I) -> Result<()>
+where S: WavFrame, I: Iterator<Item = S>, W: Write + Seek {
+    let spec = WavSpec { format, channels: S::CHANNELS, sample_rate };
+    let start = writer.stream_position()?;
+    write_header(&mut writer, spec, 0)?;
+
+    let mut data_len = 0u64;
+    let mut bytes = Vec::new();
+    for item in input {
+        bytes.clear();
+        item.for_channels(|x| format.encode(x, &mut bytes));
+        writer.write_all(&bytes)?;
+        data_len += bytes.len() as u64;
+    }
+    if data_len % 2 == 1 {
+        writer.write_all(&[0])?;
+    }
+    let data_len = u32::try_from(data_len)
+        .map_err(|_| Error::invalid_parameter("too many samples for a WAV file"))?;
+
+    writer.seek(SeekFrom::Start(start))?;
+    write_header(&mut writer, spec, data_len)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Writes a stream to a WAV file at `filename`, see `write_wav`
+pub fn file_write_wav<S, I>(filename: &Path, format: WavFormat, sample_rate: u32, input: I) -> Result<()>
+where S: WavFrame, I: Iterator<Item = S> {
+    write_wav(BufWriter::new(File::create(filename)?), format, sample_rate, input)
+}
@@ -0,0 +1,869 @@
+//! Builds and runs flowgraphs described in TOML or JSON files.
+//!
+//! A flowgraph file lists its blocks, each with a unique `name`, a `type` that
+//! is looked up in a `Registry`, and that type's parameters. It then lists the
+//! connections between them, as pairs of `"block"` or `"block:port"`, where
+//! ports are numbered from 0 in the order of a block's inputs or outputs.
+//!
+//! ```toml
+//! connections = [["source", "filter"], ["filter", "demod"], ["demod", "sink"]]
+//!
+//! [[blocks]]
+//! name = "source"
+//! type = "file_source"
+//! path = "samples.cf32"
+//! item = "complex"
+//!
+//! [[blocks]]
+//! name = "filter"
+//! type = "fir"
+//! taps = { low_pass = { cutoff = 100e3, sample_rate = 1e6, num_taps = 31 } }
+//!
+//! [[blocks]]
+//! name = "demod"
+//! type = "phase_diffs"
+//!
+//! [[blocks]]
+//! name = "sink"
+//! type = "file_sink"
+//! path = "audio.f32"
+//! ```
+//!
+//! Filter taps are either a list of numbers, or a `low_pass` table that's
+//! passed to `low_pass_filter_taps`. Its `cutoff` is normalized, unless a
+//! `sample_rate` is given, in which case both are in Hz.
+//!
+//! A `file_source` reads its file as items of type `item`, laid out as they are
+//! in memory. It can instead be given a `format`, such as `"cu8"` for an
+//! `rtl_sdr` dump, which it converts to complex samples. A `file_sink` of
+//! complex samples takes a `format` in the same way.
+//!
+//! `FlowgraphSpec::check` looks up every block and works out the item type of
+//! every connection, so a graph that doesn't fit together is rejected before
+//! anything runs. `Flowgraph::run` then builds the blocks, runs each of them on
+//! its own thread with a `Scheduler`, and waits for every sink to finish.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use num::complex::Complex;
+use serde_json::{Map, Value};
+
+use crate::blocks::stream::{Split, Interleave, DeInterleave, Multiply, Stride};
+use crate::blocks::filter::{FilterFIR, RationalResampler, HammingWindow, NumTapsSpecifier,
+                            low_pass_filter_taps, low_pass_filter_taps_hz};
+use crate::blocks::modem::{FreqMod, PhaseDiffs, Deframer};
+use crate::blocks::hier::FmDemod;
+use crate::dot::DotGraph;
+use crate::error::{Error, Result};
+use crate::file::{read_stream, write_stream, ReaderIterator};
+use crate::file::format::{read_samples, write_samples, SampleFormat, SampleReader};
+use crate::message::{MessagePort, Subscription};
+use crate::scheduler::{panic_error, Scheduler};
+
+/// The types of item that can flow between blocks in a flowgraph file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ItemType {
+    Float,
+    Complex,
+    Byte,
+}
+
+impl ItemType {
+    fn parse(name: &str) -> Option<ItemType> {
+        match name {
+            "f32" => Some(ItemType::Float),
+            "complex" => Some(ItemType::Complex),
+            "u8" => Some(ItemType::Byte),
+            _ => None,
+        }
+    }
+}
+
+pub type Stream<T> = Box<dyn Iterator<Item = T> + Send>;
+
+/// A stream whose item type is only known at runtime
+pub enum DynStream {
+    Float(Stream<f32>),
+    Complex(Stream<Complex<f32>>),
+    Byte(Stream<u8>),
+}
+
+impl DynStream {
+    pub fn item_type(&self) -> ItemType {
+        match self {
+            DynStream::Float(_) => ItemType::Float,
+            DynStream::Complex(_) => ItemType::Complex,
+            DynStream::Byte(_) => ItemType::Byte,
+        }
+    }
+}
+
+/// Applies the same generic expression to a `DynStream` of any type
+macro_rules! map_stream {
+    ($stream:expr, $s:ident => $body:expr) => {
+        match $stream {
+            DynStream::Float($s) => DynStream::Float($body),
+            DynStream::Complex($s) => DynStream::Complex($body),
+            DynStream::Byte($s) => DynStream::Byte($body),
+        }
+    };
+}
+
+/// A block's entry in a flowgraph file
+#[derive(Clone, Debug)]
+pub struct BlockSpec {
+    pub name: String,
+    pub kind: String,
+    pub params: Map<String, Value>,
+}
+
+impl BlockSpec {
+    /// An `Error::InvalidParameter` naming the block and the parameter
+    pub fn param_error(&self, param: &str, reason: &str) -> Error {
+        Error::invalid_parameter(format!("parameter {} of block {}: {}", param, self.name, reason))
+    }
+
+    pub fn type_error(&self, reason: &str) -> Error {
+        Error::Type { block: self.name.clone(), reason: reason.to_string() }
+    }
+
+    /// Reports an `Error::InvalidParameter` from a block's constructor as an
+    /// error in the parameter `param`
+    pub fn invalid(&self, param: &str, err: Error) -> Error {
+        match err {
+            Error::InvalidParameter(reason) => self.param_error(param, &reason),
+            err => err,
+        }
+    }
+
+    fn param(&self, param: &str) -> Result<&Value> {
+        self.params.get(param).ok_or_else(|| self.param_error(param, "missing"))
+    }
+
+    pub fn usize(&self, param: &str) -> Result<usize> {
+        match self.param(param)?.as_u64() {
+            Some(x) if x > 0 => Ok(x as usize),
+            _ => Err(self.param_error(param, "expected a positive integer")),
+        }
+    }
+
+    pub fn str(&self, param: &str) -> Result<&str> {
+        self.param(param)?.as_str().ok_or_else(|| self.param_error(param, "expected a string"))
+    }
+
+    pub fn item_type(&self, param: &str) -> Result<ItemType> {
+        ItemType::parse(self.str(param)?)
+            .ok_or_else(|| self.param_error(param, "expected one of f32, complex or u8"))
+    }
+
+    /// How samples are stored in a file, given as a name like `"cu8"` or `"cs16_le"`
+    pub fn sample_format(&self, param: &str) -> Result<SampleFormat> {
+        self.str(param)?.parse().map_err(|_| self.param_error(param, "expected a sample format like cu8 or cf32_le"))
+    }
+
+    /// A list of bits, given either as a list of 0s and 1s or as a string like `"0110"`
+    pub fn bits(&self, param: &str) -> Result<Vec<u8>> {
+        let error = || self.param_error(param, "expected a list or string of 0s and 1s");
+        match self.param(param)? {
+            Value::String(s) => s.chars().map(|c| c.to_digit(2).map(|b| b as u8).ok_or_else(error)).collect(),
+            Value::Array(bits) => bits.iter().map(|b| match b.as_u64() {
+                Some(b) if b < 2 => Ok(b as u8),
+                _ => Err(error()),
+            }).collect(),
+            _ => Err(error()),
+        }
+    }
+
+    /// Filter taps, given either as a list or as a `low_pass` design
+    pub fn taps(&self, param: &str) -> Result<Vec<f32>> {
+        match self.param(param)? {
+            Value::Array(taps) => taps.iter()
+                .map(|t| t.as_f64().map(|t| t as f32).ok_or_else(|| self.param_error(param, "expected numbers")))
+                .collect(),
+            Value::Object(design) => match design.get("low_pass").and_then(|d| d.as_object()) {
+                Some(low_pass) if design.len() == 1 => self.low_pass_taps(param, low_pass),
+                _ => Err(self.param_error(param, "expected a list of taps or a low_pass table")),
+            },
+            _ => Err(self.param_error(param, "expected a list of taps or a low_pass table")),
+        }
+    }
+
+    fn low_pass_taps(&self, param: &str, design: &Map<String, Value>) -> Result<Vec<f32>> {
+        const KEYS: [&str; 4] = ["cutoff", "num_taps", "window", "sample_rate"];
+        if let Some(key) = design.keys().find(|k| !KEYS.contains(&k.as_str())) {
+            return Err(self.param_error(param, &format!("low_pass doesn't take {}, only {:?}", key, KEYS)));
+        }
+        let number = |key: &str| design.get(key).and_then(|v| v.as_f64());
+        let cutoff = number("cutoff").ok_or_else(|| self.param_error(param, "low_pass needs a cutoff"))? as f32;
+        let num_taps = match design.get("num_taps").and_then(|v| v.as_u64()) {
+            Some(n) => NumTapsSpecifier::NumTaps(n as usize),
+            None => return Err(self.param_error(param, "low_pass needs num_taps")),
+        };
+        match design.get("window").map(|w| w.as_str()) {
+            None | Some(Some("hamming")) => (),
+            _ => return Err(self.param_error(param, "the only window is hamming")),
+        }
+        match number("sample_rate") {
+            Some(fs) => low_pass_filter_taps_hz(HammingWindow, cutoff, fs as f32, num_taps),
+            None => low_pass_filter_taps(HammingWindow, cutoff, num_taps),
+        }.map_err(|e| self.invalid(param, e))
+    }
+}
+
+/// A type-erased sink, which runs until its input runs out
+pub type Sink = Box<dyn FnOnce() -> Result<()> + Send>;
+
+/// What a block turns into when it's built
+pub enum Built {
+    /// The block's output streams, in port order
+    Streams(Vec<DynStream>),
+    /// The block has no outputs, and has to be driven
+    Sink(Sink),
+}
+
+/// What a block can use while it's being built
+pub struct BuildContext<'a> {
+    pub scheduler: Scheduler,
+    ports: &'a mut HashMap<String, MessagePort<Vec<u8>>>,
+    /// The first error that ended a source's stream early
+    source_error: Arc<Mutex<Option<Error>>>,
+}
+
+impl<'a> BuildContext<'a> {
+    /// The port a block publishes its packets to
+    pub fn packet_port(&mut self, block: &str) -> MessagePort<Vec<u8>> {
+        self.ports.entry(block.to_string()).or_default().clone()
+    }
+
+    /// Wraps a source's stream so that the error `check` finds once it ends
+    /// is returned by `Flowgraph::run`
+    fn checked<I>(&self, iter: I, check: fn(&mut I) -> Result<()>) -> CheckedSource<I> {
+        CheckedSource { iter, check, error: self.source_error.clone() }
+    }
+}
+
+/// A source's stream, which ends the same way whether it finished or failed
+struct CheckedSource<I> {
+    iter: I,
+    check: fn(&mut I) -> Result<()>,
+    error: Arc<Mutex<Option<Error>>>,
+}
+
+impl<I: Iterator> Iterator for CheckedSource<I> {
+    type Item = I::Item;
+    fn next(&mut self) -> Option<I::Item> {
+        let item = self.iter.next();
+        if item.is_none() {
+            if let Err(e) = (self.check)(&mut self.iter) {
+                self.error.lock().unwrap().get_or_insert(e);
+            }
+        }
+        item
+    }
+}
+
+/// How to type check and build one type of block
+#[derive(Clone, Copy)]
+pub struct BlockFactory {
+    /// The names of the parameters the block takes, so that any others, such
+    /// as a misspelled one, are rejected rather than ignored
+    pub params: &'static [&'static str],
+    /// Works out a block's output types from its input types
+    pub output_types: fn(&BlockSpec, &[ItemType]) -> Result<Vec<ItemType>>,
+    /// Builds a block from its input streams, which have already been checked
+    pub build: fn(&BlockSpec, Vec<DynStream>, &mut BuildContext) -> Result<Built>,
+}
+
+/// Maps the `type` names in flowgraph files to the blocks they build
+pub struct Registry {
+    factories: HashMap<String, BlockFactory>,
+}
+
+impl Registry {
+    /// A registry without any blocks
+    pub fn empty() -> Registry {
+        Registry { factories: HashMap::new() }
+    }
+
+    /// A registry with every block in `blocks`, plus file sources and sinks
+    pub fn new() -> Registry {
+        let mut registry = Registry::empty();
+        registry.register("split", BlockFactory { params: &[], output_types: split_types, build: build_split });
+        registry.register("deinterleave", BlockFactory { params: &[], output_types: split_types, build: build_deinterleave });
+        registry.register("interleave", BlockFactory { params: &[], output_types: interleave_types, build: build_interleave });
+        registry.register("multiply", BlockFactory { params: &[], output_types: multiply_types, build: build_multiply });
+        registry.register("stride", BlockFactory { params: &["stride"], output_types: stride_types, build: build_stride });
+        registry.register("fir", BlockFactory { params: &["taps"], output_types: filter_types, build: build_fir });
+        registry.register("rational_resampler", BlockFactory {
+            params: &["up", "down", "taps"], output_types: filter_types, build: build_resampler,
+        });
+        registry.register("freq_mod", BlockFactory { params: &[], output_types: freq_mod_types, build: build_freq_mod });
+        registry.register("phase_diffs", BlockFactory { params: &[], output_types: phase_diffs_types, build: build_phase_diffs });
+        registry.register("fm_demod", BlockFactory {
+            params: &["channel_taps", "up", "down", "resampler_taps"], output_types: fm_demod_types, build: build_fm_demod,
+        });
+        registry.register("deframer", BlockFactory {
+            params: &["access_code", "payload_len"], output_types: deframer_types, build: build_deframer,
+        });
+        registry.register("file_source", BlockFactory {
+            params: &["path", "item", "format"], output_types: file_source_types, build: build_file_source,
+        });
+        registry.register("file_sink", BlockFactory {
+            params: &["path", "format"], output_types: file_sink_types, build: build_file_sink,
+        });
+        registry
+    }
+
+    /// Adds a block type, replacing any existing one with the same name
+    pub fn register(&mut self, kind: &str, factory: BlockFactory) {
+        self.factories.insert(kind.to_string(), factory);
+    }
+
+    fn get(&self, block: &BlockSpec) -> Result<BlockFactory> {
+        self.factories.get(&block.kind).copied().ok_or_else(|| Error::UnknownBlockType {
+            block: block.name.clone(),
+            kind: block.kind.clone(),
+        })
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Registry {
+        Registry::new()
+    }
+}
+
+/// One end of a connection
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Port {
+    pub block: String,
+    pub port: usize,
+}
+
+impl Port {
+    fn parse(s: &str) -> Result<Port> {
+        match s.split_once(':') {
+            None => Ok(Port { block: s.to_string(), port: 0 }),
+            Some((block, port)) => match port.parse() {
+                Ok(port) => Ok(Port { block: block.to_string(), port }),
+                Err(_) => Err(Error::Parse(format!("bad port {}", s))),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.block, self.port)
+    }
+}
+
+/// A parsed flowgraph file, which hasn't been checked yet
+#[derive(Clone, Debug)]
+pub struct FlowgraphSpec {
+    pub blocks: Vec<BlockSpec>,
+    /// Pairs of an output port and the input port it feeds
+    pub connections: Vec<(Port, Port)>,
+}
+
+impl FlowgraphSpec {
+    /// Reads a flowgraph file, which is parsed as JSON if its extension is
+    /// `.json` and as TOML otherwise
+    pub fn load(path: &Path) -> Result<FlowgraphSpec> {
+        let text = fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => FlowgraphSpec::from_json(&text),
+            _ => FlowgraphSpec::from_toml(&text),
+        }
+    }
+
+    pub fn from_toml(text: &str) -> Result<FlowgraphSpec> {
+        let value: toml::Value = toml::from_str(text).map_err(|e| Error::Parse(e.to_string()))?;
+        let value = serde_json::to_value(value).map_err(|e| Error::Parse(e.to_string()))?;
+        FlowgraphSpec::from_value(value)
+    }
+
+    pub fn from_json(text: &str) -> Result<FlowgraphSpec> {
+        let value = serde_json::from_str(text).map_err(|e| Error::Parse(e.to_string()))?;
+        FlowgraphSpec::from_value(value)
+    }
+
+    fn from_value(value: Value) -> Result<FlowgraphSpec> {
+        let parse_error = |reason: &str| Error::Parse(reason.to_string());
+        let mut blocks = Vec::new();
+        let block_values = value.get("blocks").and_then(|b| b.as_array())
+            .ok_or_else(|| parse_error("expected a list of blocks"))?;
+        for block in block_values {
+            let mut params = block.as_object().cloned().ok_or_else(|| parse_error("expected each block to be a table"))?;
+            let name = match params.remove("name") {
+                Some(Value::String(name)) => name,
+                _ => return Err(parse_error("every block needs a name")),
+            };
+            let kind = match params.remove("type") {
+                Some(Value::String(kind)) => kind,
+                _ => return Err(Error::Parse(format!("block {} needs a type", name))),
+            };
+            blocks.push(BlockSpec { name, kind, params });
+        }
+
+        let mut connections = Vec::new();
+        let connection_values = match value.get("connections") {
+            Some(c) => c.as_array().ok_or_else(|| parse_error("expected a list of connections"))?.clone(),
+            None => Vec::new(),
+        };
+        for connection in connection_values {
+            match connection.as_array().map(|c| c.as_slice()) {
+                Some([Value::String(from), Value::String(to)]) =>
+                    connections.push((Port::parse(from)?, Port::parse(to)?)),
+                _ => return Err(parse_error("expected each connection to be a pair of ports")),
+            }
+        }
+
+        Ok(FlowgraphSpec { blocks, connections })
+    }
+
+    /// Checks that every block exists and every connection fits
+    pub fn check(self, registry: &Registry) -> Result<Flowgraph<'_>> {
+        let mut indices = HashMap::new();
+        for (i, block) in self.blocks.iter().enumerate() {
+            let factory = registry.get(block)?;
+            if let Some(param) = block.params.keys().find(|p| !factory.params.contains(&p.as_str())) {
+                return Err(block.param_error(param, &format!("{} doesn't take it, only {:?}", block.kind, factory.params)));
+            }
+            if indices.insert(block.name.clone(), i).is_some() {
+                return Err(Error::Connection(format!("there are two blocks named {}", block.name)));
+            }
+        }
+
+        // Where each block's inputs come from, in port order
+        let mut sources: Vec<Vec<Option<Port>>> = vec![Vec::new(); self.blocks.len()];
+        let mut used: HashMap<Port, Port> = HashMap::new();
+        for (from, to) in self.connections.iter() {
+            for end in [from, to] {
+                if !indices.contains_key(&end.block) {
+                    return Err(Error::Connection(format!("there's no block named {}", end.block)));
+                }
+            }
+            if let Some(other) = used.insert(from.clone(), to.clone()) {
+                return Err(Error::Connection(
+                    format!("{} feeds both {} and {}, use a split", from, other, to)));
+            }
+            let inputs = &mut sources[indices[&to.block]];
+            if inputs.len() <= to.port {
+                inputs.resize(to.port + 1, None);
+            }
+            if inputs[to.port].replace(from.clone()).is_some() {
+                return Err(Error::Connection(format!("{} has more than one input", to)));
+            }
+        }
+
+        // Work out the types in dependency order, which also finds any cycles
+        let mut types: HashMap<Port, ItemType> = HashMap::new();
+        let mut order = Vec::with_capacity(self.blocks.len());
+        while order.len() < self.blocks.len() {
+            let ready = (0..self.blocks.len()).find(|&i| {
+                !order.contains(&i) && sources[i].iter().all(|s| s.as_ref().is_some_and(|s| types.contains_key(s)))
+            });
+            let i = match ready {
+                Some(i) => i,
+                None => {
+                    let (i, _) = self.blocks.iter().enumerate().find(|&(i, _)| !order.contains(&i)).unwrap();
+                    let reason = match sources[i].iter().position(|s| s.is_none()) {
+                        Some(port) => format!("input {}:{} isn't connected", self.blocks[i].name, port),
+                        None => format!("{} is part of a cycle", self.blocks[i].name),
+                    };
+                    return Err(Error::Connection(reason));
+                },
+            };
+            let block = &self.blocks[i];
+            let input_types: Vec<ItemType> = sources[i].iter().map(|s| types[s.as_ref().unwrap()]).collect();
+            let output_types = (registry.get(block)?.output_types)(block, &input_types)?;
+            for (port, &t) in output_types.iter().enumerate() {
+                let output = Port { block: block.name.clone(), port };
+                if !used.contains_key(&output) {
+                    return Err(Error::Connection(format!("output {} isn't connected", output)));
+                }
+                types.insert(output, t);
+            }
+            order.push(i);
+        }
+        for from in used.keys() {
+            if !types.contains_key(from) {
+                return Err(Error::Connection(format!("{} isn't an output", from)));
+            }
+        }
+
+        Ok(Flowgraph { spec: self, registry, order, sources, types, ports: HashMap::new() })
+    }
+}
+
+/// A flowgraph that has been checked, and is ready to run
+pub struct Flowgraph<'r> {
+    spec: FlowgraphSpec,
+    registry: &'r Registry,
+    order: Vec<usize>,
+    sources: Vec<Vec<Option<Port>>>,
+    types: HashMap<Port, ItemType>,
+    ports: HashMap<String, MessagePort<Vec<u8>>>,
+}
+
+impl<'r> Flowgraph<'r> {
+    /// The type of the items coming out of an output port
+    pub fn item_type(&self, port: &Port) -> Option<ItemType> {
+        self.types.get(port).copied()
+    }
+
+    /// Receives the packets published by a block, such as a `deframer`
+    pub fn subscribe(&mut self, block: &str) -> Subscription<Vec<u8>> {
+        self.ports.entry(block.to_string()).or_default().subscribe()
+    }
+
+    /// Draws the flowgraph as a Graphviz DOT graph
+    ///
+    /// Each block is labelled with its type and parameters, with lists of taps
+    /// shown by their length, and each connection with its item type and the
+    /// capacity of the buffer `run` puts in between.
+    pub fn to_dot(&self) -> String {
+        let mut graph = DotGraph::new();
+        let nodes: HashMap<&str, _> = self.spec.blocks.iter().map(|block| {
+            let params = block.params.iter().map(|(name, value)| {
+                let value = match value {
+                    Value::Array(list) => list.len().to_string(),
+                    Value::String(s) => s.clone(),
+                    Value::Object(_) if name == "taps" => block.taps(name).map_or_else(
+                        |_| value.to_string(), |taps| taps.len().to_string()),
+                    _ => value.to_string(),
+                };
+                (name.clone(), value)
+            }).collect();
+            (block.name.as_str(), graph.node(&block.name, &block.kind, params))
+        }).collect();
+        let capacity = Scheduler::new().capacity;
+        for (i, block) in self.spec.blocks.iter().enumerate() {
+            for (port, source) in self.sources[i].iter().enumerate() {
+                let source = source.as_ref().unwrap();
+                let item_type = match self.types[source] {
+                    ItemType::Float => "f32",
+                    ItemType::Complex => "complex",
+                    ItemType::Byte => "u8",
+                };
+                graph.edge((nodes[source.block.as_str()], source.port), (nodes[block.name.as_str()], port),
+                           item_type, vec![capacity]);
+            }
+        }
+        graph.to_dot()
+    }
+
+    /// Builds every block, and runs until every sink has finished
+    ///
+    /// A block that panics ends its streams early, and is reported as an
+    /// `Error::Panicked` once the sinks have finished. So is a file source
+    /// that fails to read, which is reported as an `Error::Io`.
+    pub fn run(mut self) -> Result<()> {
+        let scheduler = Scheduler::new();
+        let source_error = Arc::new(Mutex::new(None));
+        let mut context = BuildContext {
+            scheduler: scheduler.clone(),
+            ports: &mut self.ports,
+            source_error: source_error.clone(),
+        };
+        let mut streams: HashMap<Port, DynStream> = HashMap::new();
+        let mut sinks = Vec::new();
+        for &i in self.order.iter() {
+            let block = &self.spec.blocks[i];
+            let inputs = self.sources[i].iter()
+                .map(|s| streams.remove(s.as_ref().unwrap()).unwrap())
+                .collect();
+            match (self.registry.get(block)?.build)(block, inputs, &mut context)? {
+                Built::Streams(outputs) => for (port, output) in outputs.into_iter().enumerate() {
+                    streams.insert(Port { block: block.name.clone(), port }, output);
+                },
+                Built::Sink(sink) => sinks.push(sink),
+            }
+        }
+        // the blocks hold their own clones of the ports, so subscriptions end with them
+        self.ports.clear();
+
+        let workers: Vec<_> = sinks.into_iter().map(thread::spawn).collect();
+        let mut result = Ok(());
+        for worker in workers {
+            let finished = worker.join().unwrap_or_else(|payload| Err(panic_error(payload)));
+            result = result.and(finished);
+        }
+        result = result.and(scheduler.wait());
+        // a source that failed just ends its stream, so its error has to be looked for
+        let source_error = source_error.lock().unwrap().take();
+        result.and(source_error.map_or(Ok(()), Err))
+    }
+}
+
+fn expect_inputs(block: &BlockSpec, inputs: &[ItemType], n: usize) -> Result<()> {
+    if inputs.len() == n {
+        Ok(())
+    } else {
+        Err(block.type_error(&format!("expected {} inputs, got {}", n, inputs.len())))
+    }
+}
+
+fn expect_type(block: &BlockSpec, input: ItemType, allowed: &[ItemType]) -> Result<()> {
+    if allowed.contains(&input) {
+        Ok(())
+    } else {
+        Err(block.type_error(&format!("can't take {:?} items, only {:?}", input, allowed)))
+    }
+}
+
+fn unexpected(block: &BlockSpec) -> Error {
+    block.type_error("was built with inputs it wasn't checked with")
+}
+
+fn split_types(block: &BlockSpec, inputs: &[ItemType]) -> Result<Vec<ItemType>> {
+    expect_inputs(block, inputs, 1)?;
+    Ok(vec![inputs[0], inputs[0]])
+}
+
+fn build_split(_: &BlockSpec, mut inputs: Vec<DynStream>, context: &mut BuildContext) -> Result<Built> {
+    let scheduler = &context.scheduler;
+    Ok(Built::Streams(match inputs.remove(0) {
+        DynStream::Float(s) => { let (a, b) = scheduler.run_split(&Split, s); vec![DynStream::Float(Box::new(a)), DynStream::Float(Box::new(b))] },
+        DynStream::Complex(s) => { let (a, b) = scheduler.run_split(&Split, s); vec![DynStream::Complex(Box::new(a)), DynStream::Complex(Box::new(b))] },
+        DynStream::Byte(s) => { let (a, b) = scheduler.run_split(&Split, s); vec![DynStream::Byte(Box::new(a)), DynStream::Byte(Box::new(b))] },
+    }))
+}
+
+fn build_deinterleave(_: &BlockSpec, mut inputs: Vec<DynStream>, context: &mut BuildContext) -> Result<Built> {
+    let scheduler = &context.scheduler;
+    Ok(Built::Streams(match inputs.remove(0) {
+        DynStream::Float(s) => { let (a, b) = scheduler.run_split(&DeInterleave, s); vec![DynStream::Float(Box::new(a)), DynStream::Float(Box::new(b))] },
+        DynStream::Complex(s) => { let (a, b) = scheduler.run_split(&DeInterleave, s); vec![DynStream::Complex(Box::new(a)), DynStream::Complex(Box::new(b))] },
+        DynStream::Byte(s) => { let (a, b) = scheduler.run_split(&DeInterleave, s); vec![DynStream::Byte(Box::new(a)), DynStream::Byte(Box::new(b))] },
+    }))
+}
+
+fn interleave_types(block: &BlockSpec, inputs: &[ItemType]) -> Result<Vec<ItemType>> {
+    expect_inputs(block, inputs, 2)?;
+    expect_type(block, inputs[1], &[inputs[0]])?;
+    Ok(vec![inputs[0]])
+}
+
+fn build_interleave(block: &BlockSpec, inputs: Vec<DynStream>, context: &mut BuildContext) -> Result<Built> {
+    let scheduler = &context.scheduler;
+    let mut inputs = inputs.into_iter();
+    Ok(Built::Streams(vec![match (inputs.next(), inputs.next()) {
+        (Some(DynStream::Float(a)), Some(DynStream::Float(b))) => DynStream::Float(Box::new(scheduler.run(&Interleave, a.zip(b)))),
+        (Some(DynStream::Complex(a)), Some(DynStream::Complex(b))) => DynStream::Complex(Box::new(scheduler.run(&Interleave, a.zip(b)))),
+        (Some(DynStream::Byte(a)), Some(DynStream::Byte(b))) => DynStream::Byte(Box::new(scheduler.run(&Interleave, a.zip(b)))),
+        _ => return Err(unexpected(block)),
+    }]))
+}
+
+fn multiply_types(block: &BlockSpec, inputs: &[ItemType]) -> Result<Vec<ItemType>> {
+    expect_inputs(block, inputs, 2)?;
+    match (inputs[0], inputs[1]) {
+        (ItemType::Float, ItemType::Float) => Ok(vec![ItemType::Float]),
+        (ItemType::Complex, ItemType::Complex) | (ItemType::Complex, ItemType::Float) |
+        (ItemType::Float, ItemType::Complex) => Ok(vec![ItemType::Complex]),
+        _ => Err(block.type_error(&format!("can't multiply {:?} by {:?}", inputs[0], inputs[1]))),
+    }
+}
+
+fn build_multiply(block: &BlockSpec, inputs: Vec<DynStream>, context: &mut BuildContext) -> Result<Built> {
+    let scheduler = &context.scheduler;
+    let mut inputs = inputs.into_iter();
+    Ok(Built::Streams(vec![match (inputs.next(), inputs.next()) {
+        (Some(DynStream::Float(a)), Some(DynStream::Float(b))) => DynStream::Float(Box::new(scheduler.run(&Multiply, a.zip(b)))),
+        (Some(DynStream::Complex(a)), Some(DynStream::Complex(b))) => DynStream::Complex(Box::new(scheduler.run(&Multiply, a.zip(b)))),
+        (Some(DynStream::Complex(a)), Some(DynStream::Float(b))) => DynStream::Complex(Box::new(scheduler.run(&Multiply, a.zip(b)))),
+        (Some(DynStream::Float(a)), Some(DynStream::Complex(b))) => DynStream::Complex(Box::new(scheduler.run(&Multiply, a.zip(b)))),
+        _ => return Err(unexpected(block)),
+    }]))
+}
+
+fn stride(block: &BlockSpec) -> Result<Stride> {
+    Stride::new(block.usize("stride")?).map_err(|e| block.invalid("stride", e))
+}
+
+fn stride_types(block: &BlockSpec, inputs: &[ItemType]) -> Result<Vec<ItemType>> {
+    expect_inputs(block, inputs, 1)?;
+    stride(block)?;
+    Ok(vec![inputs[0]])
+}
+
+fn build_stride(block: &BlockSpec, mut inputs: Vec<DynStream>, context: &mut BuildContext) -> Result<Built> {
+    let b_stride = stride(block)?;
+    let scheduler = &context.scheduler;
+    Ok(Built::Streams(vec![map_stream!(inputs.remove(0), s => Box::new(scheduler.run(&b_stride, s)))]))
+}
+
+/// `fir` and `rational_resampler` both filter real or complex samples with real taps
+fn filter_types(block: &BlockSpec, inputs: &[ItemType]) -> Result<Vec<ItemType>> {
+    expect_inputs(block, inputs, 1)?;
+    expect_type(block, inputs[0], &[ItemType::Float, ItemType::Complex])?;
+    let taps = block.taps("taps")?;
+    if block.kind == "rational_resampler" {
+        resampler(block, &taps)?;
+    }
+    Ok(vec![inputs[0]])
+}
+
+fn build_fir(block: &BlockSpec, mut inputs: Vec<DynStream>, context: &mut BuildContext) -> Result<Built> {
+    let taps = block.taps("taps")?;
+    let b_filter = FilterFIR { taps: &taps };
+    let scheduler = &context.scheduler;
+    Ok(Built::Streams(vec![match inputs.remove(0) {
+        DynStream::Float(s) => DynStream::Float(Box::new(scheduler.run(&b_filter, s))),
+        DynStream::Complex(s) => DynStream::Complex(Box::new(scheduler.run(&b_filter, s))),
+        DynStream::Byte(_) => return Err(unexpected(block)),
+    }]))
+}
+
+fn resampler<'t>(block: &BlockSpec, taps: &'t [f32]) -> Result<RationalResampler<'t, f32>> {
+    let (up, down) = (block.usize("up")?, block.usize("down")?);
+    RationalResampler::new(up, down, taps).map_err(|e| block.invalid(if up == 0 { "up" } else { "down" }, e))
+}
+
+fn build_resampler(block: &BlockSpec, mut inputs: Vec<DynStream>, context: &mut BuildContext) -> Result<Built> {
+    let taps = block.taps("taps")?;
+    let b_resampler = resampler(block, &taps)?;
+    let scheduler = &context.scheduler;
+    Ok(Built::Streams(vec![match inputs.remove(0) {
+        DynStream::Float(s) => DynStream::Float(Box::new(scheduler.run(&b_resampler, s))),
+        DynStream::Complex(s) => DynStream::Complex(Box::new(scheduler.run(&b_resampler, s))),
+        DynStream::Byte(_) => return Err(unexpected(block)),
+    }]))
+}
+
+fn freq_mod_types(block: &BlockSpec, inputs: &[ItemType]) -> Result<Vec<ItemType>> {
+    expect_inputs(block, inputs, 1)?;
+    expect_type(block, inputs[0], &[ItemType::Float])?;
+    Ok(vec![ItemType::Complex])
+}
+
+fn build_freq_mod(block: &BlockSpec, mut inputs: Vec<DynStream>, context: &mut BuildContext) -> Result<Built> {
+    match inputs.remove(0) {
+        DynStream::Float(s) => Ok(Built::Streams(vec![DynStream::Complex(Box::new(context.scheduler.run(&FreqMod, s)))])),
+        _ => Err(unexpected(block)),
+    }
+}
+
+fn phase_diffs_types(block: &BlockSpec, inputs: &[ItemType]) -> Result<Vec<ItemType>> {
+    expect_inputs(block, inputs, 1)?;
+    expect_type(block, inputs[0], &[ItemType::Complex])?;
+    Ok(vec![ItemType::Float])
+}
+
+fn build_phase_diffs(block: &BlockSpec, mut inputs: Vec<DynStream>, context: &mut BuildContext) -> Result<Built> {
+    match inputs.remove(0) {
+        DynStream::Complex(s) => Ok(Built::Streams(vec![DynStream::Float(Box::new(context.scheduler.run(&PhaseDiffs, s)))])),
+        _ => Err(unexpected(block)),
+    }
+}
+
+fn fm_demod_types(block: &BlockSpec, inputs: &[ItemType]) -> Result<Vec<ItemType>> {
+    expect_inputs(block, inputs, 1)?;
+    expect_type(block, inputs[0], &[ItemType::Complex])?;
+    let channel_taps = block.taps("channel_taps")?;
+    let resampler_taps = block.taps("resampler_taps")?;
+    fm_demod(block, &channel_taps, &resampler_taps)?;
+    Ok(vec![ItemType::Float])
+}
+
+fn fm_demod<'t>(block: &BlockSpec, channel_taps: &'t [f32], resampler_taps: &'t [f32])
+    -> Result<FmDemod<'t>> {
+    let demod = FmDemod { channel_taps, up: block.usize("up")?, down: block.usize("down")?, resampler_taps };
+    demod.validate().map_err(|e| block.invalid(if demod.up == 0 { "up" } else { "down" }, e))?;
+    Ok(demod)
+}
+
+fn build_fm_demod(block: &BlockSpec, mut inputs: Vec<DynStream>, context: &mut BuildContext) -> Result<Built> {
+    let channel_taps = block.taps("channel_taps")?;
+    let resampler_taps = block.taps("resampler_taps")?;
+    let demod = fm_demod(block, &channel_taps, &resampler_taps)?;
+    match inputs.remove(0) {
+        DynStream::Complex(s) => Ok(Built::Streams(vec![DynStream::Float(Box::new(context.scheduler.run(&demod, s)))])),
+        _ => Err(unexpected(block)),
+    }
+}
+
+fn deframer_types(block: &BlockSpec, inputs: &[ItemType]) -> Result<Vec<ItemType>> {
+    expect_inputs(block, inputs, 1)?;
+    expect_type(block, inputs[0], &[ItemType::Byte])?;
+    deframer(block, &block.bits("access_code")?, MessagePort::new())?;
+    Ok(vec![ItemType::Byte])
+}
+
+fn deframer<'c>(block: &BlockSpec, access_code: &'c [u8], packets: MessagePort<Vec<u8>>)
+    -> Result<Deframer<'c>> {
+    Deframer::new(access_code, block.usize("payload_len")?, packets).map_err(|e| block.invalid("access_code", e))
+}
+
+fn build_deframer(block: &BlockSpec, mut inputs: Vec<DynStream>, context: &mut BuildContext) -> Result<Built> {
+    let access_code = block.bits("access_code")?;
+    let deframer = deframer(block, &access_code, context.packet_port(&block.name))?;
+    match inputs.remove(0) {
+        DynStream::Byte(s) => Ok(Built::Streams(vec![DynStream::Byte(Box::new(context.scheduler.run(&deframer, s)))])),
+        _ => Err(unexpected(block)),
+    }
+}
+
+fn file_source_types(block: &BlockSpec, inputs: &[ItemType]) -> Result<Vec<ItemType>> {
+    expect_inputs(block, inputs, 0)?;
+    block.str("path")?;
+    if block.params.contains_key("format") {
+        block.sample_format("format")?;
+        return Ok(vec![ItemType::Complex]);
+    }
+    Ok(vec![block.item_type("item")?])
+}
+
+fn build_file_source(block: &BlockSpec, _: Vec<DynStream>, context: &mut BuildContext) -> Result<Built> {
+    let reader = BufReader::new(File::open(block.str("path")?)?);
+    if block.params.contains_key("format") {
+        let samples = read_samples(reader, block.sample_format("format")?);
+        let samples = context.checked(samples, SampleReader::check);
+        return Ok(Built::Streams(vec![DynStream::Complex(Box::new(samples))]));
+    }
+    Ok(Built::Streams(vec![match block.item_type("item")? {
+        ItemType::Float => DynStream::Float(Box::new(context.checked(read_stream(reader), ReaderIterator::check))),
+        ItemType::Complex => DynStream::Complex(Box::new(context.checked(read_stream(reader), ReaderIterator::check))),
+        ItemType::Byte => DynStream::Byte(Box::new(context.checked(read_stream(reader), ReaderIterator::check))),
+    }]))
+}
+
+fn file_sink_types(block: &BlockSpec, inputs: &[ItemType]) -> Result<Vec<ItemType>> {
+    expect_inputs(block, inputs, 1)?;
+    block.str("path")?;
+    if block.params.contains_key("format") {
+        if !block.sample_format("format")?.complex {
+            return Err(block.param_error("format", "expected a complex sample format"));
+        }
+        if inputs[0] != ItemType::Complex {
+            return Err(block.type_error("a format can only be given for complex input"));
+        }
+    }
+    Ok(Vec::new())
+}
+
+fn build_file_sink(block: &BlockSpec, mut inputs: Vec<DynStream>, _: &mut BuildContext) -> Result<Built> {
+    let writer = BufWriter::new(File::create(block.str("path")?)?);
+    let format = match block.params.contains_key("format") {
+        true => Some(block.sample_format("format")?),
+        false => None,
+    };
+    let input = inputs.remove(0);
+    Ok(Built::Sink(Box::new(move || {
+        match (input, format) {
+            (DynStream::Complex(s), Some(format)) => write_samples(writer, format, s),
+            (DynStream::Float(s), _) => write_stream(writer, s),
+            (DynStream::Complex(s), None) => write_stream(writer, s),
+            (DynStream::Byte(s), _) => write_stream(writer, s),
+        }?;
+        Ok(())
+    })))
+}
@@ -1,44 +1,197 @@
-#![feature(unboxed_closures)]
-#![feature(associated_types)]
-
-#![feature(macro_rules)]
-#![feature(globs)]
-
-extern crate num;
-extern crate IteratorExtras;
-
 /// The processing blocks, broken out into submodules
 pub mod blocks;
 /// The buffers that exist between blocks
 pub mod buffers;
 /// File IO
 pub mod file;
+/// Running blocks on their own threads
+pub mod scheduler;
+/// Checking the stream rates of a flowgraph
+pub mod rate;
+/// Chaining blocks as iterator adapters
+pub mod ext;
+/// The numeric types blocks work with
+pub mod sample;
+/// Metadata attached to samples
+pub mod tags;
+/// Changing block parameters while they run
+pub mod control;
+/// Messages passed between blocks
+pub mod message;
+/// Counting what blocks and buffers are doing
+pub mod stats;
+/// Drawing flowgraphs with Graphviz
+pub mod dot;
+/// The crate's error type
+pub mod error;
+/// Flowgraphs described in files
+#[cfg(feature = "flowgraph")]
+pub mod flowgraph;
+
+use std::iter::Zip;
+
+pub use error::{Error, Result};
+
+use blocks::RadioBlock;
+use buffers::{FixedBuffer1, FixedBuffer2First, FixedBuffer2Second};
 
-pub static DEFAULT_BUFFER_SIZE: uint = 2048;
+pub static DEFAULT_BUFFER_SIZE: usize = 2048;
 
-/// Simplifies connecting blocks with fixed buffers.
+/// Connects a block to its input stream through a fixed buffer.
 ///
-/// Right now, only blocks that operate on 1 or 2 streams is supported. More
-/// input/output streams should be easy to add.
+/// Blocks with several inputs take a single stream of tuples, which can be
+/// made from separate streams with `join`. Blocks with several outputs produce
+/// a stream of tuples, which can be broken into separate streams with `split`.
 ///
-/// TODO once Associated Items fully lands, we should be able to make this a function
-/// by having blocks have static variables for number of inputs and number of outputs
-#[macro_export]
-macro_rules! connect(
-
-    // Unsplitting 2->1
-    ($output:pat <- $block:ident ($source1:ident, $source2:ident)) => (
-        let $output = $block.process(rustradio::buffers::buffer_fixed($source1.zip($source2), rustradio::DEFAULT_BUFFER_SIZE));
-    );
-
-    // Splitting 1->2
-    (($output1:pat,$output2:pat) <- $block:ident ($source:ident)) => (
-        let ($output1, $output2) = rustradio::buffers::split_fixed($block.process($source), rustradio::DEFAULT_BUFFER_SIZE, rustradio::DEFAULT_BUFFER_SIZE);
-    );
-
-    // 1->1 block
-    ($output:pat <- $block:ident ($source:ident)) => (
-        let $output = $block.process(rustradio::buffers::buffer_fixed($source, rustradio::DEFAULT_BUFFER_SIZE));
-    );
-
-);
+/// # Example
+/// ```
+/// use rustradio::{connect, join, split};
+/// use rustradio::blocks::stream::{Split, Multiply};
+///
+/// let source = vec![0usize, 1, 2, 3].into_iter();
+/// let (s1, s2) = split(connect(&Split, source));
+/// let squared: Vec<usize> = connect(&Multiply, join((s1, s2))).collect();
+/// assert_eq!(squared, vec![0, 1, 4, 9]);
+/// ```
+pub fn connect<A, I, R>(block: &R, input: I) -> R::Iter
+where I: Iterator<Item = A>, R: RadioBlock<FixedBuffer1<A, I>, Input = A> {
+    block.process(buffers::buffer_fixed(input, DEFAULT_BUFFER_SIZE))
+}
+
+/// Combines a tuple of 2 to 8 streams into a single stream of tuples
+///
+/// The stream of tuples ends as soon as any of the streams does.
+pub fn join<S: JoinStreams>(streams: S) -> S::Joined {
+    streams.join_streams()
+}
+
+/// Splits a stream of tuples of 2 to 8 elements into a tuple of streams, each
+/// with a fixed buffer
+pub fn split<I>(input: I) -> <I::Item as SplitStreams<I>>::Streams
+where I: Iterator, I::Item: SplitStreams<I> {
+    <I::Item as SplitStreams<I>>::split_streams(input, DEFAULT_BUFFER_SIZE)
+}
+
+/// Tuples of streams that can be zipped into a single stream of tuples
+pub trait JoinStreams {
+    type Joined: Iterator;
+    fn join_streams(self) -> Self::Joined;
+}
+
+impl<I1, I2> JoinStreams for (I1, I2)
+where I1: Iterator, I2: Iterator {
+    type Joined = Zip<I1, I2>;
+    fn join_streams(self) -> Zip<I1, I2> {
+        let (s1, s2) = self;
+        s1.zip(s2)
+    }
+}
+
+macro_rules! join_streams {
+    ($zip:ident; $($s:ident: $I:ident),+) => {
+        /// The stream of tuples made by `join`
+        pub struct $zip<$($I),+> {
+            $($s: $I),+
+        }
+        impl<$($I),+> Iterator for $zip<$($I),+>
+        where $($I: Iterator),+ {
+            type Item = ($($I::Item,)+);
+            fn next(&mut self) -> Option<Self::Item> {
+                Some(($(self.$s.next()?,)+))
+            }
+        }
+        impl<$($I),+> JoinStreams for ($($I,)+)
+        where $($I: Iterator),+ {
+            type Joined = $zip<$($I),+>;
+            fn join_streams(self) -> Self::Joined {
+                let ($($s,)+) = self;
+                $zip { $($s),+ }
+            }
+        }
+    };
+}
+
+join_streams!(Zip3; s1: I1, s2: I2, s3: I3);
+join_streams!(Zip4; s1: I1, s2: I2, s3: I3, s4: I4);
+join_streams!(Zip5; s1: I1, s2: I2, s3: I3, s4: I4, s5: I5);
+join_streams!(Zip6; s1: I1, s2: I2, s3: I3, s4: I4, s5: I5, s6: I6);
+join_streams!(Zip7; s1: I1, s2: I2, s3: I3, s4: I4, s5: I5, s6: I6, s7: I7);
+join_streams!(Zip8; s1: I1, s2: I2, s3: I3, s4: I4, s5: I5, s6: I6, s7: I7, s8: I8);
+
+/// Tuple types whose streams, of type `I`, can be split into one stream per element
+pub trait SplitStreams<I> {
+    type Streams;
+    fn split_streams(input: I, capacity: usize) -> Self::Streams;
+}
+
+impl<A, B, I> SplitStreams<I> for (A, B)
+where I: Iterator<Item = (A, B)> {
+    type Streams = (FixedBuffer2First<A, B, I>, FixedBuffer2Second<A, B, I>);
+    fn split_streams(input: I, capacity: usize) -> Self::Streams {
+        buffers::split_fixed(input, capacity, capacity)
+    }
+}
+
+/// Tuples that can be broken into their first element and a tuple of the rest
+pub trait HeadTail {
+    type Head;
+    type Tail;
+    fn head_tail(self) -> (Self::Head, Self::Tail);
+}
+
+/// Tuples that another element can be put in front of
+pub trait Prepend<X> {
+    type Output;
+    fn prepend(self, x: X) -> Self::Output;
+}
+
+/// Turns `(a, b, c, ...)` into `(a, (b, c, ...))` so it can be split in two
+pub struct Nest<I> {
+    iter: I,
+}
+impl<I> Iterator for Nest<I>
+where I: Iterator, I::Item: HeadTail {
+    type Item = (<I::Item as HeadTail>::Head, <I::Item as HeadTail>::Tail);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(HeadTail::head_tail)
+    }
+}
+
+/// Splits off the first stream, then splits the rest of the tuple the same way
+macro_rules! split_streams {
+    ($A:ident $a:ident, $($T:ident $t:ident),+) => {
+        impl<$A, $($T),+> HeadTail for ($A, $($T,)+) {
+            type Head = $A;
+            type Tail = ($($T,)+);
+            fn head_tail(self) -> ($A, ($($T,)+)) {
+                let ($a, $($t,)+) = self;
+                ($a, ($($t,)+))
+            }
+        }
+
+        impl<X, $($T),+> Prepend<X> for ($($T,)+) {
+            type Output = (X, $($T,)+);
+            fn prepend(self, x: X) -> Self::Output {
+                let ($($t,)+) = self;
+                (x, $($t,)+)
+            }
+        }
+
+        impl<$A, $($T,)+ I> SplitStreams<I> for ($A, $($T,)+)
+        where I: Iterator<Item = ($A, $($T,)+)> {
+            type Streams = <<($($T,)+) as SplitStreams<FixedBuffer2Second<$A, ($($T,)+), Nest<I>>>>::Streams
+                as Prepend<FixedBuffer2First<$A, ($($T,)+), Nest<I>>>>::Output;
+            fn split_streams(input: I, capacity: usize) -> Self::Streams {
+                let (first, rest) = buffers::split_fixed(Nest { iter: input }, capacity, capacity);
+                <($($T,)+) as SplitStreams<_>>::split_streams(rest, capacity).prepend(first)
+            }
+        }
+    };
+}
+
+split_streams!(A a, B b, C c);
+split_streams!(A a, B b, C c, D d);
+split_streams!(A a, B b, C c, D d, E e);
+split_streams!(A a, B b, C c, D d, E e, F f);
+split_streams!(A a, B b, C c, D d, E e, F f, G g);
+split_streams!(A a, B b, C c, D d, E e, F f, G g, H h);
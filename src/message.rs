@@ -0,0 +1,86 @@
+//! Discrete messages, passed between blocks alongside their sample streams.
+//!
+//! A `MessagePort` is a publish/subscribe channel for one type of message,
+//! such as decoded packets or retune commands. Blocks that emit messages take
+//! a port as a parameter and `publish` to it. Anything interested subscribes
+//! to the port and gets its own `Subscription`, which sees every message
+//! published after it subscribed.
+//!
+//! A subscription works on either side of a thread boundary. Pull-based
+//! iterators call `try_recv` between samples, which never blocks. A worker
+//! thread can instead iterate over the subscription, which blocks until a
+//! message arrives and ends once every clone of the port has been dropped.
+//!
+//! # Example
+//! ```
+//! use rustradio::message::MessagePort;
+//!
+//! let port = MessagePort::new();
+//! let subscription = port.subscribe();
+//! port.publish(vec![1u8, 2, 3]);
+//! assert_eq!(subscription.try_recv(), Some(vec![1u8, 2, 3]));
+//! assert_eq!(subscription.try_recv(), None);
+//! ```
+
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+/// A channel that delivers each published message to every subscriber
+///
+/// Clones of a port share the same subscribers.
+pub struct MessagePort<T> {
+    subscribers: Arc<Mutex<Vec<Sender<T>>>>,
+}
+
+impl<T: Clone> MessagePort<T> {
+    pub fn new() -> MessagePort<T> {
+        MessagePort { subscribers: Arc::new(Mutex::new(Vec::new())) }
+    }
+
+    /// Sends a message to every current subscriber
+    pub fn publish(&self, msg: T) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        // drop the subscriptions that have gone away
+        subscribers.retain(|s| s.send(msg.clone()).is_ok());
+    }
+
+    /// Starts receiving the messages published from now on
+    pub fn subscribe(&self) -> Subscription<T> {
+        let (sender, receiver) = channel();
+        self.subscribers.lock().unwrap().push(sender);
+        Subscription { receiver }
+    }
+}
+
+impl<T> Clone for MessagePort<T> {
+    fn clone(&self) -> MessagePort<T> {
+        MessagePort { subscribers: self.subscribers.clone() }
+    }
+}
+
+impl<T: Clone> Default for MessagePort<T> {
+    fn default() -> MessagePort<T> {
+        MessagePort::new()
+    }
+}
+
+/// The receiving end of a `MessagePort`
+pub struct Subscription<T> {
+    receiver: Receiver<T>,
+}
+
+impl<T> Subscription<T> {
+    /// Takes the oldest message that hasn't been received yet, without blocking
+    pub fn try_recv(&self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+}
+
+impl<T> Iterator for Subscription<T> {
+    type Item = T;
+    /// Blocks until a message arrives, or returns `None` once the port and all
+    /// of its clones have been dropped
+    fn next(&mut self) -> Option<T> {
+        self.receiver.recv().ok()
+    }
+}
@@ -0,0 +1,232 @@
+//! Checks the stream rates of a flowgraph before any samples flow.
+//!
+//! Every block declares how many items it produces per item it consumes (see
+//! `BlockRate`). Starting from the sources, a `RateGraph` works out the rate of
+//! every stream relative to its source. Blocks that join several streams
+//! (e.g. `Multiply` or `Interleave` after a `Split`) need all of their inputs
+//! to have the same rate, otherwise the buffer in front of the faster input
+//! grows without bound. For consistent graphs, it also works out how many
+//! items each buffer must hold before the block after it can produce its
+//! first output.
+//!
+//! While a flowgraph runs, a `Rated` stream carries its sample rate in Hz, and
+//! every block it passes through scales that rate the same way.
+
+use num::rational::Ratio;
+use num::{One, Zero, ToPrimitive};
+
+use crate::blocks::{RadioBlock, BlockRate};
+use crate::blocks::filter::{low_pass_filter_taps_hz, NumTapsSpecifier, WindowFunction};
+use crate::error::Result;
+
+/// Identifies a node that has been added to a `RateGraph`
+pub type NodeId = usize;
+
+struct Node {
+    name: String,
+    ratio: Ratio<usize>,
+    inputs: Vec<NodeId>,
+}
+
+/// A join whose inputs arrive at different rates
+#[derive(Clone, Debug, PartialEq)]
+pub struct RateMismatch {
+    pub node: NodeId,
+    pub name: String,
+    /// The rate of each input, relative to the source
+    pub input_rates: Vec<Ratio<usize>>,
+}
+
+/// The number of items the buffer between two nodes must hold
+///
+/// `size` is `None` if the buffer would have to grow without bound.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BufferSize {
+    pub from: NodeId,
+    pub to: NodeId,
+    pub size: Option<usize>,
+}
+
+/// The result of checking a `RateGraph`
+#[derive(Clone, Debug)]
+pub struct RateReport {
+    /// The output rate of each node, relative to its source
+    pub rates: Vec<Ratio<usize>>,
+    pub mismatches: Vec<RateMismatch>,
+    pub buffers: Vec<BufferSize>,
+}
+
+impl RateReport {
+    pub fn is_consistent(&self) -> bool {
+        self.mismatches.is_empty()
+    }
+}
+
+/// A description of a flowgraph's shape, for checking its rates
+///
+/// Nodes can only take their inputs from nodes that were added before them,
+/// so a `RateGraph` is always acyclic.
+///
+/// # Example
+/// ```
+/// use rustradio::rate::RateGraph;
+/// use rustradio::blocks::stream::{Split, Stride, Interleave};
+///
+/// let mut graph = RateGraph::new();
+/// let source = graph.source("source");
+/// let split = graph.block("split", &Split, &[source])?;
+/// let sparse = graph.block("stride", &Stride { stride: 100 }, &[split])?;
+/// graph.block("interleave", &Interleave, &[sparse, split])?;
+/// assert!(!graph.check().is_consistent());
+/// # Ok::<(), rustradio::Error>(())
+/// ```
+pub struct RateGraph {
+    nodes: Vec<Node>,
+}
+
+impl RateGraph {
+    pub fn new() -> RateGraph {
+        RateGraph { nodes: Vec::new() }
+    }
+
+    /// Adds a source. All rates are measured relative to the source's rate.
+    pub fn source(&mut self, name: &str) -> NodeId {
+        self.add(name, One::one(), Vec::new())
+    }
+
+    /// Adds a block that consumes the (zipped) outputs of `inputs`
+    ///
+    /// Fails if the block's parameters don't make a rate, see `BlockRate`.
+    pub fn block<R: BlockRate>(&mut self, name: &str, block: &R, inputs: &[NodeId]) -> Result<NodeId> {
+        for &input in inputs.iter() {
+            assert!(input < self.nodes.len(), "input {} has not been added to the graph", input);
+        }
+        Ok(self.add(name, block.rate()?, inputs.to_vec()))
+    }
+
+    fn add(&mut self, name: &str, ratio: Ratio<usize>, inputs: Vec<NodeId>) -> NodeId {
+        self.nodes.push(Node { name: name.to_string(), ratio, inputs });
+        self.nodes.len() - 1
+    }
+
+    /// Works out the rate of every stream and the size of every buffer
+    pub fn check(&self) -> RateReport {
+        let mut rates: Vec<Ratio<usize>> = Vec::with_capacity(self.nodes.len());
+        // The number of source items needed before each node produces an item
+        let mut latencies: Vec<Ratio<usize>> = Vec::with_capacity(self.nodes.len());
+        let mut mismatches = Vec::new();
+        let mut buffers = Vec::new();
+
+        for (id, node) in self.nodes.iter().enumerate() {
+            if node.inputs.is_empty() {
+                rates.push(node.ratio);
+                latencies.push(One::one());
+                continue;
+            }
+
+            let input_rates: Vec<Ratio<usize>> = node.inputs.iter().map(|&i| rates[i]).collect();
+            let input_rate = input_rates[0];
+            let consistent = input_rates.iter().all(|&r| r == input_rate);
+            if !consistent {
+                mismatches.push(RateMismatch {
+                    node: id,
+                    name: node.name.clone(),
+                    input_rates: input_rates.clone(),
+                });
+            }
+
+            // The block needs `denom` input items before it can produce anything
+            let needed = Ratio::from_integer(*node.ratio.denom() - 1) / input_rate;
+            let latest = node.inputs.iter().map(|&i| latencies[i])
+                             .fold(Zero::zero(), |a: Ratio<usize>, b| if b > a { b } else { a });
+            let latency = latest + needed;
+
+            for &input in node.inputs.iter() {
+                let size = if consistent {
+                    Some(((latency - latencies[input]) * rates[input]).to_integer() + 1)
+                } else {
+                    None
+                };
+                buffers.push(BufferSize { from: input, to: id, size });
+            }
+
+            rates.push(input_rate * node.ratio);
+            latencies.push(latency);
+        }
+
+        RateReport { rates, mismatches, buffers }
+    }
+}
+
+impl Default for RateGraph {
+    fn default() -> RateGraph {
+        RateGraph::new()
+    }
+}
+
+/// A stream that knows its sample rate
+///
+/// `Rated` is itself an iterator over the stream's items. Running a block with
+/// `through` multiplies the sample rate by the block's rate, so the rate after
+/// a `Stride` is divided by the stride, and after a `RationalResampler` it's
+/// multiplied by `up / down`. The `ext` adapters and `connect` don't know about
+/// sample rates, so they return plain streams.
+///
+/// # Example
+/// ```
+/// use rustradio::rate::Rated;
+/// use rustradio::blocks::stream::Stride;
+/// use rustradio::blocks::filter::{RationalResampler, HammingWindow, NumTapsSpecifier};
+///
+/// let source = Rated::new(std::iter::repeat(0f32), 48e3);
+/// let taps = source.low_pass_filter_taps(HammingWindow, 10e3, NumTapsSpecifier::NumTaps(31))?;
+/// let upsampled = source.through(&RationalResampler{ up: 3, down: 2, taps: &taps })?;
+/// let strided = upsampled.through(&Stride{ stride: 4 })?;
+/// assert_eq!(strided.sample_rate(), 18e3);
+/// # Ok::<(), rustradio::Error>(())
+/// ```
+pub struct Rated<I> {
+    stream: I,
+    sample_rate: f64,
+}
+
+impl<I: Iterator> Rated<I> {
+    /// Wraps a stream whose samples arrive at `sample_rate` Hz
+    pub fn new(stream: I, sample_rate: f64) -> Rated<I> {
+        Rated { stream, sample_rate }
+    }
+
+    /// The rate of this stream, in Hz
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate
+    }
+
+    /// Runs a block on this stream, scaling the sample rate by the block's rate
+    ///
+    /// Fails if the block's parameters don't make a rate, see `BlockRate`.
+    pub fn through<R>(self, block: &R) -> Result<Rated<R::Iter>>
+    where R: RadioBlock<I> + BlockRate {
+        let rate = block.rate()?.to_f64().unwrap_or(f64::NAN);
+        Ok(Rated { stream: block.process(self.stream), sample_rate: self.sample_rate * rate })
+    }
+
+    /// Designs a low-pass filter for this stream, with the cutoff in Hz
+    ///
+    /// A `TransitionWidth` is in Hz as well.
+    pub fn low_pass_filter_taps<W>(&self, window_type: W, cutoff: f64, num_taps: NumTapsSpecifier) -> Result<Vec<f32>>
+    where W: WindowFunction {
+        low_pass_filter_taps_hz(window_type, cutoff as f32, self.sample_rate as f32, num_taps)
+    }
+
+    /// Splits this into the bare stream and its sample rate
+    pub fn into_parts(self) -> (I, f64) {
+        (self.stream, self.sample_rate)
+    }
+}
+
+impl<I: Iterator> Iterator for Rated<I> {
+    type Item = I::Item;
+    fn next(&mut self) -> Option<I::Item> {
+        self.stream.next()
+    }
+}
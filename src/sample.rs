@@ -0,0 +1,195 @@
+//! The numeric types that blocks do arithmetic on.
+//!
+//! Every sample type implements `Sample`, which gives blocks a zero, the
+//! conjugate and the magnitude. The arithmetic itself is `MulAcc`, a
+//! multiply-accumulate with a second type, such as a filter's taps. Blocks like
+//! `FilterFIR`, `RationalResampler` and `Multiply` are written in terms of these
+//! traits alone, so any type that implements them works with all of them.
+//!
+//! The types are arranged as:
+//! - `RealSample`: a single component, like the integer and float primitives,
+//!   or `Q15` fixed-point numbers
+//! - `FloatSample`: a real sample that's a float, which blocks that need
+//!   trigonometry, like `FreqMod`, work with
+//! - `ComplexSample`: in-phase and quadrature parts, each a `RealSample`, like
+//!   `Complex<f32>` or `Complex<i16>`
+//!
+//! A complex sample can be multiplied by another one of the same type, or by
+//! its real part type, so complex streams can be filtered with real taps.
+//!
+//! # Example
+//! ```
+//! use num::Complex;
+//! use rustradio::ext::RadioIteratorExt;
+//! use rustradio::sample::{Q15, RealSample};
+//!
+//! // the same filter, on 16 bit IQ samples and on fixed-point numbers
+//! let taps = [2i16, 1];
+//! let iq = [Complex::new(1i16, -1), Complex::new(3, 2)];
+//! let filtered: Vec<Complex<i16>> = iq.into_iter().fir(&taps).collect();
+//! assert_eq!(filtered, vec![Complex::new(2, -2), Complex::new(7, 3)]);
+//!
+//! let taps = [Q15::from_f64(0.5), Q15::from_f64(0.25)];
+//! let samples = [Q15::from_f64(0.5), Q15::from_f64(-1.0)];
+//! let filtered: Vec<f64> = samples.into_iter().fir(&taps).map(|q| q.as_f64()).collect();
+//! assert_eq!(filtered, vec![0.25, -0.375]);
+//! ```
+
+use std::fmt;
+use std::ops::Neg;
+
+use num::complex::Complex;
+use num::Float;
+
+/// A value that blocks can do arithmetic on
+pub trait Sample: Copy + PartialEq + fmt::Debug + Send + Sync + 'static {
+    /// The additive identity
+    fn zero() -> Self;
+    /// The complex conjugate, which is the sample itself for real samples
+    fn conj(self) -> Self;
+    /// The distance from zero
+    fn magnitude(self) -> f64;
+}
+
+/// A sample with a single component
+pub trait RealSample: Sample + PartialOrd {
+    /// The value as a float, so `Q15(16384)` is 0.5
+    fn as_f64(self) -> f64;
+    /// The nearest value to `x`, saturating at the ends of the type's range
+    fn from_f64(x: f64) -> Self;
+    /// `-self`, saturating like `from_f64`, so `-i16::MIN` is `i16::MAX`
+    fn saturating_neg(self) -> Self;
+}
+
+/// A real sample that's a floating point number
+pub trait FloatSample: RealSample + Float {}
+
+/// A sample with in-phase and quadrature parts
+pub trait ComplexSample: Sample {
+    type Part: RealSample;
+    fn from_parts(re: Self::Part, im: Self::Part) -> Self;
+    fn re(self) -> Self::Part;
+    fn im(self) -> Self::Part;
+}
+
+/// Multiplication by a `T`, added to an accumulator
+pub trait MulAcc<T>: Sample {
+    /// The type of the products, and their sum
+    type Output: Sample;
+    /// Returns `acc + self * other`
+    fn mul_acc(self, other: T, acc: Self::Output) -> Self::Output;
+    /// Returns `self * other`
+    fn mul(self, other: T) -> Self::Output {
+        self.mul_acc(other, Sample::zero())
+    }
+}
+
+macro_rules! int_sample {
+    ($($t:ty),*) => {$(
+        impl Sample for $t {
+            fn zero() -> $t { 0 }
+            fn conj(self) -> $t { self }
+            fn magnitude(self) -> f64 { (self as f64).abs() }
+        }
+        impl RealSample for $t {
+            fn as_f64(self) -> f64 { self as f64 }
+            // `as` saturates when converting floats to integers
+            fn from_f64(x: f64) -> $t { x.round() as $t }
+            fn saturating_neg(self) -> $t { (0 as $t).saturating_sub(self) }
+        }
+        // saturates rather than overflowing, as `Q15` does
+        impl MulAcc<$t> for $t {
+            type Output = $t;
+            fn mul_acc(self, other: $t, acc: $t) -> $t { acc.saturating_add(self.saturating_mul(other)) }
+        }
+    )*};
+}
+int_sample!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+macro_rules! float_sample {
+    ($($t:ty),*) => {$(
+        impl Sample for $t {
+            fn zero() -> $t { 0.0 }
+            fn conj(self) -> $t { self }
+            fn magnitude(self) -> f64 { (self as f64).abs() }
+        }
+        impl RealSample for $t {
+            fn as_f64(self) -> f64 { self as f64 }
+            fn from_f64(x: f64) -> $t { x as $t }
+            fn saturating_neg(self) -> $t { -self }
+        }
+        impl FloatSample for $t {}
+        impl MulAcc<$t> for $t {
+            type Output = $t;
+            fn mul_acc(self, other: $t, acc: $t) -> $t { acc + self * other }
+        }
+    )*};
+}
+float_sample!(f32, f64);
+
+/// A fixed-point number between -1 and 1, with 15 fractional bits
+///
+/// `Q15(x)` is `x / 32768`. Arithmetic saturates rather than wrapping, and
+/// each product is rounded to 15 bits before it's accumulated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Q15(pub i16);
+
+impl Sample for Q15 {
+    fn zero() -> Q15 { Q15(0) }
+    fn conj(self) -> Q15 { self }
+    fn magnitude(self) -> f64 { self.as_f64().abs() }
+}
+impl RealSample for Q15 {
+    fn as_f64(self) -> f64 { self.0 as f64 / 32768.0 }
+    fn from_f64(x: f64) -> Q15 { Q15((x * 32768.0).round() as i16) }
+    fn saturating_neg(self) -> Q15 { -self }
+}
+impl MulAcc<Q15> for Q15 {
+    type Output = Q15;
+    fn mul_acc(self, other: Q15, acc: Q15) -> Q15 {
+        let product = (self.0 as i32 * other.0 as i32 + (1 << 14)) >> 15;
+        Q15((acc.0 as i32 + product).clamp(i16::MIN as i32, i16::MAX as i32) as i16)
+    }
+}
+impl Neg for Q15 {
+    type Output = Q15;
+    fn neg(self) -> Q15 { Q15(self.0.saturating_neg()) }
+}
+
+impl<T> Sample for Complex<T>
+where T: RealSample + Neg<Output = T> {
+    fn zero() -> Complex<T> { Complex::new(T::zero(), T::zero()) }
+    fn conj(self) -> Complex<T> { Complex::new(self.re, self.im.saturating_neg()) }
+    fn magnitude(self) -> f64 { self.re.as_f64().hypot(self.im.as_f64()) }
+}
+impl<T> ComplexSample for Complex<T>
+where T: RealSample + Neg<Output = T> {
+    type Part = T;
+    fn from_parts(re: T, im: T) -> Complex<T> { Complex::new(re, im) }
+    fn re(self) -> T { self.re }
+    fn im(self) -> T { self.im }
+}
+
+impl<T> MulAcc<Complex<T>> for Complex<T>
+where T: RealSample + MulAcc<T, Output = T> + Neg<Output = T> {
+    type Output = Complex<T>;
+    fn mul_acc(self, other: Complex<T>, acc: Complex<T>) -> Complex<T> {
+        let re = self.im.saturating_neg().mul_acc(other.im, self.re.mul_acc(other.re, acc.re));
+        let im = self.im.mul_acc(other.re, self.re.mul_acc(other.im, acc.im));
+        Complex::new(re, im)
+    }
+}
+impl<T> MulAcc<T> for Complex<T>
+where T: RealSample + MulAcc<T, Output = T> + Neg<Output = T> {
+    type Output = Complex<T>;
+    fn mul_acc(self, other: T, acc: Complex<T>) -> Complex<T> {
+        Complex::new(self.re.mul_acc(other, acc.re), self.im.mul_acc(other, acc.im))
+    }
+}
+impl<T> MulAcc<Complex<T>> for T
+where T: RealSample + MulAcc<T, Output = T> + Neg<Output = T> {
+    type Output = Complex<T>;
+    fn mul_acc(self, other: Complex<T>, acc: Complex<T>) -> Complex<T> {
+        other.mul_acc(self, acc)
+    }
+}
@@ -0,0 +1,176 @@
+//! Runs the iterators produced by processing blocks on their own threads.
+//!
+//! A `Scheduler` calls `process` on a block, moves the resulting iterator to a
+//! worker thread, and hands back a `Consumer` that yields the block's output.
+//! That `Consumer` is itself an iterator, so it can be passed as the input of
+//! the next block. Chaining blocks this way gives every stage of a flowgraph its
+//! own thread, connected by `push_buffer`s. Workers drain their block a chunk at
+//! a time with `ChunkedIterator::fill`, so blocks with vectorised kernels get to
+//! use them. When a block's iterator runs out, its worker closes the buffer, so
+//! the end of the stream propagates down the chain and every worker thread exits.
+//! The end also propagates up the chain: when a `Consumer` is dropped, the
+//! worker feeding it stops and drops its input, which is the `Consumer` of the
+//! worker before it.
+//!
+//! A block that panics ends its stream just as if it had run out, so once the
+//! streams have ended, `Scheduler::wait` joins the workers and reports any
+//! panic as an error.
+
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use crate::blocks::{RadioBlock, ChunkedIterator};
+use crate::buffers::{Consumer, push_buffer};
+use crate::error::{Error, Result};
+
+/// The number of output items a worker collects before pushing them downstream
+pub static DEFAULT_CHUNK_SIZE: usize = 512;
+
+/// Spawns blocks onto worker threads
+///
+/// # Example
+/// ```no_run
+/// use rustradio::scheduler::Scheduler;
+/// use rustradio::blocks::filter::FilterFIR;
+///
+/// let taps = vec![1i64, 2, 3];
+/// let b_filter = FilterFIR{ taps: &taps };
+/// let scheduler = Scheduler::new();
+/// let filtered = scheduler.run(&b_filter, 0i64..);
+/// for x in filtered.take(10) {
+///     println!("got value {}", x);
+/// }
+/// scheduler.wait()?;
+/// # Ok::<(), rustradio::Error>(())
+/// ```
+#[derive(Clone)]
+pub struct Scheduler {
+    /// Capacity of the buffer after each block
+    pub capacity: usize,
+    /// Number of items each worker pushes to its buffer at a time
+    pub chunk_size: usize,
+    /// The workers started so far, shared with every clone
+    workers: Arc<Mutex<Vec<JoinHandle<()>>>>,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            capacity: crate::DEFAULT_BUFFER_SIZE,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            workers: Arc::default(),
+        }
+    }
+
+    /// Waits for every worker started so far, by this scheduler or any of its
+    /// clones, returning `Error::Panicked` if any of their blocks panicked
+    ///
+    /// A worker only finishes once its block's iterator runs out or its
+    /// `Consumer` is dropped, so this should be called after the streams have
+    /// been read or dropped.
+    pub fn wait(&self) -> Result<()> {
+        let workers = std::mem::take(&mut *self.workers.lock().unwrap());
+        let mut result = Ok(());
+        for worker in workers {
+            if let Err(payload) = worker.join() {
+                result = result.and(Err(panic_error(payload)));
+            }
+        }
+        result
+    }
+
+    fn spawn(&self, f: impl FnOnce() + Send + 'static) {
+        self.workers.lock().unwrap().push(thread::spawn(f));
+    }
+
+    /// Runs a 1->1 (or N->1, with a zipped input) block on its own thread
+    ///
+    /// The block's `process` is called on the current thread, so blocks that
+    /// borrow their parameters can still be used. Only the resulting iterator
+    /// is moved to the worker thread.
+    pub fn run<I, R>(&self, block: &R, input: I) -> Consumer<R::Output>
+    where R: RadioBlock<I>, R::Output: Send + Clone + 'static, R::Iter: Send + 'static {
+        self.run_iter(block.process(input))
+    }
+
+    /// Runs an iterator that has already been made by a block on its own thread
+    ///
+    /// This is for iterators made some other way than `process`, such as by a
+    /// block's `process_controlled`.
+    pub fn run_iter<It>(&self, output: It) -> Consumer<It::Item>
+    where It: ChunkedIterator + Send + 'static, It::Item: Send + Clone + 'static {
+        let (producer, consumer) = push_buffer(self.capacity);
+        let chunk_size = self.chunk_size;
+        self.spawn(move|| {
+            let mut output = output;
+            // the first item stands in for the rest of the chunk until it's filled
+            let mut chunk = match output.next() {
+                Some(first) => vec![first; chunk_size.max(1)],
+                None => return,
+            };
+            let mut count = 1 + output.fill(&mut chunk[1..]);
+            while count > 0 {
+                if producer.push_slice_blocking(&chunk[..count]).is_err() {
+                    return;
+                }
+                count = output.fill(&mut chunk);
+            }
+        });
+        consumer
+    }
+
+    /// Runs a 1->2 block on its own thread, returning a `Consumer` for each output
+    ///
+    /// The worker keeps going until both `Consumer`s have been dropped, or the
+    /// block's iterator runs out.
+    pub fn run_split<B, C, I, R>(&self, block: &R, input: I) -> (Consumer<B>, Consumer<C>)
+    where B: Send + Clone + 'static, C: Send + Clone + 'static,
+          R: RadioBlock<I, Output=(B, C)>, R::Iter: Send + 'static {
+        let (producer_b, consumer_b) = push_buffer(self.capacity);
+        let (producer_c, consumer_c) = push_buffer(self.capacity);
+        let chunk_size = self.chunk_size;
+        let output = block.process(input);
+        self.spawn(move|| {
+            let mut output = output;
+            let mut chunk = match output.next() {
+                Some(first) => vec![first; chunk_size.max(1)],
+                None => return,
+            };
+            let mut count = 1 + output.fill(&mut chunk[1..]);
+            let mut chunk_b = Vec::with_capacity(chunk_size);
+            let mut chunk_c = Vec::with_capacity(chunk_size);
+            let (mut open_b, mut open_c) = (true, true);
+            while count > 0 {
+                chunk_b.clear();
+                chunk_c.clear();
+                for (b, c) in chunk[..count].iter().cloned() {
+                    chunk_b.push(b);
+                    chunk_c.push(c);
+                }
+                open_b = open_b && producer_b.push_slice_blocking(&chunk_b).is_ok();
+                open_c = open_c && producer_c.push_slice_blocking(&chunk_c).is_ok();
+                if !open_b && !open_c {
+                    return;
+                }
+                count = output.fill(&mut chunk);
+            }
+        });
+        (consumer_b, consumer_c)
+    }
+}
+
+impl Default for Scheduler {
+    fn default() -> Scheduler {
+        Scheduler::new()
+    }
+}
+
+/// Turns what a thread panicked with into an `Error::Panicked`
+pub(crate) fn panic_error(payload: Box<dyn Any + Send>) -> Error {
+    let message = match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload.downcast_ref::<&str>().map_or("a thread panicked", |m| m).to_string(),
+    };
+    Error::Panicked(message)
+}
@@ -1,119 +1,246 @@
-extern crate libc;
-extern crate num;
-extern crate rustradio;
-
 use std::ptr;
-use std::iter;
-use std::sync::Arc;
-use std::thread::Thread;
-use libc::{c_void, uint32_t, c_int};
+use std::thread::{self, JoinHandle};
+use std::time::SystemTime;
+use libc::{c_void, c_int};
 use num::Complex;
 
 use rustradio::buffers::{Producer, Consumer, push_buffer};
+use rustradio::tags::{Tag, Tagged};
+use rustradio::message::{MessagePort, Subscription};
+use rustradio::blocks::ChunkedIterator;
+use rustradio::stats::BufferStats;
+use rustradio::{Error, Result};
 
 #[link(name = "rtlsdr")]
-extern {
-    fn rtlsdr_open(dev: & *mut c_void, index: uint32_t) -> c_int;
-    fn rtlsdr_get_device_count() -> uint32_t;
-    fn rtlsdr_set_center_freq(dev: *mut c_void, freq: uint32_t) -> c_int;
-    fn rtlsdr_get_center_freq(dev: *mut c_void) -> uint32_t;
-    fn rtlsdr_set_sample_rate(dev: *mut c_void, rate: uint32_t) -> c_int;
-    fn rtlsdr_get_sample_rate(dev: *mut c_void) -> uint32_t;
+extern "C" {
+    fn rtlsdr_open(dev: *mut *mut c_void, index: u32) -> c_int;
+    fn rtlsdr_get_device_count() -> u32;
+    fn rtlsdr_set_center_freq(dev: *mut c_void, freq: u32) -> c_int;
+    fn rtlsdr_get_center_freq(dev: *mut c_void) -> u32;
+    fn rtlsdr_set_sample_rate(dev: *mut c_void, rate: u32) -> c_int;
+    fn rtlsdr_get_sample_rate(dev: *mut c_void) -> u32;
     fn rtlsdr_cancel_async(dev: *mut c_void) -> c_int;
     fn rtlsdr_reset_buffer(dev: *mut c_void) -> c_int;
     fn rtlsdr_close(dev: *mut c_void) -> c_int;
-	fn rtlsdr_read_async(dev: *mut c_void, cb: extern fn(*const u8, u32, Arc<Producer<Complex<f32>>>),
-        producer: Arc<Producer<Complex<f32>>>, buf_num: u32, buf_len: u32) -> c_int;
+    fn rtlsdr_read_async(dev: *mut c_void, cb: extern "C" fn(*const u8, u32, *mut c_void),
+        ctx: *mut c_void, buf_num: u32, buf_len: u32) -> c_int;
 }
 
 fn i2f(i: u8) -> f32 {i as f32/127.0 - 1.0}
 
-extern fn async_callback(buf: *const u8, len: u32, producer: Arc<Producer<Complex<f32>>>) {
-    let mut complex_vec: Vec<Complex<f32>> = Vec::with_capacity(len as uint / 2);
-    unsafe {
-        for i in iter::range_step(0, len, 2) {
-            let real = *(buf.offset(i as int));
-            let imag = *(buf.offset((i + 1) as int));
-            let sample = Complex{re: i2f(real), im: i2f(imag)};
-            complex_vec.push(sample);
-        }
-    }
-    if let Err(_) = producer.push_slice(complex_vec.as_slice()) {
-        panic!("Underflow!");
-        //TODO call stop_async
-    }
+/// The number of samples buffered between the device and the reader
+const BUFFER_SIZE: usize = 16 * 32 * 512;
+
+/// `ctx` points at the `Producer` owned by the thread running the async read,
+/// which outlives the read.
+///
+/// Blocking here would only make the device drop samples itself, so when the
+/// reader falls behind, the samples that don't fit are discarded. The buffer
+/// counts each time this happens as an overflow.
+extern "C" fn async_callback(buf: *const u8, len: u32, ctx: *mut c_void) {
+    let producer = unsafe { &*(ctx as *const Producer<Complex<f32>>) };
+    let bytes = unsafe { std::slice::from_raw_parts(buf, len as usize) };
+    let complex_vec: Vec<Complex<f32>> = bytes.chunks_exact(2)
+        .map(|iq| Complex{re: i2f(iq[0]), im: i2f(iq[1])})
+        .collect();
+    let _ = producer.push_slice(&complex_vec);
 }
 
+/// The device handle, which is handed to the thread running the async read
+struct DevPtr(*mut c_void);
+unsafe impl Send for DevPtr {}
+
+/// A message asking an `RTLSDR` to retune to a centre frequency, in Hz
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SetFrequency(pub u32);
+
 pub struct RTLSDR {
     dev_ptr: *mut c_void,
     is_streaming: bool,
-    producer: Arc<Producer<Complex<f32>>>,
+    /// The thread running the async read, which calls back into the `Producer`
+    reader: Option<JoinHandle<()>>,
+    commands: Option<Subscription<SetFrequency>>,
+    /// Moved to the thread running the async read once streaming starts
+    producer: Option<Producer<Complex<f32>>>,
     consumer: Consumer<Complex<f32>>,
+    stats: BufferStats,
 }
 
 impl RTLSDR {
-    pub fn new() -> Result<RTLSDR, &'static str> {
-        let dev = ptr::null_mut();
+    /// Opens the first device that isn't already in use
+    pub fn new() -> Result<RTLSDR> {
+        let mut dev = ptr::null_mut();
         unsafe {
-            for i in range(0, rtlsdr_get_device_count()) {
-                if 0 == rtlsdr_open(&dev, i) {
-                    let (producer, consumer) = push_buffer(16 * 32 * 512);
-                    let producer_ptr = Arc::new(producer);
+            for i in 0..rtlsdr_get_device_count() {
+                if 0 == rtlsdr_open(&mut dev, i) {
+                    let (producer, consumer) = push_buffer(BUFFER_SIZE);
+                    let stats = BufferStats::new(BUFFER_SIZE);
                     return Ok(RTLSDR { dev_ptr: dev,
                                        is_streaming: false,
-                                       producer: producer_ptr,
-                                       consumer: consumer});
+                                       reader: None,
+                                       commands: None,
+                                       producer: Some(producer),
+                                       consumer: consumer.with_stats(stats.clone()),
+                                       stats});
                 }
             }
         }
-        Err("No devices found")
+        Err(Error::Device("no devices found".to_string()))
     }
 
-    pub fn set_freq(&mut self, freq: u32) -> Result<u32, ()> {
+    /// Tunes to `freq` Hz, returning the frequency the device actually tuned to
+    pub fn set_freq(&mut self, freq: u32) -> Result<u32> {
         unsafe {
-            if 0 != rtlsdr_set_center_freq(self.dev_ptr, freq) {
-                Err(())
-            } else {
-                Ok(rtlsdr_get_center_freq(self.dev_ptr))
+            match rtlsdr_set_center_freq(self.dev_ptr, freq) {
+                0 => Ok(rtlsdr_get_center_freq(self.dev_ptr)),
+                code => Err(Error::Device(format!("couldn't tune to {} Hz (error {})", freq, code))),
             }
         }
     }
 
-    pub fn set_sample_rate(&mut self, fs: u32) -> Result<u32, ()> {
+    /// Sets the sample rate to `fs` Hz, returning the rate the device actually uses
+    pub fn set_sample_rate(&mut self, fs: u32) -> Result<u32> {
         unsafe {
-            if 0 != rtlsdr_set_sample_rate(self.dev_ptr, fs) {
-                Err(())
-            } else {
-                Ok(rtlsdr_get_sample_rate(self.dev_ptr))
+            match rtlsdr_set_sample_rate(self.dev_ptr, fs) {
+                0 => Ok(rtlsdr_get_sample_rate(self.dev_ptr)),
+                code => Err(Error::Device(format!("couldn't set the sample rate to {} Hz (error {})", fs, code))),
             }
         }
     }
 }
 
-impl Iterator<Complex<f32>> for RTLSDR {
+impl RTLSDR {
+    /// The counters of the buffer the device writes into
+    ///
+    /// Every overflow counted here lost a block of samples. The counters can be
+    /// added to a `Stats` with `Stats::add_buffer`.
+    pub fn buffer_stats(&self) -> BufferStats {
+        self.stats.clone()
+    }
+
+    /// Retunes whenever a `SetFrequency` message is published to `port`
+    ///
+    /// The messages are handled between samples, by whichever thread is
+    /// reading from this source.
+    pub fn subscribe(&mut self, port: &MessagePort<SetFrequency>) {
+        self.commands = Some(port.subscribe());
+    }
+
+    fn handle_commands(&mut self) -> Option<u32> {
+        let mut tuned = None;
+        while let Some(SetFrequency(freq)) = self.commands.as_ref().and_then(|c| c.try_recv()) {
+            // a failed retune leaves the device at its old frequency
+            if let Ok(freq) = self.set_freq(freq) {
+                tuned = Some(freq);
+            }
+        }
+        tuned
+    }
+}
+
+impl Iterator for RTLSDR {
+    type Item = Complex<f32>;
     fn next(&mut self) -> Option<Complex<f32>> {
+        self.handle_commands();
         if !self.is_streaming {
             unsafe {
                 rtlsdr_reset_buffer(self.dev_ptr);
             }
-            let producer = self.producer.clone();
-            let ptr = self.dev_ptr.clone();
-            Thread::spawn(move|| {
+            let producer = self.producer.take()?;
+            let dev = DevPtr(self.dev_ptr);
+            self.reader = Some(thread::spawn(move|| {
+                let dev = dev;
                 unsafe {
-                    rtlsdr_read_async(ptr, async_callback, producer, 0, 0);
+                    rtlsdr_read_async(dev.0, async_callback,
+                                      &producer as *const Producer<Complex<f32>> as *mut c_void, 0, 0);
                 }
-            }).detach();
+            }));
             self.is_streaming = true;
         }
         self.consumer.next()
     }
 }
 
+impl ChunkedIterator for RTLSDR {}
+
+impl RTLSDR {
+    /// Turns this into a stream of tagged samples
+    ///
+    /// The first sample is tagged with the device's current frequency and
+    /// sample rate, where they've been set, and the time streaming started.
+    /// Each successful `set_freq` or `set_sample_rate` attaches a tag with the
+    /// new setting to the next sample read. Samples already in the buffer were
+    /// captured before the change, so the tag can be up to a buffer early.
+    /// These are the tags `file::sigmf::write_sigmf` turns into metadata.
+    pub fn tagged(self) -> TaggedRTLSDR {
+        let (freq, fs) = unsafe {
+            (rtlsdr_get_center_freq(self.dev_ptr), rtlsdr_get_sample_rate(self.dev_ptr))
+        };
+        let mut tags = Vec::new();
+        if freq != 0 {
+            tags.push(Tag::CenterFrequency(freq as f64));
+        }
+        if fs != 0 {
+            tags.push(Tag::SampleRate(fs as f64));
+        }
+        TaggedRTLSDR { sdr: self, tags }
+    }
+}
+
+pub struct TaggedRTLSDR {
+    sdr: RTLSDR,
+    tags: Vec<Tag>,
+}
+
+impl TaggedRTLSDR {
+    /// See `RTLSDR::buffer_stats`
+    pub fn buffer_stats(&self) -> BufferStats {
+        self.sdr.buffer_stats()
+    }
+
+    /// See `RTLSDR::subscribe`
+    pub fn subscribe(&mut self, port: &MessagePort<SetFrequency>) {
+        self.sdr.subscribe(port);
+    }
+
+    pub fn set_freq(&mut self, freq: u32) -> Result<u32> {
+        let freq = self.sdr.set_freq(freq)?;
+        self.tags.push(Tag::CenterFrequency(freq as f64));
+        Ok(freq)
+    }
+
+    pub fn set_sample_rate(&mut self, fs: u32) -> Result<u32> {
+        let fs = self.sdr.set_sample_rate(fs)?;
+        // no sample has been read at the rate a pending tag names
+        self.tags.retain(|tag| !matches!(tag, Tag::SampleRate(_)));
+        self.tags.push(Tag::SampleRate(fs as f64));
+        Ok(fs)
+    }
+}
+
+impl Iterator for TaggedRTLSDR {
+    type Item = Tagged<Complex<f32>>;
+    fn next(&mut self) -> Option<Tagged<Complex<f32>>> {
+        if let Some(freq) = self.sdr.handle_commands() {
+            self.tags.push(Tag::CenterFrequency(freq as f64));
+        }
+        if !self.sdr.is_streaming {
+            self.tags.push(Tag::Timestamp(SystemTime::now()));
+        }
+        let sample = self.sdr.next()?;
+        Some(Tagged { sample, tags: std::mem::take(&mut self.tags) })
+    }
+}
+
+impl ChunkedIterator for TaggedRTLSDR {}
+
 impl Drop for RTLSDR {
     fn drop(&mut self) {
         unsafe {
-            if self.is_streaming {
+            if let Some(reader) = self.reader.take() {
                 rtlsdr_cancel_async(self.dev_ptr);
+                // the callback may still be running until the read returns
+                let _ = reader.join();
             }
             rtlsdr_close(self.dev_ptr);
         }
@@ -0,0 +1,334 @@
+//! Counters for finding the stage of a flowgraph that holds the rest back.
+//!
+//! Instrumentation is opt-in, so blocks and buffers that aren't being watched
+//! don't pay for it. A `Stats` collects named counters. `Stats::block` hands out
+//! a `BlockStats`, whose `process` runs a block with its input and output
+//! wrapped in counters. These count the samples going in and out and time the
+//! block's `next` and `fill` calls. Time spent waiting on the input, whether
+//! that is upstream blocks running on the same thread or an empty buffer
+//! between threads, is counted separately, so `busy` is the block's own work.
+//!
+//! `Stats::buffer` hands out a `BufferStats`, which is attached to a buffer with
+//! its `with_stats` method. The buffer then keeps track of how full it is, the
+//! most it has ever held, and how often it overflowed, i.e. had no room for
+//! data that had to go in.
+//!
+//! `Stats::snapshot` reads every counter at once. The `Snapshot` displays as a
+//! text report, and `Stats::report_every` produces one periodically.
+//!
+//! # Example
+//! ```
+//! use rustradio::blocks::filter::FilterFIR;
+//! use rustradio::scheduler::Scheduler;
+//! use rustradio::stats::Stats;
+//!
+//! let taps = vec![1f32, 2.0, 1.0];
+//! let stats = Stats::new();
+//! let scheduler = Scheduler::new();
+//! let filtered = stats.block("fir").process(&FilterFIR{ taps: &taps }, (0..1000).map(|x| x as f32));
+//! let filtered = scheduler.run_iter(filtered).with_stats(stats.buffer("fir", scheduler.capacity));
+//! assert_eq!(filtered.count(), 1000);
+//!
+//! let snapshot = stats.snapshot();
+//! assert_eq!(snapshot.blocks[0].items_in, 1000);
+//! assert_eq!(snapshot.blocks[0].items_out, 1000);
+//! assert!(snapshot.buffers[0].high_water <= scheduler.capacity);
+//! println!("{}", snapshot);
+//! ```
+
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::blocks::{RadioBlock, ChunkedIterator};
+
+#[derive(Default)]
+struct BlockCounters {
+    items_in: AtomicU64,
+    items_out: AtomicU64,
+    total_nanos: AtomicU64,
+    input_nanos: AtomicU64,
+}
+
+/// The counters for one block
+#[derive(Clone, Default)]
+pub struct BlockStats {
+    counters: Arc<BlockCounters>,
+}
+
+impl BlockStats {
+    pub fn new() -> BlockStats {
+        BlockStats::default()
+    }
+
+    /// Processes `input` with `block`, counting on the way in and out
+    pub fn process<I, R>(&self, block: &R, input: I) -> Instrumented<R::Iter>
+    where I: Iterator, R: RadioBlock<CountIn<I>> {
+        self.output(block.process(self.input(input)))
+    }
+
+    /// Counts the samples a block takes from `input`, and the time spent waiting on it
+    pub fn input<I: Iterator>(&self, input: I) -> CountIn<I> {
+        CountIn { iterator: input, counters: self.counters.clone() }
+    }
+
+    /// Counts the samples a block produces, and the time spent producing them
+    pub fn output<It: Iterator>(&self, output: It) -> Instrumented<It> {
+        Instrumented { iterator: output, counters: self.counters.clone() }
+    }
+
+    fn snapshot(&self, name: &str) -> BlockSnapshot {
+        let counters = &self.counters;
+        let total = counters.total_nanos.load(Ordering::Relaxed);
+        let waiting = counters.input_nanos.load(Ordering::Relaxed);
+        BlockSnapshot {
+            name: name.to_string(),
+            items_in: counters.items_in.load(Ordering::Relaxed),
+            items_out: counters.items_out.load(Ordering::Relaxed),
+            busy: Duration::from_nanos(total.saturating_sub(waiting)),
+            waiting: Duration::from_nanos(waiting),
+        }
+    }
+}
+
+fn add_elapsed(counter: &AtomicU64, start: Instant) {
+    counter.fetch_add(start.elapsed().as_nanos() as u64, Ordering::Relaxed);
+}
+
+/// A block's input, counting what the block takes from it
+pub struct CountIn<I> {
+    iterator: I,
+    counters: Arc<BlockCounters>,
+}
+impl<I: Iterator> Iterator for CountIn<I> {
+    type Item = I::Item;
+    fn next(&mut self) -> Option<I::Item> {
+        let start = Instant::now();
+        let next = self.iterator.next();
+        add_elapsed(&self.counters.input_nanos, start);
+        if next.is_some() {
+            self.counters.items_in.fetch_add(1, Ordering::Relaxed);
+        }
+        next
+    }
+}
+
+/// A block's output, counting what the block produces
+pub struct Instrumented<It> {
+    iterator: It,
+    counters: Arc<BlockCounters>,
+}
+impl<It: Iterator> Iterator for Instrumented<It> {
+    type Item = It::Item;
+    fn next(&mut self) -> Option<It::Item> {
+        let start = Instant::now();
+        let next = self.iterator.next();
+        add_elapsed(&self.counters.total_nanos, start);
+        if next.is_some() {
+            self.counters.items_out.fetch_add(1, Ordering::Relaxed);
+        }
+        next
+    }
+}
+impl<It: ChunkedIterator> ChunkedIterator for Instrumented<It> {
+    fn fill(&mut self, out: &mut [It::Item]) -> usize {
+        let start = Instant::now();
+        let count = self.iterator.fill(out);
+        add_elapsed(&self.counters.total_nanos, start);
+        self.counters.items_out.fetch_add(count as u64, Ordering::Relaxed);
+        count
+    }
+}
+
+struct BufferCounters {
+    capacity: usize,
+    len: AtomicUsize,
+    high_water: AtomicUsize,
+    overflows: AtomicU64,
+}
+
+/// The counters for one buffer
+#[derive(Clone)]
+pub struct BufferStats {
+    counters: Arc<BufferCounters>,
+}
+
+impl BufferStats {
+    /// Counters for a buffer that normally holds up to `capacity` items
+    pub fn new(capacity: usize) -> BufferStats {
+        BufferStats { counters: Arc::new(BufferCounters {
+            capacity,
+            len: AtomicUsize::new(0),
+            high_water: AtomicUsize::new(0),
+            overflows: AtomicU64::new(0),
+        })}
+    }
+
+    /// Records the number of items the buffer currently holds
+    pub fn record_len(&self, len: usize) {
+        self.counters.len.store(len, Ordering::Relaxed);
+        self.counters.high_water.fetch_max(len, Ordering::Relaxed);
+    }
+
+    /// Records that the buffer had no room for items that had to go in
+    pub fn record_overflow(&self) {
+        self.counters.overflows.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The number of overflows recorded so far
+    pub fn overflows(&self) -> u64 {
+        self.counters.overflows.load(Ordering::Relaxed)
+    }
+
+    fn snapshot(&self, name: &str) -> BufferSnapshot {
+        let counters = &self.counters;
+        BufferSnapshot {
+            name: name.to_string(),
+            capacity: counters.capacity,
+            len: counters.len.load(Ordering::Relaxed),
+            high_water: counters.high_water.load(Ordering::Relaxed),
+            overflows: counters.overflows.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A block's counters at the time of a `Snapshot`
+#[derive(Clone, Debug, PartialEq)]
+pub struct BlockSnapshot {
+    pub name: String,
+    pub items_in: u64,
+    pub items_out: u64,
+    /// Time spent in the block's own code
+    pub busy: Duration,
+    /// Time the block spent waiting on its input
+    pub waiting: Duration,
+}
+
+/// A buffer's counters at the time of a `Snapshot`
+#[derive(Clone, Debug, PartialEq)]
+pub struct BufferSnapshot {
+    pub name: String,
+    pub capacity: usize,
+    /// The number of items held
+    pub len: usize,
+    /// The most items ever held
+    pub high_water: usize,
+    pub overflows: u64,
+}
+
+/// Every counter of a `Stats`, read at one time
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    /// Time since the `Stats` was created
+    pub elapsed: Duration,
+    pub blocks: Vec<BlockSnapshot>,
+    pub buffers: Vec<BufferSnapshot>,
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let seconds = self.elapsed.as_secs_f64().max(f64::MIN_POSITIVE);
+        writeln!(f, "after {:.1}s", self.elapsed.as_secs_f64())?;
+        if !self.blocks.is_empty() {
+            writeln!(f, "{:<16} {:>12} {:>12} {:>10} {:>7}", "block", "in", "out", "out/s", "busy")?;
+        }
+        for block in self.blocks.iter() {
+            writeln!(f, "{:<16} {:>12} {:>12} {:>10.3e} {:>6.1}%",
+                     block.name, block.items_in, block.items_out, block.items_out as f64 / seconds,
+                     100.0 * block.busy.as_secs_f64() / seconds)?;
+        }
+        if !self.buffers.is_empty() {
+            writeln!(f, "{:<16} {:>12} {:>12} {:>10} {:>9}", "buffer", "len", "high water", "capacity", "overflows")?;
+        }
+        for buffer in self.buffers.iter() {
+            writeln!(f, "{:<16} {:>12} {:>12} {:>10} {:>9}",
+                     buffer.name, buffer.len, buffer.high_water, buffer.capacity, buffer.overflows)?;
+        }
+        Ok(())
+    }
+}
+
+struct Registered {
+    blocks: Vec<(String, BlockStats)>,
+    buffers: Vec<(String, BufferStats)>,
+}
+
+/// A named collection of block and buffer counters
+///
+/// Clones share the same counters.
+#[derive(Clone)]
+pub struct Stats {
+    start: Instant,
+    registered: Arc<Mutex<Registered>>,
+}
+
+impl Stats {
+    pub fn new() -> Stats {
+        Stats {
+            start: Instant::now(),
+            registered: Arc::new(Mutex::new(Registered { blocks: Vec::new(), buffers: Vec::new() })),
+        }
+    }
+
+    /// Adds counters for a block
+    pub fn block(&self, name: &str) -> BlockStats {
+        let stats = BlockStats::new();
+        self.registered.lock().unwrap().blocks.push((name.to_string(), stats.clone()));
+        stats
+    }
+
+    /// Adds counters for a buffer of the given capacity
+    pub fn buffer(&self, name: &str, capacity: usize) -> BufferStats {
+        let stats = BufferStats::new(capacity);
+        self.add_buffer(name, &stats);
+        stats
+    }
+
+    /// Adds counters that were made elsewhere, such as by a source's own buffer
+    pub fn add_buffer(&self, name: &str, stats: &BufferStats) {
+        self.registered.lock().unwrap().buffers.push((name.to_string(), stats.clone()));
+    }
+
+    /// Reads every counter
+    pub fn snapshot(&self) -> Snapshot {
+        let registered = self.registered.lock().unwrap();
+        Snapshot {
+            elapsed: self.start.elapsed(),
+            blocks: registered.blocks.iter().map(|(name, stats)| stats.snapshot(name)).collect(),
+            buffers: registered.buffers.iter().map(|(name, stats)| stats.snapshot(name)).collect(),
+        }
+    }
+
+    /// Passes a snapshot to `report` every `period`, until the `Reporter` is dropped
+    ///
+    /// ```no_run
+    /// # use std::time::Duration;
+    /// # let stats = rustradio::stats::Stats::new();
+    /// let _reporter = stats.report_every(Duration::from_secs(1), |snapshot| eprint!("{}", snapshot));
+    /// ```
+    pub fn report_every<F>(&self, period: Duration, mut report: F) -> Reporter
+    where F: FnMut(&Snapshot) + Send + 'static {
+        let (stop, stopped) = channel::<()>();
+        let stats = self.clone();
+        thread::spawn(move|| {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(period) {
+                report(&stats.snapshot());
+            }
+        });
+        Reporter { _stop: stop }
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Stats {
+        Stats::new()
+    }
+}
+
+/// Keeps a periodic report going, see `Stats::report_every`
+pub struct Reporter {
+    _stop: Sender<()>,
+}
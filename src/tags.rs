@@ -0,0 +1,191 @@
+//! Metadata that travels alongside the samples of a stream.
+//!
+//! Tags are opt-in. A tagged stream is an iterator over `Tagged` items, each
+//! carrying the tags that apply from that sample on. Blocks that just move
+//! samples around, like `Split`, `Interleave` and `DeInterleave`, handle tagged
+//! items as they are, so tags stay with their samples. Blocks that compute new
+//! samples or change the rate, like `FilterFIR`, `RationalResampler` and
+//! `Stride`, are wrapped in `WithTags`. It strips the tags off, runs the block on
+//! the bare samples, and reattaches each tag to the output sample at the
+//! corresponding index, rescaled by the block's rate.
+//!
+//! # Example
+//! ```
+//! use rustradio::blocks::RadioBlock;
+//! use rustradio::blocks::stream::Stride;
+//! use rustradio::tags::{insert_tags, Tag, WithTags};
+//!
+//! let tagged = insert_tags(0usize..20, vec![(9, Tag::BurstStart)]);
+//! let strided: Vec<_> = WithTags(Stride{ stride: 4 }).process(tagged).collect();
+//! // sample 9 was dropped, so its tag moves to the next sample kept, sample 12
+//! assert_eq!(strided[3].sample, 12);
+//! assert_eq!(strided[3].tags, vec![Tag::BurstStart]);
+//! ```
+
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::time::SystemTime;
+
+use num::rational::Ratio;
+
+use crate::blocks::{RadioBlock, BlockRate, ChunkedIterator, Describe};
+use crate::error::Result;
+
+/// A piece of metadata about the stream, starting at the sample it's attached to
+#[derive(Clone, Debug, PartialEq)]
+pub enum Tag {
+    /// The centre frequency the samples were captured at, in Hz
+    CenterFrequency(f64),
+    /// The sample rate of the stream, in Hz
+    SampleRate(f64),
+    /// The time at which the sample was captured
+    Timestamp(SystemTime),
+    /// A change of the receiver's gain, in dB
+    Gain(f64),
+    /// The first sample of a burst
+    BurstStart,
+    /// The last sample of a burst
+    BurstEnd,
+    /// A note about the samples starting here
+    Annotation(Annotation),
+}
+
+/// A description of part of a stream, such as a signal found in a recording
+///
+/// The fields are those of a SigMF annotation. `sample_count` is a number of
+/// samples at the rate the annotation was made, which isn't rescaled when the
+/// tag passes through a block that changes the rate.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Annotation {
+    pub sample_count: Option<u64>,
+    /// The lowest frequency of the annotated signal, in Hz
+    pub freq_lower_edge: Option<f64>,
+    /// The highest frequency of the annotated signal, in Hz
+    pub freq_upper_edge: Option<f64>,
+    /// A short name for what the samples hold
+    pub label: Option<String>,
+    pub comment: Option<String>,
+}
+
+/// A sample along with the tags attached to it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tagged<A> {
+    pub sample: A,
+    pub tags: Vec<Tag>,
+}
+
+impl<A> Tagged<A> {
+    /// Wraps a sample without any tags
+    pub fn new(sample: A) -> Tagged<A> {
+        Tagged { sample, tags: Vec::new() }
+    }
+}
+
+/// Attaches tags to a stream of samples
+///
+/// `tags` holds pairs of a sample index and a tag, sorted by index. Tags whose
+/// index is past the end of the stream are dropped.
+pub fn insert_tags<A, I>(input: I, tags: Vec<(usize, Tag)>) -> InsertTags<I>
+where I: Iterator<Item = A> {
+    InsertTags { iterator: input, index: 0, tags: tags.into() }
+}
+
+pub struct InsertTags<I> {
+    iterator: I,
+    index: usize,
+    tags: VecDeque<(usize, Tag)>,
+}
+impl<A, I: Iterator<Item = A>> Iterator for InsertTags<I> {
+    type Item = Tagged<A>;
+    fn next(&mut self) -> Option<Tagged<A>> {
+        let mut tagged = Tagged::new(self.iterator.next()?);
+        while self.tags.front().is_some_and(|&(index, _)| index <= self.index) {
+            tagged.tags.extend(self.tags.pop_front().map(|(_, tag)| tag));
+        }
+        self.index += 1;
+        Some(tagged)
+    }
+}
+
+/// Runs a single-input, single-output block on a tagged stream
+///
+/// A tag on input sample `k` ends up on output sample `ceil(k * rate)`, which
+/// is the first output that sample can have affected.
+#[derive(Clone, Copy)]
+pub struct WithTags<R>(pub R);
+
+/// The bare samples of a tagged stream, which are fed to the wrapped block
+pub struct Untag<I> {
+    iterator: I,
+    index: usize,
+    sender: Sender<(usize, Tag)>,
+}
+impl<A, I: Iterator<Item = Tagged<A>>> Iterator for Untag<I> {
+    type Item = A;
+    fn next(&mut self) -> Option<A> {
+        let Tagged { sample, tags } = self.iterator.next()?;
+        for tag in tags {
+            // the receiver only goes away along with this iterator
+            let _ = self.sender.send((self.index, tag));
+        }
+        self.index += 1;
+        Some(sample)
+    }
+}
+
+pub struct WithTagsIter<It> {
+    iterator: It,
+    rate: Ratio<usize>,
+    index: usize,
+    received: Receiver<(usize, Tag)>,
+    pending: VecDeque<(usize, Tag)>,
+}
+impl<B, It: Iterator<Item = B>> Iterator for WithTagsIter<It> {
+    type Item = Tagged<B>;
+    fn next(&mut self) -> Option<Tagged<B>> {
+        let mut tagged = Tagged::new(self.iterator.next()?);
+        self.pending.extend(self.received.try_iter());
+        while let Some(&(input_index, _)) = self.pending.front() {
+            let output_index = (Ratio::from_integer(input_index) * self.rate).ceil().to_integer();
+            if output_index > self.index {
+                break;
+            }
+            tagged.tags.extend(self.pending.pop_front().map(|(_, tag)| tag));
+        }
+        self.index += 1;
+        Some(tagged)
+    }
+}
+impl<B, It: Iterator<Item = B>> ChunkedIterator for WithTagsIter<It> {}
+
+impl<A, I, R> RadioBlock<I> for WithTags<R>
+where I: Iterator<Item = Tagged<A>>, R: RadioBlock<Untag<I>, Input = A> + BlockRate {
+    type Input = Tagged<A>;
+    type Output = Tagged<R::Output>;
+    type Iter = WithTagsIter<R::Iter>;
+    const INPUTS: usize = 1;
+    const OUTPUTS: usize = 1;
+
+    fn process(&self, input: I) -> WithTagsIter<R::Iter> {
+        let (sender, received) = channel();
+        let untagged = Untag { iterator: input, index: 0, sender };
+        // as with the block's own `process`, invalid parameters panic
+        let rate = self.0.rate().unwrap_or_else(|e| panic!("{}", e));
+        WithTagsIter {
+            iterator: self.0.process(untagged),
+            rate,
+            index: 0,
+            received,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<R: BlockRate> BlockRate for WithTags<R> {
+    fn rate(&self) -> Result<Ratio<usize>> { self.0.rate() }
+}
+
+impl<R: Describe> Describe for WithTags<R> {
+    fn kind(&self) -> String { format!("WithTags<{}>", self.0.kind()) }
+    fn params(&self) -> Vec<(String, String)> { self.0.params() }
+}
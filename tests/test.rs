@@ -1,95 +1,495 @@
-#![feature(phase)]
-#![feature(globs)]
-#[allow(unused_imports)]
-#[phase(plugin, link)]
+// reference values are copied verbatim from numpy and gnuradio
+#![allow(clippy::excessive_precision)]
 
-extern crate rustradio;
-extern crate num;
-
-//Note: This is needed for the macro to work. TODO: fix this
-use rustradio::blocks::RadioBlock;
-use std::iter;
+use rustradio::{connect, join, split, Error};
 
 use rustradio::blocks::stream::*;
 use rustradio::blocks::filter::*;
 use rustradio::blocks::modem::*;
+use rustradio::blocks::hier::{Then, FmDemod};
+use rustradio::blocks::BlockRate;
+use rustradio::scheduler::Scheduler;
+use rustradio::rate::{RateGraph, BufferSize, Rated};
+use rustradio::buffers::{FixedBuffer2First, FixedBuffer2Second, push_buffer, buffer_fixed, split_fixed, fan_out_fixed, split_fixed_with_policy, OverflowPolicy, BufferError};
+use rustradio::ext::RadioIteratorExt;
+use rustradio::blocks::{RadioBlock, ChunkedIterator};
+use num::rational::Ratio;
+use std::iter::Zip;
+use rustradio::tags::{insert_tags, Annotation, Tag, Tagged, WithTags};
+use rustradio::message::MessagePort;
+use rustradio::flowgraph::{BlockFactory, BlockSpec, BuildContext, Built, DynStream, FlowgraphSpec, ItemType, Registry};
+use rustradio::file::{file_read_stream, file_write_stream, read_stream};
+use rustradio::file::sigmf::{file_read_sigmf, file_write_sigmf, read_sigmf, Global, Meta};
+use rustradio::file::format::{file_read_samples, file_write_samples, read_samples, SampleFormat, Scalar};
+use rustradio::file::mmap::MmapFile;
+use rustradio::file::wav::{file_read_wav, file_write_wav, read_wav, WavFormat};
+use rustradio::dot::DotGraph;
+use rustradio::stats::Stats;
+use rustradio::sample::{MulAcc, Sample, RealSample, Q15};
+use num::Complex;
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+#[test]
+fn split_multiply() {
+    let source = vec![0usize, 1, 2, 3, 4].into_iter();
+
+    let (s1, s2) = split(connect(&Split, source));
+    let combined = connect(&Multiply, join((s1, s2)));
+
+    let collected: Vec<usize> = combined.collect();
+    assert_eq!(collected, vec![0usize, 1, 4, 9, 16]);
+}
+
+#[test]
+fn split_three_ways() {
+    let source = vec![(0usize, 1usize, 2usize), (3, 4, 5)].into_iter();
+
+    let (s1, s2, s3) = split(source);
+    let collected: Vec<(usize, usize, usize)> = join((s3, s1, s2)).collect();
+    assert_eq!(collected, vec![(2usize, 0usize, 1usize), (5, 3, 4)]);
+}
+
+#[test]
+fn split_eight_ways() {
+    let source = (0usize..3).map(|x| (x, x + 1, x + 2, x + 3, x + 4, x + 5, x + 6, x + 7));
+
+    let (s1, s2, s3, s4, s5, s6, s7, s8) = split(source);
+    let sums: Vec<usize> = join((s1, s2, s3, s4, s5, s6, s7, s8))
+        .map(|(a, b, c, d, e, f, g, h)| a + b + c + d + e + f + g + h)
+        .collect();
+    assert_eq!(sums, vec![28usize, 36, 44]);
+}
 
 #[test]
-fn split() {
-    let source = vec![0u, 1, 2, 3, 4].into_iter();
+fn join_uneven_lengths() {
+    let (s1, s2, s3, s4) = split((0usize..5).map(|x| (x, x as f32, x as u8, x % 2 == 0)));
+    let joined: Vec<(usize, u8, bool, f32, char)> = join((s1, s3, s4.take(3), s2, "abcde".chars())).collect();
+    assert_eq!(joined, vec![(0usize, 0u8, true, 0f32, 'a'), (1, 1, false, 1., 'b'), (2, 2, true, 2., 'c')]);
+}
+
+#[test]
+fn fan_out() {
+    let source = vec![0usize, 1, 2, 3, 4].into_iter();
 
-    connect!((s1, s2) <- Split (source));
-    connect!(combined <- Multiply (s1, s2));
+    let fan_out = fan_out_fixed(source, rustradio::DEFAULT_BUFFER_SIZE);
+    let (s1, s2, s3) = (fan_out.output(), fan_out.output(), fan_out.output());
 
-    let collected: Vec<uint> = combined.collect();
-    assert_eq!(collected, vec![0u, 1, 4, 9, 16]);
+    let collected: Vec<usize> = s1.zip(s2).zip(s3).map(|((a, b), c)| a + b * c).collect();
+    assert_eq!(collected, vec![0usize, 2, 6, 12, 20]);
 }
 
 #[test]
-#[should_fail]
-#[allow(unused_variables)]
 fn split_buffer_overrun() {
     /*
         This should fail because the flowgraph requires unbounded memory growth, which
         fixed buffers can't satisfy. Note that an equivalent graph locks up in GNURadio
     */
-    let source = iter::count(0u,1);
+    let source = 0usize..;
 
     let b_stride = Stride { stride: 100 };
 
-    connect!((block_a, block_b) <- Split (source));
-    connect!(sparse <- b_stride (block_a));
-    connect!(together <- Interleave (sparse, block_b));
+    let (mut block_a, mut block_b) = split(connect(&Split, source));
+    let sparse = connect(&b_stride, &mut block_a);
+    let together = connect(&Interleave, join((sparse, &mut block_b)));
+
+    let collected: Vec<usize> = together.take(1000000).collect();
+    assert!(collected.len() < 1000000);
+    assert!(matches!(block_a.check(), Err(Error::BufferOverrun(_))));
+}
+
+#[test]
+fn split_overflow_policies() {
+    let source = || (0usize..).map(|x| (x, x));
+
+    let (mut first, _second) = split_fixed_with_policy(source(), 4, 4, OverflowPolicy::Fixed);
+    for i in 0..4 {
+        assert_eq!(first.try_next(), Ok(Some(i)));
+    }
+    assert_eq!(first.try_next(), Err(BufferError::Overrun(4)));
+
+    let (mut first, second) = split_fixed_with_policy(source(), 4, 4, OverflowPolicy::Grow);
+    let collected: Vec<usize> = first.by_ref().take(20).collect();
+    assert_eq!(collected, (0..20).collect::<Vec<usize>>());
+    let collected: Vec<usize> = second.take(20).collect();
+    assert_eq!(collected, (0..20).collect::<Vec<usize>>());
+
+    let (mut first, _second) = split_fixed_with_policy(source(), 4, 4, OverflowPolicy::GrowTo(6));
+    for i in 0..6 {
+        assert_eq!(first.try_next(), Ok(Some(i)));
+    }
+    assert_eq!(first.try_next(), Err(BufferError::Overrun(6)));
+
+    let (mut first, second) = split_fixed_with_policy(source(), 4, 4, OverflowPolicy::DropOldest);
+    let collected: Vec<usize> = first.by_ref().take(8).collect();
+    assert_eq!(collected, (0..8).collect::<Vec<usize>>());
+    assert_eq!(first.dropped(), 4);
+    let collected: Vec<usize> = second.take(4).collect();
+    assert_eq!(collected, vec![4usize, 5, 6, 7]);
+}
+
+#[test]
+fn rate_analysis() {
+    // the graph from split_buffer_overrun
+    let mut graph = RateGraph::new();
+    let source = graph.source("source");
+    let split = graph.block("split", &Split, &[source]).unwrap();
+    let sparse = graph.block("stride", &Stride { stride: 100 }, &[split]).unwrap();
+    let together = graph.block("interleave", &Interleave, &[sparse, split]).unwrap();
+    let report = graph.check();
+    assert!(!report.is_consistent());
+    assert_eq!(report.mismatches[0].node, together);
+    assert!(report.buffers.contains(&BufferSize { from: split, to: together, size: None }));
 
-    let collected: Vec<uint> = together.take(1000000).collect();
+    // decimating then interpolating one branch balances the rates, but the
+    // other branch has to buffer while the stride waits for its input
+    let taps = vec![1f32];
+    let mut graph = RateGraph::new();
+    let source = graph.source("source");
+    let split = graph.block("split", &Split, &[source]).unwrap();
+    let sparse = graph.block("stride", &Stride { stride: 4 }, &[split]).unwrap();
+    let upsampled = graph.block("resampler",
+                                &RationalResampler{ up: 4, down: 1, taps: &taps },
+                                &[sparse]).unwrap();
+    let product = graph.block("multiply", &Multiply, &[upsampled, split]).unwrap();
+    let report = graph.check();
+    assert!(report.is_consistent());
+    assert_eq!(report.rates[product], report.rates[source]);
+    assert!(report.buffers.contains(&BufferSize { from: split, to: sparse, size: Some(4) }));
+    assert!(report.buffers.contains(&BufferSize { from: split, to: product, size: Some(4) }));
+    assert!(report.buffers.contains(&BufferSize { from: upsampled, to: product, size: Some(1) }));
 }
 
 #[test]
 fn filter_fir() {
-    let source = iter::count(0i,1);
-    let taps = vec![1i, 2, 3];
-    let b_filter = FilterFIR{ taps: taps.as_slice() };
-    connect!(filtered <- b_filter (source));
-    let collected: Vec<int> = filtered.take(6).collect();
+    let source = 0i64..;
+    let taps = vec![1i64, 2, 3];
+    let b_filter = FilterFIR{ taps: &taps };
+    let filtered = connect(&b_filter, source);
+    let collected: Vec<i64> = filtered.take(6).collect();
 
-    assert_eq!(collected, vec![0i, 1, 4, 10, 16, 22]);
+    assert_eq!(collected, vec![0i64, 1, 4, 10, 16, 22]);
+}
+
+#[test]
+fn threaded_chain() {
+    let taps = vec![1i64, 2, 3];
+    let b_filter = FilterFIR{ taps: &taps };
+    let b_stride = Stride { stride: 2 };
+
+    let scheduler = Scheduler::new();
+    let filtered = scheduler.run(&b_filter, (0i64..).take(12));
+    let strided = scheduler.run(&b_stride, filtered);
+    let collected: Vec<i64> = strided.take(6).collect();
+
+    assert_eq!(collected, vec![0i64, 4, 16, 28, 40, 52]);
+}
+
+#[test]
+fn push_buffer_consumer_dropped() {
+    let (producer, mut consumer) = push_buffer(4);
+    let guard = thread::spawn(move|| {
+        let mut pushed = 0usize;
+        loop {
+            match producer.push_slice_blocking(&[1usize, 2, 3]) {
+                Ok(()) => pushed += 3,
+                Err(count) => return pushed + count,
+            }
+        }
+    });
+
+    assert_eq!(consumer.next(), Some(1usize));
+    assert_eq!(consumer.next(), Some(2usize));
+    drop(consumer);
+
+    let pushed = guard.join().unwrap();
+    assert!(pushed >= 2);
+}
+
+/// Reports on a channel when it's dropped, so a test can see a worker exit
+struct DropNotify(std::ops::RangeFrom<i64>, std::sync::mpsc::Sender<()>);
+
+impl Drop for DropNotify {
+    fn drop(&mut self) {
+        self.1.send(()).unwrap();
+    }
+}
+
+impl Iterator for DropNotify {
+    type Item = i64;
+    fn next(&mut self) -> Option<i64> {
+        self.0.next()
+    }
+}
+
+#[test]
+fn threaded_chain_stops_early() {
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let taps = vec![1i64, 2, 3];
+    let b_filter = FilterFIR{ taps: &taps };
+    let b_stride = Stride { stride: 2 };
+
+    let scheduler = Scheduler::new();
+    let filtered = scheduler.run(&b_filter, DropNotify(0i64.., sender));
+    let mut strided = scheduler.run(&b_stride, filtered);
+    assert_eq!(strided.next(), Some(0i64));
+    drop(strided);
+
+    // the source never runs dry, so it's only dropped if both workers exit
+    receiver.recv_timeout(Duration::from_secs(10)).unwrap();
+}
+
+#[test]
+fn threaded_chain_panics() {
+    let taps = vec![1i64];
+    let b_filter = FilterFIR{ taps: &taps };
+    let scheduler = Scheduler::new();
+    let source = (0i64..).map(|x| if x < 5 { x } else { panic!("ran out at {}", x) });
+    let filtered = scheduler.run(&b_filter, source);
+    let strided = scheduler.run(&Stride { stride: 1 }, filtered);
+
+    // the stream ends early, losing the chunk the worker was filling, and
+    // waiting reports why
+    assert!(strided.count() <= 5);
+    match scheduler.wait() {
+        Err(Error::Panicked(message)) => assert_eq!(message, "ran out at 5"),
+        other => panic!("expected a panic, got {:?}", other),
+    }
+    assert!(scheduler.wait().is_ok());
+}
+
+#[test]
+fn threaded_split() {
+    let source = vec![0usize, 1, 2, 3, 4].into_iter();
+
+    let scheduler = Scheduler::new();
+    let (s1, s2) = scheduler.run_split(&Split, source);
+    let combined = scheduler.run(&Multiply, s1.zip(s2));
+    let collected: Vec<usize> = combined.collect();
+
+    assert_eq!(collected, vec![0usize, 1, 4, 9, 16]);
+}
+
+#[test]
+fn push_buffer_close() {
+    let (producer, mut consumer) = push_buffer(4);
+
+    assert_eq!(producer.push_slice(&[1usize, 2, 3, 4, 5]), Err(4));
+    assert_eq!(consumer.try_next(), Some(1usize));
+    producer.push_slice_blocking(&[5usize]).unwrap();
+    drop(producer);
+
+    let collected: Vec<usize> = consumer.collect();
+    assert_eq!(collected, vec![2usize, 3, 4, 5]);
+}
+
+#[test]
+fn push_buffer_threaded() {
+    let source: Vec<usize> = (0..100).collect();
+    let (producer, consumer) = push_buffer(16);
+
+    let to_send = source.clone();
+    thread::spawn(move|| {
+        for chunk in to_send.chunks(7) {
+            producer.push_slice_blocking(chunk).unwrap();
+        }
+    });
+
+    let collected: Vec<usize> = consumer.collect();
+    assert_eq!(collected, source);
+}
+
+#[test]
+#[should_panic(expected = "at least one element")]
+fn push_buffer_without_room() {
+    let (producer, _consumer) = push_buffer::<usize>(0);
+    let _ = producer.push_slice_blocking(&[1]);
+}
+
+#[test]
+fn push_buffer_waits() {
+    let source: Vec<usize> = (0..64).collect();
+    let (producer, consumer) = push_buffer(8);
+
+    // long enough for each side to give up spinning and go to sleep
+    let to_send = source.clone();
+    let guard = thread::spawn(move|| {
+        for chunk in to_send.chunks(16) {
+            producer.push_slice_blocking(chunk).unwrap();
+            thread::sleep(Duration::from_millis(5));
+        }
+    });
+
+    let mut collected = Vec::new();
+    for x in consumer {
+        collected.push(x);
+        if x % 20 == 0 {
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+    guard.join().unwrap();
+    assert_eq!(collected, source);
 }
 
 #[test]
 fn phase_differences() {
-    let phase_diffs = vec![0.3f32, 0.2, -2f32, 0f32];
-    let source = phase_diffs.iter().map(|&x| x);
+    let phase_diffs = [0.3f32, 0.2, -2f32, 0f32];
+    let source = phase_diffs.iter().copied();
 
-    connect!(samples <- FreqMod (source));
-    connect!(diffs <- PhaseDiffs (samples));
+    let samples = connect(&FreqMod, source);
+    let diffs = connect(&PhaseDiffs, samples);
 
     // assert that they're close enough
     let sse = phase_diffs.iter().zip(diffs).fold(0f32, |sse, (&b,c)| sse + (c - b) * (c - b));
     assert!(sse < 0.001f32);
 }
 
+#[test]
+fn sample_types() {
+    // complex samples multiply with each other, and with their part type either way round
+    let a = [Complex::new(1i16, 2), Complex::new(0, -3)];
+    let b = [Complex::new(3i16, -1), Complex::new(2, 2)];
+    let products: Vec<Complex<i16>> = a.into_iter().zip(b).multiply().collect();
+    assert_eq!(products, vec![Complex::new(5, 5), Complex::new(6, -6)]);
+    let scaled: Vec<Complex<f32>> = [2f32, -1.0].into_iter().zip([Complex::new(1f32, 1.0); 2]).multiply().collect();
+    assert_eq!(scaled, vec![Complex::new(2.0, 2.0), Complex::new(-1.0, -1.0)]);
+
+    assert_eq!(Complex::new(3f32, 4.0).magnitude(), 5.0);
+    assert_eq!(Complex::new(3i16, 4).conj(), Complex::new(3, -4));
+    assert_eq!((-7i64).magnitude(), 7.0);
+
+    // fixed-point arithmetic saturates instead of wrapping
+    assert_eq!(Q15::from_f64(2.0), Q15(i16::MAX));
+    let loud = [Q15::from_f64(0.75); 4];
+    let summed: Vec<Q15> = loud.into_iter().fir(&[Q15(i16::MAX); 2]).collect();
+    assert_eq!(summed[3], Q15(i16::MAX));
+    let resampled: Vec<Complex<Q15>> = [Complex::new(Q15(16384), Q15(-16384)); 2].into_iter()
+        .resample(2, 1, &[Q15(16384); 2]).collect();
+    assert_eq!(resampled[1], Complex::new(Q15(8192), Q15(-8192)));
+
+    // and so do integers, rather than overflowing
+    let full_scale = [Complex::new(i16::MAX, i16::MIN), Complex::new(i16::MIN, i16::MAX)];
+    let filtered: Vec<Complex<i16>> = full_scale.into_iter().cycle().take(8).fir(&[i16::MAX, 2, -3]).collect();
+    assert_eq!(filtered[0], Complex::new(i16::MAX, i16::MIN));
+    // each product saturates before it's added, MIN + MAX - MIN and MAX + MIN + MIN here
+    assert_eq!(filtered[7], Complex::new(i16::MAX - 1, i16::MIN));
+    let summed: Vec<i16> = [i16::MIN; 4].into_iter().fir(&[i16::MIN; 3]).collect();
+    assert_eq!(summed, vec![i16::MAX; 4]);
+    // even negating the most negative part
+    assert_eq!(Complex::new(0i16, i16::MIN).conj(), Complex::new(0, i16::MAX));
+    assert_eq!(Complex::new(0i16, i16::MIN).mul(Complex::new(0, 1)), Complex::new(i16::MAX, 0));
+    assert_eq!(Complex::new(Q15(0), Q15(i16::MIN)).conj(), Complex::new(Q15(0), Q15(i16::MAX)));
+}
+
+#[test]
+fn fluent_chain() {
+    let taps = vec![1i64, 2, 3];
+    let fluent: Vec<i64> = (0i64..20).fir(&taps).buffered(4).resample(3, 2, &taps).stride(2).collect();
+
+    let b_filter = FilterFIR{ taps: &taps };
+    let b_resampler = RationalResampler{ up: 3, down: 2, taps: &taps };
+    let b_stride = Stride { stride: 2 };
+    let filtered = connect(&b_filter, 0i64..20);
+    let resampled = connect(&b_resampler, filtered);
+    let connected: Vec<i64> = connect(&b_stride, resampled).collect();
+    assert_eq!(fluent, connected);
+
+    let squares: Vec<usize> = (0usize..4).split().multiply().collect();
+    assert_eq!(squares, vec![0usize, 1, 4, 9]);
+    let round_trip: Vec<usize> = (0usize..6).deinterleave().interleave().collect();
+    assert_eq!(round_trip, (0usize..6).collect::<Vec<usize>>());
+
+    let phase_diffs = [0.3f32, 0.2, -2f32, 0f32];
+    let diffs: Vec<f32> = phase_diffs.iter().copied().freq_mod().phase_diffs().collect();
+    let sse = phase_diffs.iter().zip(diffs).fold(0f32, |sse, (&b,c)| sse + (c - b) * (c - b));
+    assert!(sse < 0.001f32);
+}
+
+/// Drains an iterator with `fill`, using a different chunk size each time
+fn fill_all<I: ChunkedIterator>(mut iter: I, init: I::Item) -> Vec<I::Item>
+where I::Item: Clone {
+    let mut collected = Vec::new();
+    for size in (1usize..8).cycle() {
+        let mut chunk = vec![init.clone(); size];
+        let count = iter.fill(&mut chunk);
+        collected.extend_from_slice(&chunk[..count]);
+        if count < size {
+            return collected;
+        }
+    }
+    unreachable!()
+}
+
+#[test]
+fn chunked_matches_per_sample() {
+    let taps = vec![1i64, -1, 2, 3, 5];
+    let source = || (0i64..50).map(|x| x * x % 17);
+
+    let expected: Vec<i64> = source().fir(&taps).collect();
+    assert_eq!(fill_all(source().fir(&taps), 0), expected);
+
+    for &(up, down) in &[(1, 1), (2, 1), (1, 2), (3, 5), (5, 3)] {
+        let expected: Vec<i64> = source().resample(up, down, &taps).collect();
+        assert_eq!(fill_all(source().resample(up, down, &taps), 0), expected);
+    }
+
+    let expected: Vec<i64> = source().zip(source()).multiply().collect();
+    assert_eq!(fill_all(source().zip(source()).multiply(), 0), expected);
+
+    let phases = || (0..50).map(|x| x as f32 / 10.0);
+    let expected: Vec<Complex<f32>> = phases().freq_mod().collect();
+    let chunked = fill_all(phases().freq_mod(), Complex::new(0.0, 0.0));
+    assert_eq!(chunked.len(), expected.len());
+    assert!(chunked.iter().zip(expected).all(|(a, b)| (a - b).norm() < 1e-5));
+}
+
+#[test]
+fn tag_propagation() {
+    let taps = vec![1i64, 2, 3];
+    let source = insert_tags(0i64..40, vec![(0, Tag::SampleRate(1e3)),
+                                            (10, Tag::CenterFrequency(100e6)),
+                                            (11, Tag::BurstStart)]);
+
+    let filtered = WithTags(FilterFIR{ taps: &taps }).process(source);
+    let upsampled = WithTags(RationalResampler{ up: 2, down: 1, taps: &taps }).process(filtered);
+    let strided = WithTags(Stride{ stride: 4 }).process(upsampled);
+    let (s1, s2) = split(connect(&Split, strided));
+    let together: Vec<Tagged<i64>> = connect(&Interleave, join((s1, s2))).collect();
+
+    // input sample 10 is upsampled to 20, and 11 to 22, which stride 4 takes to 5 and 6
+    let tagged: Vec<(usize, &Vec<Tag>)> = together.iter().map(|x| &x.tags).enumerate()
+        .filter(|(_, tags)| !tags.is_empty()).collect();
+    assert_eq!(tagged, vec![(0, &vec![Tag::SampleRate(1e3)]), (1, &vec![Tag::SampleRate(1e3)]),
+                            (10, &vec![Tag::CenterFrequency(100e6)]), (11, &vec![Tag::CenterFrequency(100e6)]),
+                            (12, &vec![Tag::BurstStart]), (13, &vec![Tag::BurstStart])]);
+
+    let samples: Vec<i64> = together.into_iter().untagged().step_by(2).collect();
+    let expected: Vec<i64> = (0i64..40).fir(&taps).resample(2, 1, &taps).stride(4).collect();
+    assert_eq!(samples, expected);
+}
+
 #[test]
 // This tests that, at upsample = downsample = 1, the RationalResampler
 // is just an FIR filter.
 fn resampler_is_filter() {
-    let taps = vec![1i, -1i, 2, 3];
-    let source = iter::count(0i, 1).take(10);
+    let taps = vec![1i64, -1i64, 2, 3];
+    let source = (0i64..).take(10);
     let source_copy = source.clone();
 
-    let b_filter = FilterFIR{ taps: taps.as_slice() };
-    let b_resampler = RationalResampler{ up: 1, down: 1, taps: taps.as_slice() };
+    let b_filter = FilterFIR{ taps: &taps };
+    let b_resampler = RationalResampler{ up: 1, down: 1, taps: &taps };
 
-    connect!(fir_filtered <- b_filter (source_copy));
-    connect!(resampler_filtered <- b_resampler (source));
+    let fir_filtered = connect(&b_filter, source_copy);
+    let resampler_filtered = connect(&b_resampler, source);
 
-    let fir_filtered: Vec<int> = fir_filtered.collect();
-    let resampler_filtered: Vec<int> = resampler_filtered.collect();
+    let fir_filtered: Vec<i64> = fir_filtered.collect();
+    let resampler_filtered: Vec<i64> = resampler_filtered.collect();
     assert_eq!(fir_filtered, resampler_filtered);
 }
 
 #[test]
 fn test_hamming() {
-    let num_taps = 10u;
+    let num_taps = 10usize;
     let window = HammingWindow.time_domain_taps(num_taps);
     // from numpy.hamming
     let correct = vec![0.08, 0.18761956, 0.46012184, 0.77,
@@ -104,7 +504,7 @@ fn test_hamming_low_pass() {
     let cutoff = 20e3;
     let num_taps = 13; //transition width of 10e3 Hz;
 
-    let taps = low_pass_filter_taps(HammingWindow, cutoff / fs, NumTapsSpecifier::NumTaps(num_taps));
+    let taps = low_pass_filter_taps(HammingWindow, cutoff / fs, NumTapsSpecifier::NumTaps(num_taps)).unwrap();
     // from gnuradio
     let correct_taps = vec![0.0024871660862118006, -4.403502608370943e-18, -0.014456653036177158,
         0.0543283149600029, -0.116202212870121, 0.17504146695137024,
@@ -116,45 +516,790 @@ fn test_hamming_low_pass() {
     let sse = taps.iter().zip(correct_taps.iter())
                   .fold(0f32, |sse, (&b,&c)| sse + (c - b) * (c - b));
     assert!(sse < 0.001f32);
+
+    // parameters a filter can't be designed with are errors, not panics
+    for (bandwidth, num_taps) in [(0.2, NumTapsSpecifier::TransitionWidth(0.1)),
+                                  (0.2, NumTapsSpecifier::NumTaps(0)),
+                                  (0.7, NumTapsSpecifier::NumTaps(13))] {
+        assert!(matches!(low_pass_filter_taps(HammingWindow, bandwidth, num_taps),
+                         Err(Error::InvalidParameter(_))));
+    }
+}
+
+#[test]
+fn invalid_block_parameters() {
+    let taps = [1f32; 4];
+    assert!(matches!(Stride::new(0), Err(Error::InvalidParameter(_))));
+    assert_eq!(Stride::new(3).unwrap().stride, 3);
+    assert!(matches!(RationalResampler::new(0, 2, &taps), Err(Error::InvalidParameter(_))));
+    assert!(matches!(RationalResampler::new(3, 0, &taps), Err(Error::InvalidParameter(_))));
+    assert!(RationalResampler::new(3, 2, &taps).is_ok());
+    let demod = FmDemod { channel_taps: &taps, up: 0, down: 1, resampler_taps: &taps };
+    assert!(matches!(demod.validate(), Err(Error::InvalidParameter(_))));
+    assert!(matches!(Deframer::new(&[], 2, MessagePort::new()), Err(Error::InvalidParameter(_))));
+    assert!(Deframer::new(&[0, 1], 2, MessagePort::new()).is_ok());
+}
+
+#[test]
+fn sample_rate_propagation() {
+    let source = (0i64..).with_sample_rate(50e3);
+    assert_eq!(source.sample_rate(), 50e3);
+
+    let taps = vec![1i64, 2, 1];
+    let upsampled = source.through(&RationalResampler{ up: 3, down: 2, taps: &taps }).unwrap();
+    assert_eq!(upsampled.sample_rate(), 75e3);
+    let strided = upsampled.through(&Stride{ stride: 5 }).unwrap();
+    assert_eq!(strided.sample_rate(), 15e3);
+    let filtered = strided.through(&FilterFIR{ taps: &taps }).unwrap();
+    assert_eq!(filtered.sample_rate(), 15e3);
+
+    let expected: Vec<i64> = (0i64..).resample(3, 2, &taps).stride(5).fir(&taps).take(10).collect();
+    let collected: Vec<i64> = filtered.take(10).collect();
+    assert_eq!(collected, expected);
+
+    // parameters that don't make a rate are an error, not a panic
+    let rated = Rated::new(std::iter::repeat(0i64), 50e3);
+    assert!(matches!(rated.through(&RationalResampler{ up: 1, down: 0, taps: &taps }), Err(Error::InvalidParameter(_))));
+    let mut graph = RateGraph::new();
+    let source = graph.source("source");
+    assert!(matches!(graph.block("stride", &Stride { stride: 0 }, &[source]), Err(Error::InvalidParameter(_))));
+
+    let rated = Rated::new(std::iter::repeat(0f32), 50e3);
+    let taps = rated.low_pass_filter_taps(HammingWindow, 20e3, NumTapsSpecifier::NumTaps(13)).unwrap();
+    assert_eq!(taps, low_pass_filter_taps(HammingWindow, 0.4, NumTapsSpecifier::NumTaps(13)).unwrap());
+}
+
+#[test]
+fn runtime_control() {
+    let taps = vec![1i64, 1];
+    let (mut filtered, control) = FilterFIR{ taps: &taps }.process_controlled(0i64..);
+    let before: Vec<i64> = filtered.by_ref().take(4).collect();
+    control.set(vec![1, 0, 10]).unwrap();
+    let after: Vec<i64> = filtered.take(3).collect();
+    assert_eq!(before, vec![0i64, 1, 3, 5]);
+    // the old filter only kept one sample of history, so 2 is missing from the first output
+    assert_eq!(after, vec![4i64, 5 + 10 * 3, 6 + 10 * 4]);
+
+    let taps = vec![1i64];
+    let (mut resampled, control) = RationalResampler{ up: 2, down: 1, taps: &taps }
+        .process_controlled(0i64..);
+    let before: Vec<i64> = resampled.by_ref().take(3).collect();
+    control.set(ResamplerSettings { up: 1, down: 2, taps: vec![1] }).unwrap();
+    let after: Vec<i64> = resampled.by_ref().take(3).collect();
+    // the rest of the upsampled output of sample 1 is dropped, and decimation starts at 2
+    assert_eq!(before, vec![0i64, 0, 1]);
+    assert_eq!(after, vec![2i64, 4, 6]);
+    // settings the resampler can't use are rejected, and it carries on as before
+    let rejected = control.set(ResamplerSettings { up: 0, down: 1, taps: vec![1] });
+    assert!(matches!(rejected, Err(Error::InvalidParameter(_))));
+    assert_eq!(resampled.next(), Some(8i64));
+
+    // the stride can be changed from another thread while a worker runs it
+    let (strided, control) = Stride{ stride: 1 }.process_controlled((0usize..).take(50));
+    let mut scheduler = Scheduler::new();
+    scheduler.capacity = 1;
+    scheduler.chunk_size = 1;
+    let mut strided = scheduler.run_iter(strided);
+    let before: Vec<usize> = strided.by_ref().take(1).collect();
+    control.set(10).unwrap();
+    let after: Vec<usize> = strided.collect();
+    assert_eq!(before, vec![0usize]);
+    // a few samples were already in flight when the stride changed
+    assert!(after.len() < 10);
+    assert_eq!(after.windows(2).last().map(|w| w[1] - w[0]), Some(10));
+}
+
+/// Turns bytes into bits, most significant bit first
+fn to_bits(bytes: &[u8]) -> Vec<u8> {
+    bytes.iter().flat_map(|&b| (0..8).rev().map(move |i| (b >> i) & 1)).collect()
+}
+
+#[test]
+fn deframer_messages() {
+    let access_code = to_bits(&[0x7e]);
+    let mut bits = vec![1u8, 0, 1];
+    for packet in [[0xdeu8, 0xad], [0xbe, 0xef]].iter() {
+        bits.extend(&access_code);
+        bits.extend(to_bits(packet));
+        bits.extend(&[0u8, 0, 0, 0]);
+    }
+
+    // pull-based: messages are published as the iterator is driven
+    let port = MessagePort::new();
+    let packets = port.subscribe();
+    let deframer = Deframer { access_code: &access_code, payload_len: 2, packets: port.clone() };
+    let mut passed = deframer.process(bits.clone().into_iter());
+    assert_eq!(packets.try_recv(), None);
+    let passed_count = passed.by_ref().take(3 + 8 + 16).count();
+    assert_eq!(passed_count, 27);
+    assert_eq!(packets.try_recv(), Some(vec![0xde, 0xad]));
+    assert_eq!(passed.collect::<Vec<u8>>().len(), bits.len() - 27);
+    assert_eq!(packets.try_recv(), Some(vec![0xbe, 0xef]));
+
+    // threaded: the deframer runs on a worker, and the subscription ends with it
+    let port = MessagePort::new();
+    let packets = port.subscribe();
+    let deframer = Deframer { access_code: &access_code, payload_len: 2, packets: port };
+    let passed: Vec<u8> = Scheduler::new().run(&deframer, bits.clone().into_iter()).collect();
+    drop(deframer);
+    assert_eq!(passed, bits);
+    assert_eq!(packets.collect::<Vec<Vec<u8>>>(), vec![vec![0xde, 0xad], vec![0xbe, 0xef]]);
+}
+
+/// A hierarchical block with split buffers inside, that squares its input
+struct Square;
+type SquareSplit<I> = SplitIter<I>;
+type SquareIter<I> = MultiplyIter<Zip<FixedBuffer2First<i64, i64, SquareSplit<I>>,
+                                      FixedBuffer2Second<i64, i64, SquareSplit<I>>>>;
+impl<I: Iterator<Item = i64>> RadioBlock<I> for Square {
+    type Input = i64;
+    type Output = i64;
+    type Iter = SquareIter<I>;
+    const INPUTS: usize = 1;
+    const OUTPUTS: usize = 1;
+
+    fn process(&self, input: I) -> SquareIter<I> {
+        let (s1, s2) = split(Split.process(input));
+        Multiply.process(join((s1, s2)))
+    }
+}
+impl BlockRate for Square {
+    fn rate(&self) -> Result<Ratio<usize>, Error> { Ok(Ratio::from_integer(1)) }
+}
+
+#[test]
+fn hierarchical_blocks() {
+    let taps = vec![1i64, 2];
+    let nested = Then { first: Square, second: Then { first: FilterFIR{ taps: &taps }, second: Stride{ stride: 3 } } };
+    let collected: Vec<i64> = connect(&nested, 0i64..10).collect();
+    let expected: Vec<i64> = (0i64..10).map(|x| x * x).fir(&taps).stride(3).collect();
+    assert_eq!(collected, expected);
+    assert_eq!(nested.rate().unwrap(), Ratio::new(1, 3));
+
+    let channel_taps = low_pass_filter_taps(HammingWindow, 0.2, NumTapsSpecifier::NumTaps(15)).unwrap();
+    let resampler_taps = low_pass_filter_taps(HammingWindow, 0.1, NumTapsSpecifier::NumTaps(31)).unwrap();
+    let demod = FmDemod { channel_taps: &channel_taps, up: 2, down: 5, resampler_taps: &resampler_taps };
+    let phases = || (0..200).map(|x| (x as f32 / 20.0).sin() * 0.1).freq_mod();
+    let collected: Vec<f32> = demod.process(phases()).collect();
+    let expected: Vec<f32> = phases().fir(&channel_taps).resample(2, 5, &resampler_taps).phase_diffs().collect();
+    assert_eq!(collected, expected);
+
+    // without split buffers inside, it can be run on a thread like any other block
+    let threaded: Vec<f32> = Scheduler::new().run(&demod, phases()).collect();
+    assert_eq!(threaded, expected);
+}
+
+#[test]
+fn flowgraph_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.f32");
+    let output = dir.path().join("output.f32");
+    let samples: Vec<f32> = (0..100).map(|x| (x as f32 / 10.0).sin()).collect();
+    file_write_stream(&input, samples.iter().copied()).unwrap();
+
+    let graph = format!(r#"
+        connections = [["source", "split"], ["split:0", "filter"], ["filter", "stride"],
+                       ["split:1", "stride2"], ["stride", "mult:0"], ["stride2", "mult:1"],
+                       ["mult", "sink"]]
+
+        [[blocks]]
+        name = "source"
+        type = "file_source"
+        path = "{}"
+        item = "f32"
+
+        [[blocks]]
+        name = "split"
+        type = "split"
+
+        [[blocks]]
+        name = "filter"
+        type = "fir"
+        taps = {{ low_pass = {{ cutoff = 10e3, sample_rate = 50e3, num_taps = 7 }} }}
+
+        [[blocks]]
+        name = "stride"
+        type = "stride"
+        stride = 2
+
+        [[blocks]]
+        name = "stride2"
+        type = "stride"
+        stride = 2
+
+        [[blocks]]
+        name = "mult"
+        type = "multiply"
+
+        [[blocks]]
+        name = "sink"
+        type = "file_sink"
+        path = "{}"
+    "#, input.display(), output.display());
+
+    let registry = Registry::new();
+    FlowgraphSpec::from_toml(&graph).unwrap().check(&registry).unwrap().run().unwrap();
+
+    let taps = low_pass_filter_taps(HammingWindow, 0.2, NumTapsSpecifier::NumTaps(7)).unwrap();
+    let filtered = samples.iter().copied().fir(&taps).stride(2);
+    let expected: Vec<f32> = filtered.zip(samples.iter().copied().stride(2)).multiply().collect();
+    let collected: Vec<f32> = file_read_stream(&output).unwrap().collect();
+    assert_eq!(collected, expected);
+
+    // the connections are type checked before anything runs
+    let graph = r#"{
+        "blocks": [
+            {"name": "source", "type": "file_source", "path": "missing.u8", "item": "u8"},
+            {"name": "demod", "type": "phase_diffs"},
+            {"name": "sink", "type": "file_sink", "path": "out.f32"}
+        ],
+        "connections": [["source", "demod"], ["demod", "sink"]]
+    }"#;
+    match FlowgraphSpec::from_json(graph).unwrap().check(&registry) {
+        Err(Error::Type { block, .. }) => assert_eq!(block, "demod"),
+        _ => panic!("expected a type error"),
+    }
+
+    // so are parameter names, so a misspelling isn't quietly ignored
+    let graph = r#"{
+        "blocks": [
+            {"name": "source", "type": "file_source", "path": "missing.u8", "item": "u8"},
+            {"name": "stride", "type": "stride", "strde": 2},
+            {"name": "sink", "type": "file_sink", "path": "out.u8"}
+        ],
+        "connections": [["source", "stride"], ["stride", "sink"]]
+    }"#;
+    match FlowgraphSpec::from_json(graph).unwrap().check(&registry) {
+        Err(Error::InvalidParameter(message)) => assert!(message.contains("strde"), "{}", message),
+        _ => panic!("expected an unknown parameter"),
+    }
+    let graph = r#"{
+        "blocks": [
+            {"name": "source", "type": "file_source", "path": "missing.f32", "item": "f32"},
+            {"name": "fir", "type": "fir", "taps": {"low_pass": {"cutof": 0.2}}},
+            {"name": "sink", "type": "file_sink", "path": "out.f32"}
+        ],
+        "connections": [["source", "fir"], ["fir", "sink"]]
+    }"#;
+    match FlowgraphSpec::from_json(graph).unwrap().check(&registry) {
+        Err(Error::InvalidParameter(message)) => assert!(message.contains("cutof"), "{}", message),
+        _ => panic!("expected an unknown parameter"),
+    }
+
+    // messages can be received from a loaded flowgraph
+    let bits = dir.path().join("bits.u8");
+    let mut data = to_bits(&[0x7e, 0x12, 0x34]);
+    data.extend(&[0, 1]);
+    file_write_stream(&bits, data.into_iter()).unwrap();
+    let graph = format!(r#"{{
+        "blocks": [
+            {{"name": "source", "type": "file_source", "path": "{}", "item": "u8"}},
+            {{"name": "deframer", "type": "deframer", "access_code": "01111110", "payload_len": 2}},
+            {{"name": "sink", "type": "file_sink", "path": "{}"}}
+        ],
+        "connections": [["source", "deframer"], ["deframer", "sink"]]
+    }}"#, bits.display(), dir.path().join("bits_out.u8").display());
+    let mut graph = FlowgraphSpec::from_json(&graph).unwrap().check(&registry).unwrap();
+    let packets = graph.subscribe("deframer");
+    graph.run().unwrap();
+    assert_eq!(packets.collect::<Vec<Vec<u8>>>(), vec![vec![0x12, 0x34]]);
+
+    // a block that panics fails the run, rather than just ending its stream
+    fn same_types(_: &BlockSpec, inputs: &[ItemType]) -> Result<Vec<ItemType>, Error> {
+        Ok(inputs.to_vec())
+    }
+    fn build_panics(_: &BlockSpec, mut inputs: Vec<DynStream>, context: &mut BuildContext)
+        -> Result<Built, Error> {
+        let DynStream::Byte(s) = inputs.remove(0) else { unreachable!() };
+        let panics = s.map(|_| -> u8 { panic!("bad bit") });
+        Ok(Built::Streams(vec![DynStream::Byte(Box::new(context.scheduler.run(&Stride { stride: 1 }, panics)))]))
+    }
+    let mut registry = Registry::new();
+    registry.register("panics", BlockFactory { params: &[], output_types: same_types, build: build_panics });
+    let graph = format!(r#"{{
+        "blocks": [
+            {{"name": "source", "type": "file_source", "path": "{}", "item": "u8"}},
+            {{"name": "panics", "type": "panics"}},
+            {{"name": "sink", "type": "file_sink", "path": "{}"}}
+        ],
+        "connections": [["source", "panics"], ["panics", "sink"]]
+    }}"#, bits.display(), dir.path().join("bits_out.u8").display());
+    match FlowgraphSpec::from_json(&graph).unwrap().check(&registry).unwrap().run() {
+        Err(Error::Panicked(message)) => assert_eq!(message, "bad bit"),
+        other => panic!("expected a panic, got {:?}", other),
+    }
+}
+
+#[test]
+fn instrumentation() {
+    let stats = Stats::new();
+
+    // a block's samples are counted on the way in and out
+    let strided: Vec<usize> = stats.block("stride").process(&Stride{ stride: 4 }, 0usize..100).collect();
+    assert_eq!(strided.len(), 25);
+
+    // the fill level of a fixed buffer peaks after each refill
+    let buffered = buffer_fixed(0usize..10, 4).with_stats(stats.buffer("fixed", 4));
+    assert_eq!(buffered.count(), 10);
+
+    // a push that doesn't fit is an overflow
+    let (producer, consumer) = push_buffer(4);
+    let mut consumer = consumer.with_stats(stats.buffer("push", 4));
+    assert_eq!(producer.push_slice(&[1usize, 2, 3]), Ok(()));
+    assert_eq!(consumer.try_next(), Some(1));
+    assert_eq!(producer.push_slice(&[4usize, 5, 6, 7]), Err(2));
+
+    // and so is one output of a split falling too far behind
+    let (mut first, second) = split_fixed((0usize..).map(|x| (x, x)), 4, 4);
+    let second = second.with_stats(stats.buffer("split", 4));
+    for _ in 0..4 {
+        first.try_next().unwrap();
+    }
+    assert_eq!(first.try_next(), Err(BufferError::Overrun(4)));
+
+    let snapshot = stats.snapshot();
+    let block = &snapshot.blocks[0];
+    assert_eq!((block.name.as_str(), block.items_in, block.items_out), ("stride", 100, 25));
+    let levels: Vec<_> = snapshot.buffers.iter()
+        .map(|b| (b.name.as_str(), b.len, b.high_water, b.capacity, b.overflows))
+        .collect();
+    assert_eq!(levels, vec![("fixed", 0, 4, 4, 0), ("push", 4, 4, 4, 1), ("split", 4, 4, 4, 1)]);
+    let report = snapshot.to_string();
+    assert!(report.contains("stride"));
+    assert!(report.contains("high water"));
+    drop(second);
+
+    let (sender, reports) = std::sync::mpsc::channel();
+    let reporter = stats.report_every(std::time::Duration::from_millis(1), move |snapshot| {
+        let _ = sender.send(snapshot.buffers.len());
+    });
+    assert_eq!(reports.recv(), Ok(3));
+    drop(reporter);
+}
+
+#[test]
+fn dot_export() {
+    let taps = [1f32; 5];
+    let mut graph = DotGraph::new();
+    let source = graph.source("source", "Samples", (0..40).map(|x| Complex::new(x as f32, 0.)));
+    let pairs = graph.connect("split", &Split, source);
+    let (s1, s2) = graph.split_with_capacity(pairs, 64);
+    let filtered = graph.connect_with_capacity("filter", &FilterFIR{ taps: &taps }, s1, 32);
+    let resampled = graph.connect_with_capacity("resampler", &Then{
+        first: Stride{ stride: 2 },
+        second: RationalResampler{ up: 3, down: 4, taps: &taps },
+    }, s2, 32);
+    let joined = graph.join((filtered, resampled));
+    let product = graph.connect_with_capacity("mult", &Multiply, joined, 128);
+    assert_eq!(graph.sink("sink", "Collect", product).count(), 15);
+    assert_eq!(graph.to_dot(), "\
+digraph flowgraph {
+    rankdir=LR;
+    node [shape=box];
+    n0 [label=\"source\\nSamples\"];
+    n1 [label=\"split\\nSplit\"];
+    n2 [label=\"filter\\nFilterFIR\\ntaps: 5\"];
+    n3 [label=\"resampler\\nStride -> RationalResampler\\nstride: 2\\nup: 3\\ndown: 4\\ntaps: 5\"];
+    n4 [label=\"mult\\nMultiply\"];
+    n5 [label=\"sink\\nCollect\"];
+    n0 -> n1 [label=\"Complex<f32>\\n2048\"];
+    n1 -> n2 [label=\"Complex<f32>\\n64, 32\", taillabel=\"0\"];
+    n1 -> n3 [label=\"Complex<f32>\\n64, 32\", taillabel=\"1\"];
+    n2 -> n4 [label=\"Complex<f32>\\n128\", headlabel=\"0\"];
+    n3 -> n4 [label=\"Complex<f32>\\n128\", headlabel=\"1\"];
+    n4 -> n5 [label=\"Complex<f32>\"];
+}
+");
+
+    // a source of tuples is split into one stream per element
+    let mut graph = DotGraph::new();
+    let source = graph.source("pairs", "Pairs", (0..4).map(|x| (Tagged { sample: x as f32, tags: Vec::new() }, x as u8)));
+    let (floats, bytes) = graph.split(source);
+    let floats = graph.connect("tagged", &WithTags(FilterFIR{ taps: &taps }), floats);
+    assert_eq!(graph.sink("floats", "Collect", floats).count(), 4);
+    assert_eq!(graph.sink("bytes", "Collect", bytes).count(), 4);
+    let dot = graph.to_dot();
+    assert!(dot.contains("n0 -> n1 [label=\"Tagged<f32>\\n2048, 2048\"];"));
+    assert!(dot.contains("n0 -> n3 [label=\"u8\\n2048\"];"));
+    assert!(dot.contains("n1 [label=\"tagged\\nWithTags<FilterFIR>\\ntaps: 5\"];"));
+
+    // a loaded flowgraph is drawn the same way, with the buffers `run` would use
+    let graph = r#"{
+        "blocks": [
+            {"name": "source", "type": "file_source", "path": "in.cf32", "item": "complex"},
+            {"name": "filter", "type": "fir", "taps": [0.25, 0.5, 0.25]},
+            {"name": "demod", "type": "phase_diffs"},
+            {"name": "sink", "type": "file_sink", "path": "out.f32"}
+        ],
+        "connections": [["source", "filter"], ["filter", "demod"], ["demod", "sink"]]
+    }"#;
+    let registry = Registry::new();
+    let dot = FlowgraphSpec::from_json(graph).unwrap().check(&registry).unwrap().to_dot();
+    assert!(dot.contains("n1 [label=\"filter\\nfir\\ntaps: 3\"];"));
+    assert!(dot.contains("n0 -> n1 [label=\"complex\\n2048\"];"));
+    assert!(dot.contains("n2 -> n3 [label=\"f32\\n2048\"];"));
+}
+
+/// A reader that returns at most `step` bytes from each `read`
+struct Trickle<'a> {
+    bytes: &'a [u8],
+    step: usize,
+}
+
+impl std::io::Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = buf.len().min(self.step).min(self.bytes.len());
+        buf[..n].copy_from_slice(&self.bytes[..n]);
+        self.bytes = &self.bytes[n..];
+        Ok(n)
+    }
+}
+
+/// A reader that always fails
+struct Broken;
+
+impl std::io::Read for Broken {
+    fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+        Err(std::io::Error::other("broken"))
+    }
+}
+
+#[test]
+fn stream_elements() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("stream");
+
+    // more than a chunk, with part of an element at the end
+    let source: Vec<u16> = (0..100000).map(|x| x as u16).collect();
+    file_write_stream(&path, source.iter().copied()).unwrap();
+    let mut bytes = std::fs::read(&path).unwrap();
+    assert_eq!(bytes.len(), 200000);
+    bytes.push(1);
+    std::fs::write(&path, &bytes).unwrap();
+    let read: Vec<u16> = file_read_stream(&path).unwrap().collect();
+    assert_eq!(read, source);
+    assert_eq!(fill_all(file_read_stream::<u16>(&path).unwrap(), 0), source);
+
+    // elements split across reads
+    let trickle = Trickle { bytes: &bytes[..40], step: 3 };
+    let read: Vec<[u16; 4]> = read_stream(trickle).collect();
+    assert_eq!(read, vec![[0, 1, 2, 3], [4, 5, 6, 7], [8, 9, 10, 11], [12, 13, 14, 15], [16, 17, 18, 19]]);
+    let trickle = Trickle { bytes: &bytes[..40], step: 3 };
+    let read: Vec<Complex<i16>> = read_stream(trickle).collect();
+    assert_eq!(read[2], Complex::new(4, 5));
+
+    // a failed read ends the stream, and is kept for `check`
+    let mut stream = read_stream::<u16, _>(std::io::Read::chain(&bytes[..6], Broken));
+    assert_eq!(stream.by_ref().collect::<Vec<_>>(), vec![0, 1, 2]);
+    assert!(matches!(stream.check(), Err(Error::Io(_))));
+    assert!(stream.check().is_ok());
+    let mut stream = read_stream::<u16, _>(&bytes[..6]);
+    assert_eq!(stream.by_ref().count(), 3);
+    assert!(stream.check().is_ok());
+
+    let source = [[Complex::new(1.5f64, -2.0); 3], [Complex::new(0.0, 7.0); 3]];
+    file_write_stream(&path, source.into_iter()).unwrap();
+    let read: Vec<[Complex<f64>; 3]> = file_read_stream(&path).unwrap().collect();
+    assert_eq!(read, source);
+}
+
+#[test]
+fn mmap_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("capture.cf32");
+    let samples: Vec<Complex<f32>> = (0..1000).map(|x| Complex::new(x as f32, -x as f32)).collect();
+    file_write_stream(&path, samples.iter().copied()).unwrap();
+
+    let file = MmapFile::<Complex<f32>>::open(&path).unwrap();
+    assert_eq!(file.len(), 1000);
+    assert_eq!(file.as_slice(), &samples[..]);
+    assert_eq!(file.iter().collect::<Vec<_>>(), samples);
+
+    // seeking by index and by time
+    let mut source = file.iter();
+    source.seek(990);
+    assert_eq!(source.position(), 990);
+    assert_eq!(source.collect::<Vec<_>>(), &samples[990..]);
+    let mut source = file.iter();
+    source.seek_time(Duration::from_millis(250), 1000.0);
+    assert_eq!(source.next(), Some(samples[250]));
+    source.seek(5000);
+    assert_eq!(source.next(), None);
+
+    // ranges, and ranges of ranges, count from their own start
+    let window = file.range(100..200);
+    assert_eq!(window.len(), 100);
+    assert_eq!(window.collect::<Vec<_>>(), &samples[100..200]);
+    let mut inner = file.range(100..200).range(10..=12);
+    assert_eq!(inner.len(), 3);
+    inner.seek(1);
+    assert_eq!(inner.collect::<Vec<_>>(), &samples[111..113]);
+
+    // looping goes back to the start of the range, from next and from fill
+    let looped: Vec<_> = file.range(998..).looping().take(5).collect();
+    assert_eq!(looped, vec![samples[998], samples[999], samples[998], samples[999], samples[998]]);
+    let mut looping = file.range(0..3).looping();
+    let mut chunk = vec![Complex::default(); 7];
+    assert_eq!(looping.fill(&mut chunk), 7);
+    assert_eq!(chunk, [0, 1, 2, 0, 1, 2, 0].map(|i| samples[i]));
+    assert_eq!(file.range(5..5).looping().next(), None);
+    assert_eq!(fill_all(file.range(500..), Complex::default()), &samples[500..]);
+
+    // the same file as another type, and a partial element at the end
+    let floats = MmapFile::<f32>::open(&path).unwrap();
+    assert_eq!(floats.len(), 2000);
+    assert_eq!(floats.as_slice()[3], -1.0);
+    let bytes = MmapFile::<[u8; 3]>::open(&path).unwrap();
+    assert_eq!(bytes.len(), 8000 / 3);
+    std::fs::write(&path, []).unwrap();
+    assert!(MmapFile::<f32>::open(&path).unwrap().is_empty());
+    assert!(MmapFile::<f32>::open(&dir.path().join("missing")).is_err());
+}
+
+#[test]
+fn wav_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("test.wav");
+    let samples: Vec<f32> = (0..101).map(|x| (x as f32 / 10.0).sin() * 0.9).collect();
+    let formats = [(WavFormat::Pcm8, 1e-2), (WavFormat::Pcm16, 1e-4), (WavFormat::Pcm24, 1e-6),
+                   (WavFormat::Pcm32, 1e-6), (WavFormat::Float32, 0.0), (WavFormat::Float64, 0.0)];
+    for (format, tolerance) in formats {
+        file_write_wav(&path, format, 48000, samples.iter().copied()).unwrap();
+        let read = file_read_wav::<f32>(&path).unwrap();
+        assert_eq!(read.spec().format, format);
+        assert_eq!(read.spec().sample_rate, 48000);
+        assert_eq!(read.len(), samples.len());
+        let read: Vec<f32> = read.collect();
+        assert_eq!(read.len(), samples.len());
+        for (a, b) in read.iter().zip(&samples) {
+            assert!((a - b).abs() <= tolerance, "{:?}: {} != {}", format, a, b);
+        }
+    }
+
+    // out of range samples are clipped
+    file_write_wav(&path, WavFormat::Pcm16, 8000, [2.0f32, -2.0].into_iter()).unwrap();
+    let read: Vec<f32> = file_read_wav(&path).unwrap().collect();
+    assert_eq!(read, vec![32767.0 / 32768.0, -1.0]);
+
+    // stereo files are pairs, which interleave into one stream
+    let left = samples.iter().copied();
+    let right = samples.iter().map(|x| -x);
+    file_write_wav(&path, WavFormat::Float32, 8000, left.zip(right)).unwrap();
+    let stereo = file_read_wav::<(f32, f32)>(&path).unwrap();
+    let together: Vec<f32> = connect(&Interleave, stereo).collect();
+    assert_eq!(together.len(), 2 * samples.len());
+    assert_eq!(&together[..4], &[samples[0], -samples[0], samples[1], -samples[1]]);
+    let pairs: Vec<(f32, f32)> = connect(&DeInterleave, together.into_iter()).collect();
+    assert_eq!(pairs, samples.iter().map(|&x| (x, -x)).collect::<Vec<_>>());
+
+    // and the same file can be read as I/Q samples
+    let iq: Vec<Complex<f32>> = file_read_wav(&path).unwrap().collect();
+    assert_eq!(iq[1], Complex::new(samples[1], -samples[1]));
+    assert!(matches!(file_read_wav::<f32>(&path), Err(Error::InvalidParameter(_))));
+
+    // 24 bit samples from another tool, in a WAVE_FORMAT_EXTENSIBLE file with an extra chunk
+    let mut file = b"RIFF\0\0\0\0WAVEfmt \x28\0\0\0\xfe\xff\x01\0\x44\xac\0\0\0\0\0\0\x03\0\x18\0".to_vec();
+    file.extend([22, 0, 24, 0, 4, 0, 0, 0, 1, 0]);
+    file.extend([0u8; 14]);
+    file.extend(b"LIST\x03\0\0\0abc\0data\x06\0\0\0\0\0\x80\0\0\x40");
+    let read = read_wav::<f32, _>(file.as_slice()).unwrap();
+    assert_eq!(read.spec().format, WavFormat::Pcm24);
+    assert_eq!(read.spec().sample_rate, 44100);
+    assert_eq!(read.collect::<Vec<f32>>(), vec![-1.0, 0.5]);
+    assert!(matches!(read_wav::<f32, _>(&b"RIFF\0\0\0\0AVI "[..]), Err(Error::Io(_))));
+    // a fmt chunk claiming to be huge isn't read into memory
+    let huge = b"RIFF\0\0\0\0WAVEfmt \xff\xff\xff\xff\x01\0\x01\0";
+    assert!(matches!(read_wav::<f32, _>(&huge[..]), Err(Error::Io(_))));
+
+    // an odd number of bytes of samples is padded, and the RIFF size counts the pad
+    file_write_wav(&path, WavFormat::Pcm8, 8000, [0.5f32, 0.0, -0.5].into_iter()).unwrap();
+    let bytes = std::fs::read(&path).unwrap();
+    assert_eq!(bytes.len(), 48);
+    assert_eq!(&bytes[4..8], &40u32.to_le_bytes());
+    assert_eq!(file_read_wav::<f32>(&path).unwrap().len(), 3);
+
+    // the byte rate has to fit in the header
+    let rate = file_write_wav(&path, WavFormat::Float64, u32::MAX, samples.iter().copied());
+    assert!(matches!(rate, Err(Error::InvalidParameter(_))));
+}
+
+#[test]
+fn sample_formats() {
+    // the same two samples, 0.5 - 0.25j and -1 + 0j, as each tool writes them
+    let expected = vec![Complex::new(0.5f32, -0.25), Complex::new(-1.0, 0.0)];
+    let files: [(SampleFormat, &[u8]); 6] = [
+        (SampleFormat::CU8, &[192, 96, 0, 128]),
+        (SampleFormat::CS8, &[64, 0xe0, 0x80, 0]),
+        (SampleFormat::CS16_LE, &[0, 0x40, 0, 0xe0, 0, 0x80, 0, 0]),
+        (SampleFormat::CS16_BE, &[0x40, 0, 0xe0, 0, 0x80, 0, 0, 0]),
+        (SampleFormat::CF32_LE, &[0, 0, 0, 0x3f, 0, 0, 0x80, 0xbe, 0, 0, 0x80, 0xbf, 0, 0, 0, 0]),
+        (SampleFormat::CF64_LE, &[0, 0, 0, 0, 0, 0, 0xe0, 0x3f, 0, 0, 0, 0, 0, 0, 0xd0, 0xbf,
+                                  0, 0, 0, 0, 0, 0, 0xf0, 0xbf, 0, 0, 0, 0, 0, 0, 0, 0]),
+    ];
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("samples");
+    for (format, bytes) in files {
+        assert_eq!(read_samples(bytes, format).collect::<Vec<_>>(), expected, "{}", format);
+        file_write_samples(&path, format, expected.iter().copied()).unwrap();
+        assert_eq!(std::fs::read(&path).unwrap(), bytes, "{}", format);
+        assert_eq!(file_read_samples(&path, format).unwrap().collect::<Vec<_>>(), expected);
+        assert_eq!(format.to_string().parse::<SampleFormat>().unwrap(), format);
+    }
+    assert_eq!("cs8".parse::<SampleFormat>().unwrap(), SampleFormat::CS8);
+    assert_eq!("ci16_be".parse::<SampleFormat>().unwrap(), SampleFormat::CS16_BE);
+
+    // integers are clipped, and real formats can't hold complex samples
+    file_write_samples(&path, SampleFormat::CS8, [Complex::new(2.0, -2.0)].into_iter()).unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), vec![127, 0x80]);
+    let real = SampleFormat { complex: false, scalar: Scalar::I16, big_endian: false };
+    assert!(matches!(file_write_samples(&path, real, expected.into_iter()), Err(Error::InvalidParameter(_))));
+
+    // a failed read ends the stream, and is kept for `check`, but a partial sample isn't an error
+    let mut samples = read_samples(std::io::Read::chain(&[192u8, 96][..], Broken), SampleFormat::CU8);
+    assert_eq!(samples.by_ref().collect::<Vec<_>>(), vec![Complex::new(0.5, -0.25)]);
+    assert!(matches!(samples.check(), Err(Error::Io(_))));
+    assert!(samples.check().is_ok());
+    let mut samples = read_samples(&[192u8, 96, 0][..], SampleFormat::CU8);
+    assert_eq!(samples.by_ref().count(), 1);
+    assert!(samples.check().is_ok());
+
+    // a flowgraph that converts an rtl_sdr dump to a cfile
+    std::fs::write(&path, [192, 96, 0, 128]).unwrap();
+    let output = dir.path().join("out.cfile");
+    let graph = format!(r#"{{
+        "blocks": [
+            {{"name": "source", "type": "file_source", "path": "{}", "format": "cu8"}},
+            {{"name": "sink", "type": "file_sink", "path": "{}", "format": "cf32_le"}}
+        ],
+        "connections": [["source", "sink"]]
+    }}"#, path.display(), output.display());
+    FlowgraphSpec::from_json(&graph).unwrap().check(&Registry::new()).unwrap().run().unwrap();
+    let converted: Vec<Complex<f32>> = file_read_stream(&output).unwrap().collect();
+    assert_eq!(converted, vec![Complex::new(0.5, -0.25), Complex::new(-1.0, 0.0)]);
+
+    // a source that can't be read fails the run, rather than looking like an empty file
+    for source in [r#""format": "cu8""#, r#""item": "complex""#] {
+        let graph = format!(r#"{{
+            "blocks": [
+                {{"name": "source", "type": "file_source", "path": "{}", {}}},
+                {{"name": "sink", "type": "file_sink", "path": "{}"}}
+            ],
+            "connections": [["source", "sink"]]
+        }}"#, dir.path().display(), source, output.display());
+        let result = FlowgraphSpec::from_json(&graph).unwrap().check(&Registry::new()).unwrap().run();
+        assert!(matches!(result, Err(Error::Io(_))), "{:?}", result);
+    }
+}
+
+#[test]
+fn sigmf_recordings() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("capture");
+    let start = UNIX_EPOCH + Duration::from_secs(1709296200) + Duration::from_micros(250000);
+    let burst = Annotation { sample_count: Some(3), label: Some("burst".into()), ..Annotation::default() };
+    // the tags a tagged RTLSDR stream carries
+    let tags = vec![(0, Tag::SampleRate(2.4e6)), (0, Tag::CenterFrequency(100e6)), (0, Tag::Timestamp(start)),
+                    (4, Tag::Annotation(burst.clone())), (6, Tag::CenterFrequency(101e6))];
+    let samples: Vec<Complex<f32>> = (0..10).map(|x| Complex::new(x as f32 / 10.0, -0.5)).collect();
+    let datatypes = ["cf32_le", "ci16_be", "cu8"];
+    for datatype in datatypes {
+        let global = Global { datatype: datatype.parse().unwrap(), hw: Some("rtl-sdr".into()), ..Global::default() };
+        let input = insert_tags(samples.iter().copied(), tags.clone());
+        let meta = file_write_sigmf(&path, global, input).unwrap();
+        assert_eq!(std::fs::metadata(dir.path().join("capture.sigmf-data")).unwrap().len(),
+                   (samples.len() * meta.global.datatype.bytes()) as u64);
+
+        let recording = file_read_sigmf(&dir.path().join("capture.sigmf-meta")).unwrap();
+        assert_eq!(recording.meta(), &meta);
+        let global = &recording.meta().global;
+        assert_eq!(global.datatype.to_string(), datatype);
+        assert_eq!(global.sample_rate, Some(2.4e6));
+        assert_eq!(global.hw.as_deref(), Some("rtl-sdr"));
+        assert_eq!(recording.meta().captures.len(), 2);
+        assert_eq!(recording.meta().captures[1].sample_start, 6);
+        assert_eq!(recording.meta().captures[1].datetime, None);
+
+        let read: Vec<Tagged<Complex<f32>>> = recording.collect();
+        assert_eq!(read.len(), samples.len());
+        for (a, b) in read.iter().zip(&samples) {
+            assert!((a.sample - b).norm() < 1e-2, "{}: {} != {}", datatype, a.sample, b);
+        }
+        assert_eq!(read[0].tags, vec![Tag::SampleRate(2.4e6), Tag::CenterFrequency(100e6), Tag::Timestamp(start)]);
+        assert_eq!(read[4].tags, vec![Tag::Annotation(burst.clone())]);
+        assert_eq!(read[6].tags, vec![Tag::CenterFrequency(101e6)]);
+    }
+
+    // a recording made by another tool, with fields this crate doesn't know about
+    let meta = Meta::from_json(r#"{
+        "global": {"core:datatype": "ri8", "core:version": "1.0.0", "core:sample_rate": 1000,
+                   "core:recorder": "hackrf_transfer", "example:antenna": "dipole"},
+        "captures": [{"core:sample_start": 0, "core:datetime": "2024-03-01T13:30:00.5+01:00"}],
+        "annotations": [{"core:sample_start": 1, "core:freq_lower_edge": -100, "core:freq_upper_edge": 100,
+                         "core:comment": "noise"}]
+    }"#).unwrap();
+    assert_eq!(meta.global.datatype, SampleFormat { complex: false, scalar: Scalar::I8, big_endian: false });
+    assert_eq!(meta.global.recorder.as_deref(), Some("hackrf_transfer"));
+    assert_eq!(meta.global.extra["example:antenna"], "dipole");
+    assert_eq!(meta.captures[0].datetime, Some(UNIX_EPOCH + Duration::from_secs(1709296200) + Duration::from_millis(500)));
+    let read: Vec<Tagged<Complex<f32>>> = read_sigmf(meta, &[64u8, 0x80][..]).unwrap().collect();
+    assert_eq!(read.iter().map(|t| t.sample).collect::<Vec<_>>(), vec![Complex::new(0.5, 0.0), Complex::new(-1.0, 0.0)]);
+    let noise = Annotation {
+        freq_lower_edge: Some(-100.0), freq_upper_edge: Some(100.0), comment: Some("noise".into()), ..Annotation::default()
+    };
+    assert_eq!(read[1].tags, vec![Tag::Annotation(noise)]);
+
+    assert!(matches!("cf16_le".parse::<SampleFormat>(), Err(Error::Io(_))));
+    assert!(matches!("ci16".parse::<SampleFormat>(), Err(Error::Io(_))));
+    let global = Global { datatype: "rf32_le".parse().unwrap(), ..Global::default() };
+    let input = insert_tags(samples.iter().copied(), Vec::new());
+    assert!(matches!(file_write_sigmf(&path, global, input), Err(Error::InvalidParameter(_))));
+
+    // a recording has one sample rate, so a change partway through fails the write
+    let global = Global { datatype: "cf32_le".parse().unwrap(), ..Global::default() };
+    let input = insert_tags(samples.iter().copied(), vec![(0, Tag::SampleRate(2.4e6)), (5, Tag::SampleRate(1e6))]);
+    assert!(matches!(file_write_sigmf(&path, global, input), Err(Error::InvalidParameter(_))));
+    let global = Global { datatype: "cf32_le".parse().unwrap(), sample_rate: Some(2.4e6), ..Global::default() };
+    let input = insert_tags(samples.iter().copied(), vec![(5, Tag::SampleRate(2.4e6))]);
+    assert_eq!(file_write_sigmf(&path, global, input).unwrap().global.sample_rate, Some(2.4e6));
 }
 
 #[test]
 // Tests a couple of known rational resampler outputs
 fn test_resampler() {
 
-    let source = iter::count(0i, 1).take(5);
-    let taps = vec![1i, -1, 1];
-    let b_resampler = RationalResampler{ up: 2, down: 1, taps: taps.as_slice() };
-    connect!(resampled <- b_resampler (source));
-    let resampled: Vec<int> = resampled.collect();
-    assert_eq!(resampled, vec![0i, 0, 1, -1, 3, -2, 5, -3, 7, -4]);
-
-    let source = iter::count(0i, 1).take(5);
-    let taps = vec![1i];
-    let b_resampler = RationalResampler{ up: 2, down: 1, taps: taps.as_slice() };
-    connect!(resampled <- b_resampler (source));
-    let resampled: Vec<int> = resampled.collect();
-    assert_eq!(resampled, vec![0i, 0, 1, 0, 2, 0, 3, 0, 4, 0]);
-
-    let source = iter::count(0i, 1).take(10);
-    let taps = vec![1i];
-    let b_resampler = RationalResampler{ up: 1, down: 2, taps: taps.as_slice() };
-    connect!(resampled <- b_resampler (source));
-    let resampled: Vec<int> = resampled.collect();
-    assert_eq!(resampled, vec![0i, 2, 4, 6, 8]);
-
-    let source = iter::count(0i, 1).take(5);
-    let taps = vec![1i, -1];
-    let b_resampler = RationalResampler{ up: 2, down: 1, taps: taps.as_slice() };
-    connect!(resampled <- b_resampler (source));
-    let resampled: Vec<int> = resampled.collect();
+    let source = (0i64..).take(5);
+    let taps = vec![1i64, -1, 1];
+    let b_resampler = RationalResampler{ up: 2, down: 1, taps: &taps };
+    let resampled = connect(&b_resampler, source);
+    let resampled: Vec<i64> = resampled.collect();
+    assert_eq!(resampled, vec![0i64, 0, 1, -1, 3, -2, 5, -3, 7, -4]);
+
+    let source = (0i64..).take(5);
+    let taps = vec![1i64];
+    let b_resampler = RationalResampler{ up: 2, down: 1, taps: &taps };
+    let resampled = connect(&b_resampler, source);
+    let resampled: Vec<i64> = resampled.collect();
+    assert_eq!(resampled, vec![0i64, 0, 1, 0, 2, 0, 3, 0, 4, 0]);
+
+    let source = (0i64..).take(10);
+    let taps = vec![1i64];
+    let b_resampler = RationalResampler{ up: 1, down: 2, taps: &taps };
+    let resampled = connect(&b_resampler, source);
+    let resampled: Vec<i64> = resampled.collect();
+    assert_eq!(resampled, vec![0i64, 2, 4, 6, 8]);
+
+    let source = (0i64..).take(5);
+    let taps = vec![1i64, -1];
+    let b_resampler = RationalResampler{ up: 2, down: 1, taps: &taps };
+    let resampled = connect(&b_resampler, source);
+    let resampled: Vec<i64> = resampled.collect();
     assert_eq!(resampled, vec![0, 0, 1, -1, 2, -2, 3, -3, 4, -4]);
 
-    let source = iter::count(0i, 1).take(5);
-    let taps = vec![1i, -1, 1];
-    let b_resampler = RationalResampler{ up: 3, down: 5, taps: taps.as_slice() };
-    connect!(resampled <- b_resampler (source));
-    let resampled: Vec<int> = resampled.collect();
+    let source = (0i64..).take(5);
+    let taps = vec![1i64, -1, 1];
+    let b_resampler = RationalResampler{ up: 3, down: 5, taps: &taps };
+    let resampled = connect(&b_resampler, source);
+    let resampled: Vec<i64> = resampled.collect();
     assert_eq!(resampled, vec![0, 1, -3]);
 
 }